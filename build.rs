@@ -0,0 +1,44 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::env;
+use std::path::PathBuf;
+
+// embedded unless EVALFILE names another net at build time
+const DEFAULT_NET: &str = "src/nnue/net004.nnue";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=EVALFILE");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    // relative paths are from the repository root, absolute ones replace it
+    let path = match env::var("EVALFILE") {
+        Ok(path) if !path.is_empty() => manifest_dir.join(path),
+        _ => manifest_dir.join(DEFAULT_NET),
+    };
+
+    assert!(
+        path.is_file(),
+        "EVALFILE {} does not exist or is not a file",
+        path.display()
+    );
+
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rustc-env=SPHX_EMBEDDED_NET={}", path.display());
+}
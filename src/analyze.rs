@@ -0,0 +1,206 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::core::{Score, MAX_DEPTH};
+use crate::error::Result;
+use crate::limit::SearchLimiter;
+use crate::position::Position;
+use crate::search::{format_score, RootLine, SearchContext, Searcher};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub const DEFAULT_ANALYSIS_DEPTH: i32 = 8;
+
+const TT_SIZE: usize = 16;
+const MAX_PV_LENGTH: usize = 16;
+
+// the solution has to win clearly, while the next best move doesn't
+const PUZZLE_MIN_SCORE: Score = 400;
+const PUZZLE_MAX_SECOND_SCORE: Score = 50;
+
+pub struct AnalysisLimits {
+    pub depth: i32,
+    pub nodes: Option<usize>,
+    pub threads: usize,
+}
+
+impl Default for AnalysisLimits {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_ANALYSIS_DEPTH,
+            nodes: None,
+            threads: 1,
+        }
+    }
+}
+
+// lines may carry datagen annotations after the fen, which are ignored
+fn search_line(
+    searcher: &mut Searcher,
+    line: &str,
+    limits: &AnalysisLimits,
+) -> Result<(String, Vec<RootLine>, Vec<AtaxxMove>)> {
+    let fen = line.split('|').next().unwrap_or_default().trim();
+
+    let mut pos = Position::from_fen(fen)?;
+
+    searcher.new_game();
+
+    let limiter = limits
+        .nodes
+        .map_or_else(SearchLimiter::infinite, SearchLimiter::fixed_nodes);
+
+    let mut ctx = SearchContext::new(&mut pos);
+    ctx.nnue_state.reset(ctx.pos);
+
+    let _ = searcher.run_datagen_search(&mut ctx, limiter, limits.depth.clamp(1, MAX_DEPTH));
+
+    let root_lines = std::mem::take(&mut ctx.root_lines);
+    let pv = root_lines.first().map_or_else(Vec::new, |line| {
+        searcher.tt_pv(ctx.pos, line.mv, MAX_PV_LENGTH)
+    });
+
+    Ok((fen.to_string(), root_lines, pv))
+}
+
+#[must_use]
+fn format_pv(pv: &[AtaxxMove]) -> String {
+    pv.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn analyze_line(searcher: &mut Searcher, line: &str, limits: &AnalysisLimits) -> Result<String> {
+    let (fen, root_lines, pv) = search_line(searcher, line, limits)?;
+
+    let Some(best) = root_lines.first() else {
+        return Ok(format!("{} | none | none |", fen));
+    };
+
+    Ok(format!(
+        "{} | {} | {} | {}",
+        fen,
+        best.mv,
+        format_score(best.score),
+        format_pv(&pv)
+    ))
+}
+
+// one json object per line
+fn find_puzzle(
+    searcher: &mut Searcher,
+    line: &str,
+    limits: &AnalysisLimits,
+) -> Result<Option<String>> {
+    let (fen, root_lines, pv) = search_line(searcher, line, limits)?;
+
+    let [best, second, ..] = root_lines.as_slice() else {
+        return Ok(None);
+    };
+
+    if best.score < PUZZLE_MIN_SCORE || second.score > PUZZLE_MAX_SECOND_SCORE {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "{{\"fen\": \"{}\", \"solution\": \"{}\", \"pv\": \"{}\", \"score\": \"{}\", \"second_best\": \"{}\", \"second_best_score\": \"{}\"}}",
+        fen,
+        best.mv,
+        format_pv(&pv),
+        format_score(best.score),
+        second.mv,
+        format_score(second.score)
+    )))
+}
+
+// each thread takes the next unprocessed line, and results are written in input order
+fn process_lines<F>(
+    input: &str,
+    output: &str,
+    threads: usize,
+    multi_pv: usize,
+    process: F,
+) -> Result<()>
+where
+    F: Fn(&mut Searcher, &str) -> Result<Option<String>> + Sync,
+{
+    let lines = BufReader::new(File::open(input)?)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; lines.len()]);
+
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                let mut searcher = Searcher::new();
+                searcher.resize_tt(TT_SIZE);
+                searcher.set_multi_pv(multi_pv);
+
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(line) = lines.get(idx) else {
+                        break;
+                    };
+
+                    match process(&mut searcher, line) {
+                        Ok(result) => results.lock().unwrap()[idx] = result,
+                        Err(err) => eprintln!("Skipping line {}: {}", idx + 1, err),
+                    }
+                }
+            });
+        }
+    });
+
+    let mut out = BufWriter::new(File::create(output)?);
+
+    let mut written = 0usize;
+
+    for result in results.into_inner().unwrap().into_iter().flatten() {
+        writeln!(out, "{}", result)?;
+        written += 1;
+    }
+
+    out.flush()?;
+
+    println!("wrote {} lines from {} positions", written, lines.len());
+
+    Ok(())
+}
+
+// scores are from the side to move's point of view
+pub fn run_analyze_fens(input: &str, output: &str, limits: &AnalysisLimits) -> Result<()> {
+    process_lines(input, output, limits.threads, 1, |searcher, line| {
+        analyze_line(searcher, line, limits).map(Some)
+    })
+}
+
+// a puzzle has exactly one move that wins, with every alternative at best roughly equal
+pub fn run_puzzles(input: &str, output: &str, limits: &AnalysisLimits) -> Result<()> {
+    process_lines(input, output, limits.threads, 2, |searcher, line| {
+        find_puzzle(searcher, line, limits)
+    })
+}
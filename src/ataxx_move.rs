@@ -35,6 +35,7 @@ impl AtaxxMove {
     }
 }
 
+#[derive(Debug)]
 pub enum MoveStrError {
     InvalidFrom,
     InvalidTo,
@@ -73,6 +74,16 @@ impl FromStr for AtaxxMove {
     }
 }
 
+impl Display for MoveStrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveStrError::InvalidFrom => write!(f, "invalid from-square"),
+            MoveStrError::InvalidTo => write!(f, "invalid to-square"),
+            MoveStrError::WrongSize => write!(f, "wrong size"),
+        }
+    }
+}
+
 impl Display for AtaxxMove {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -94,7 +105,7 @@ impl PackedMove {
     pub const NULL: Self = Self::from_raw(1 << 12);
 
     #[must_use]
-    const fn from_raw(value: u16) -> Self {
+    pub const fn from_raw(value: u16) -> Self {
         Self { value }
     }
 
@@ -111,8 +122,7 @@ impl PackedMove {
     }
 
     #[must_use]
-    #[allow(unused)]
-    fn raw(self) -> u16 {
+    pub fn raw(self) -> u16 {
         self.value
     }
 
@@ -16,13 +16,19 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::error::Result;
 use crate::position::Position;
 use crate::search::Searcher;
+use crate::ttable::TTable;
+use crate::util::simd;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
 
 pub const DEFAULT_BENCH_DEPTH: i32 = 6;
 pub const BENCH_TT_SIZE: usize = 16;
 
-const BENCH_FENS: &[&str] = &[
+pub const BENCH_FENS: &[&str] = &[
     "x-1-1-o/-1-1-1-/1-1-1-1/-1-1-1-/1-1-1-1/-1-1-1-/o-1-1-x x 0 1",
     "x-1-1-o/1-1-1-1/1-1-1-1/1-1-1-1/1-1-1-1/1-1-1-1/o-1-1-x x 0 1",
     "x1-1-1o/2-1-2/-------/2-1-2/-------/2-1-2/o1-1-1x x 0 1",
@@ -75,20 +81,33 @@ const BENCH_FENS: &[&str] = &[
     "x6/7/4x2/3x3/7/7/o5x o 2 2",
 ];
 
-pub fn run_bench(searcher: &mut Searcher, depth: i32) {
-    searcher.resize_tt(BENCH_TT_SIZE);
-    println!("set TT size to {} MB", BENCH_TT_SIZE);
+pub const DEFAULT_SMP_BENCH_MAX_THREADS: usize = 8;
+
+pub const DEFAULT_AUTOCONFIG_PATH: &str = "sanctaphraxx.cfg";
+
+const AUTOCONFIG_DEPTH: i32 = 5;
+const AUTOCONFIG_MAX_HASH_MB: usize = 4096;
+
+// extra threads are recommended while each still adds this share of a full thread's nps
+const AUTOCONFIG_MIN_THREAD_EFFICIENCY: f64 = 0.8;
+// and bigger hash sizes while they stay this close to the fastest one, as
+// fewer overwrites make up for some lost speed in longer searches than bench's
+const AUTOCONFIG_MIN_HASH_SPEED: f64 = 0.8;
 
+fn run_suite(
+    searcher: &mut Searcher,
+    depth: i32,
+    depth_nodes: &mut Vec<usize>,
+) -> Result<(usize, f64)> {
     let mut total_nodes = 0usize;
     let mut total_time = 0f64;
 
+    depth_nodes.clear();
+
     let mut pos = Position::empty();
 
     for fen in BENCH_FENS {
-        if let Err(err) = pos.reset_from_fen(fen) {
-            eprintln!("Invalid bench fen {}", fen);
-            eprintln!("{}", err);
-        }
+        pos.reset_from_fen(fen)?;
 
         searcher.new_game();
 
@@ -96,10 +115,182 @@ pub fn run_bench(searcher: &mut Searcher, depth: i32) {
 
         total_nodes += nodes;
         total_time += time;
+
+        for (idx, &nodes) in searcher.depth_nodes().iter().enumerate() {
+            if idx >= depth_nodes.len() {
+                depth_nodes.push(0);
+            }
+            depth_nodes[idx] += nodes;
+        }
     }
 
+    Ok((total_nodes, total_time))
+}
+
+fn print_simd_info() {
+    println!(
+        "simd backend {} ({} x i16 per register)",
+        simd::BACKEND,
+        simd::CHUNK_SIZE_I16
+    );
+
+    let best = simd::best_supported_backend();
+    if best != simd::BACKEND {
+        println!(
+            "warning: this cpu supports {}, but this binary was built for {}",
+            best,
+            simd::BACKEND
+        );
+    }
+}
+
+pub fn run_bench(searcher: &mut Searcher, depth: i32) -> Result<()> {
+    searcher.resize_tt(BENCH_TT_SIZE);
+    println!("set TT size to {} MB", BENCH_TT_SIZE);
+
+    print_simd_info();
+
+    let mut depth_nodes = Vec::new();
+    let (total_nodes, total_time) = run_suite(searcher, depth, &mut depth_nodes)?;
+
     let nps = (total_nodes as f64 / total_time) as usize;
 
+    println!("depth        nodes   share");
+    for (idx, &nodes) in depth_nodes.iter().enumerate() {
+        println!(
+            "{:>5} {:>12} {:>6.2}%",
+            idx + 1,
+            nodes,
+            nodes as f64 / total_nodes as f64 * 100.0
+        );
+    }
+
     println!("{:.2} seconds", total_time);
     println!("{} nodes {} nps", total_nodes, nps);
+
+    Ok(())
+}
+
+// runs the suite at 1, 2, 4, ... threads. nodes are not deterministic
+// with more than one thread, so speedup is measured in nps and time
+pub fn run_bench_smp(searcher: &mut Searcher, depth: i32, max_threads: usize) -> Result<()> {
+    let prev_threads = searcher.threads();
+
+    searcher.resize_tt(BENCH_TT_SIZE);
+    println!("set TT size to {} MB", BENCH_TT_SIZE);
+
+    print_simd_info();
+
+    let mut baseline: Option<(f64, f64)> = None;
+    let mut threads = 1usize;
+
+    while threads <= max_threads.clamp(1, Searcher::MAX_THREADS) {
+        searcher.set_threads(threads);
+
+        let (nodes, time) = run_suite(searcher, depth, &mut Vec::new())?;
+        let nps = nodes as f64 / time;
+
+        let (base_nps, base_time) = *baseline.get_or_insert((nps, time));
+
+        println!(
+            "threads {:>3} time {:>8.2} nodes {:>12} nps {:>10} nps speedup {:>5.2}x time speedup {:>5.2}x",
+            threads,
+            time,
+            nodes,
+            nps as usize,
+            nps / base_nps,
+            base_time / time
+        );
+
+        threads *= 2;
+    }
+
+    searcher.set_threads(prev_threads);
+
+    Ok(())
+}
+
+// measures nps at 1, 2, 4, ... threads up to the core count, then at growing hash sizes,
+// and writes the best settings out as setoption commands. with a memory budget,
+// the hash sizes tried stop where it runs out
+pub fn run_autoconfig(path: &str, memory_budget_mb: Option<usize>) -> Result<()> {
+    let mut searcher = Searcher::new();
+
+    let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+    let mut threads = 1usize;
+    let mut best_threads = 1usize;
+    let mut base_nps = None;
+
+    searcher.resize_tt(BENCH_TT_SIZE);
+
+    while threads <= cores.min(Searcher::MAX_THREADS) {
+        searcher.set_threads(threads);
+
+        let (nodes, time) = run_suite(&mut searcher, AUTOCONFIG_DEPTH, &mut Vec::new())?;
+        let nps = nodes as f64 / time;
+
+        let base = *base_nps.get_or_insert(nps);
+        let efficiency = nps / (base * threads as f64);
+
+        println!(
+            "threads {:>3} nps {:>10} efficiency {:>5.1}%",
+            threads,
+            nps as usize,
+            efficiency * 100.0
+        );
+
+        if efficiency >= AUTOCONFIG_MIN_THREAD_EFFICIENCY {
+            best_threads = threads;
+        }
+
+        threads *= 2;
+    }
+
+    searcher.set_threads(best_threads);
+
+    let mut max_hash = TTable::max_affordable_size_mb().min(AUTOCONFIG_MAX_HASH_MB);
+
+    if let Some(budget) = memory_budget_mb {
+        max_hash = max_hash.min(budget.saturating_sub(searcher.fixed_memory_mb()));
+    }
+
+    let mut hash_nps = Vec::new();
+    let mut hash_mb = BENCH_TT_SIZE;
+
+    while hash_mb <= max_hash {
+        let applied = searcher.resize_tt(hash_mb);
+        if applied < hash_mb {
+            break;
+        }
+
+        let (nodes, time) = run_suite(&mut searcher, AUTOCONFIG_DEPTH, &mut Vec::new())?;
+        let nps = nodes as f64 / time;
+
+        println!("hash {:>6} MB nps {:>10}", hash_mb, nps as usize);
+
+        hash_nps.push((hash_mb, nps));
+        hash_mb *= 4;
+    }
+
+    let fastest = hash_nps.iter().map(|&(_, nps)| nps).fold(0.0, f64::max);
+    let best_hash = hash_nps
+        .iter()
+        .rev()
+        .find(|&&(_, nps)| nps >= fastest * AUTOCONFIG_MIN_HASH_SPEED)
+        .map_or(BENCH_TT_SIZE, |&(mb, _)| mb);
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "setoption name Threads value {}", best_threads)?;
+    writeln!(out, "setoption name Hash value {}", best_hash)?;
+
+    out.flush()?;
+
+    println!(
+        "recommended Threads {} and Hash {} MB, written to {}",
+        best_threads, best_hash, path
+    );
+
+    Ok(())
 }
@@ -218,6 +218,103 @@ impl Bitboard {
             .and(Bitboard::ALL)
     }
 
+    #[must_use]
+    pub const fn mirror_horizontal(self) -> Self {
+        Self {
+            value: self.value.reverse_bits().swap_bytes() >> 1,
+        }
+    }
+
+    #[must_use]
+    pub const fn mirror_vertical(self) -> Self {
+        Self {
+            value: self.value.swap_bytes() >> 8,
+        }
+    }
+
+    // across the a1-g7 diagonal. the unused eighth rank and file swap
+    // into each other, so they stay empty
+    #[must_use]
+    pub const fn flip_diagonal(self) -> Self {
+        const K1: u64 = 0x5500_5500_5500_5500;
+        const K2: u64 = 0x3333_0000_3333_0000;
+        const K4: u64 = 0x0f0f_0f0f_0000_0000;
+
+        let mut value = self.value;
+
+        let t = K4 & (value ^ (value << 28));
+        value ^= t ^ (t >> 28);
+        let t = K2 & (value ^ (value << 14));
+        value ^= t ^ (t >> 14);
+        let t = K1 & (value ^ (value << 7));
+        value ^= t ^ (t >> 7);
+
+        Self { value }
+    }
+
+    // all squares in `within` that are connected
+    // to this board through king steps inside `within`
+    #[must_use]
+    pub const fn flood_fill(self, within: Bitboard) -> Self {
+        let mut filled = self.and(within);
+
+        loop {
+            let next = filled.or(filled.expand().and(within));
+            if next.value == filled.value {
+                return filled;
+            }
+            filled = next;
+        }
+    }
+
+    // like flood_fill, but also connected through
+    // jumps of two squares, i.e. anything a piece could reach
+    #[must_use]
+    pub const fn flood_fill_with_jumps(self, within: Bitboard) -> Self {
+        let mut filled = self.and(within);
+
+        loop {
+            let next = filled.or(filled.expand().expand().and(within));
+            if next.value == filled.value {
+                return filled;
+            }
+            filled = next;
+        }
+    }
+
+    // number of king steps from this board to every square in `within`,
+    // indexed by bit index, or u8::MAX if a square cannot be reached
+    #[must_use]
+    pub const fn distances(self, within: Bitboard) -> [u8; 64] {
+        let mut result = [u8::MAX; 64];
+
+        let mut reached = self.and(within);
+        let mut frontier = reached;
+        let mut distance = 0u8;
+
+        while !frontier.is_empty() {
+            let mut remaining = frontier.value;
+            while remaining != 0 {
+                result[remaining.trailing_zeros() as usize] = distance;
+                remaining &= remaining - 1;
+            }
+
+            frontier = reached.expand().and(within).and(reached.inverse());
+            reached = reached.or(frontier);
+            distance += 1;
+        }
+
+        result
+    }
+
+    #[must_use]
+    pub fn subsets(self) -> Subsets {
+        Subsets {
+            set: self,
+            next: Some(Bitboard::EMPTY),
+        }
+    }
+
     #[must_use]
     pub const fn lowest_square(self) -> Square {
         Square::from_raw(self.value.trailing_zeros() as u8)
@@ -234,7 +331,6 @@ impl IntoIterator for Bitboard {
     type Item = Square;
     type IntoIter = Biterator;
 
-    #[must_use]
     fn into_iter(self) -> Self::IntoIter {
         Biterator { board: self }
     }
@@ -243,7 +339,6 @@ impl IntoIterator for Bitboard {
 impl BitAnd for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn bitand(self, rhs: Self) -> Self::Output {
         self.and(rhs)
     }
@@ -258,7 +353,6 @@ impl BitAndAssign for Bitboard {
 impl BitOr for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn bitor(self, rhs: Self) -> Self::Output {
         self.or(rhs)
     }
@@ -273,7 +367,6 @@ impl BitOrAssign for Bitboard {
 impl BitXor for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn bitxor(self, rhs: Self) -> Self::Output {
         self.xor(rhs)
     }
@@ -288,7 +381,6 @@ impl BitXorAssign for Bitboard {
 impl Not for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn not(self) -> Self::Output {
         self.inverse()
     }
@@ -297,7 +389,6 @@ impl Not for Bitboard {
 impl Shr<u32> for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn shr(self, rhs: u32) -> Self::Output {
         self.bitshift_right(rhs)
     }
@@ -312,7 +403,6 @@ impl ShrAssign<u32> for Bitboard {
 impl Shl<u32> for Bitboard {
     type Output = Self;
 
-    #[must_use]
     fn shl(self, rhs: u32) -> Self::Output {
         self.bitshift_left(rhs)
     }
@@ -359,7 +449,6 @@ pub struct Biterator {
 impl Iterator for Biterator {
     type Item = Square;
 
-    #[must_use]
     fn next(&mut self) -> Option<Self::Item> {
         if self.board.is_empty() {
             None
@@ -368,3 +457,100 @@ impl Iterator for Biterator {
         }
     }
 }
+
+// carry-rippler enumeration of every subset of a board, including the empty one
+pub struct Subsets {
+    set: Bitboard,
+    next: Option<Bitboard>,
+}
+
+impl Iterator for Subsets {
+    type Item = Bitboard;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let subset = self.next?;
+
+        let next = subset.value.wrapping_sub(self.set.value) & self.set.value;
+        self.next = if next == 0 {
+            None
+        } else {
+            Some(Bitboard::from_raw(next))
+        };
+
+        Some(subset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitboard::Bitboard;
+    use crate::core::Square;
+
+    #[test]
+    fn mirrors() {
+        assert_eq!(Square::A1.bit().mirror_horizontal(), Square::G1.bit());
+        assert_eq!(Square::C5.bit().mirror_horizontal(), Square::E5.bit());
+        assert_eq!(Square::A1.bit().mirror_vertical(), Square::A7.bit());
+        assert_eq!(Square::C5.bit().mirror_vertical(), Square::C3.bit());
+
+        assert_eq!(Bitboard::ALL.mirror_horizontal(), Bitboard::ALL);
+        assert_eq!(Bitboard::ALL.mirror_vertical(), Bitboard::ALL);
+        assert_eq!(Bitboard::FILE_B.mirror_horizontal(), Bitboard::FILE_F);
+        assert_eq!(Bitboard::RANK_2.mirror_vertical(), Bitboard::RANK_6);
+
+        assert_eq!(Square::A1.bit().flip_diagonal(), Square::A1.bit());
+        assert_eq!(Square::C5.bit().flip_diagonal(), Square::E3.bit());
+        assert_eq!(Bitboard::ALL.flip_diagonal(), Bitboard::ALL);
+        assert_eq!(Bitboard::FILE_B.flip_diagonal(), Bitboard::RANK_2);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_walls() {
+        let within = Bitboard::ALL & !Bitboard::FILE_D;
+
+        let left = Square::A1.bit().flood_fill(within);
+        assert_eq!(left, Bitboard::FILE_A | Bitboard::FILE_B | Bitboard::FILE_C);
+
+        let everything = (Square::A1.bit() | Square::G7.bit()).flood_fill(within);
+        assert_eq!(everything, within);
+
+        assert_eq!(Square::D4.bit().flood_fill(within), Bitboard::EMPTY);
+
+        // a single file wall can be jumped over, but not a double one
+        assert_eq!(Square::A1.bit().flood_fill_with_jumps(within), within);
+
+        let double_wall = within & !Bitboard::FILE_E;
+        assert_eq!(
+            Square::A1.bit().flood_fill_with_jumps(double_wall),
+            Bitboard::FILE_A | Bitboard::FILE_B | Bitboard::FILE_C
+        );
+    }
+
+    #[test]
+    fn distances_from_square() {
+        let distances = Square::A1.bit().distances(Bitboard::ALL);
+
+        assert_eq!(distances[Square::A1.bit_idx()], 0);
+        assert_eq!(distances[Square::B2.bit_idx()], 1);
+        assert_eq!(distances[Square::C1.bit_idx()], 2);
+        assert_eq!(distances[Square::G7.bit_idx()], 6);
+
+        let walled = Square::A1
+            .bit()
+            .distances(Bitboard::ALL & !Bitboard::FILE_B);
+        assert_eq!(walled[Square::C1.bit_idx()], u8::MAX);
+    }
+
+    #[test]
+    fn subsets_are_exhaustive() {
+        let set = Square::A1.bit() | Square::D4.bit() | Square::G7.bit();
+        let subsets: Vec<Bitboard> = set.subsets().collect();
+
+        assert_eq!(subsets.len(), 8);
+        assert!(subsets.contains(&Bitboard::EMPTY));
+        assert!(subsets.contains(&set));
+        assert!(subsets.iter().all(|&subset| (subset & !set).is_empty()));
+
+        assert_eq!(Bitboard::EMPTY.subsets().count(), 1);
+    }
+}
@@ -0,0 +1,33 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::error::Result;
+use crate::nnue::{add_header, NetworkError, NetworkLayout};
+
+// turns a dump of bare parameters, as written before nets had a header, into a
+// network file. the layout has to be given, as nothing in the parameters says it
+pub fn run_convertnet(input: &str, output: &str, layout: NetworkLayout) -> Result<()> {
+    let parameters = std::fs::read(input)?;
+    let bytes = add_header(&parameters, layout).map_err(NetworkError::Format)?;
+
+    std::fs::write(output, &bytes)?;
+
+    println!("wrote {} ({} bytes)", output, bytes.len());
+
+    Ok(())
+}
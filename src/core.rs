@@ -132,6 +132,11 @@ impl Square {
     pub const fn flip_vertical(self) -> Self {
         Self::from_coords(6 - self.rank(), self.file())
     }
+
+    #[must_use]
+    pub const fn flip_diagonal(self) -> Self {
+        Self::from_coords(self.file(), self.rank())
+    }
 }
 
 pub enum SquareStrError {
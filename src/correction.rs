@@ -0,0 +1,125 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::bitboard::Bitboard;
+use crate::core::{Color, Score, SCORE_WIN};
+use crate::position::Position;
+
+const ENTRY_BITS: u32 = 14;
+const ENTRIES: usize = 1 << ENTRY_BITS;
+
+// entries are kept in fractions of a centipawn, so small differences still accumulate
+const GRAIN: i32 = 256;
+const WEIGHT_SCALE: i32 = 256;
+const MAX_WEIGHT: i32 = 16;
+const MAX_CORRECTION: i32 = 64 * GRAIN;
+
+#[must_use]
+fn shape_index(stones: Bitboard) -> usize {
+    (stones.raw().wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - ENTRY_BITS)) as usize
+}
+
+// how far search scores have strayed from the static eval in positions where each
+// side's stones formed the same shapes, indexed by side to move. both shapes get a
+// say, so a correction learned for one blob carries over as the other one changes
+#[derive(Debug, Clone)]
+pub struct CorrectionHistory {
+    // too big for the stack, so both are flattened over the side to move
+    ours: Vec<i16>,
+    theirs: Vec<i16>,
+}
+
+impl CorrectionHistory {
+    pub fn clear(&mut self) {
+        self.ours.fill(0);
+        self.theirs.fill(0);
+    }
+
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        (self.ours.len() + self.theirs.len()) * std::mem::size_of::<i16>()
+    }
+
+    #[must_use]
+    fn indices(pos: &Position) -> (usize, usize) {
+        let stm = pos.side_to_move();
+        (
+            stm.idx() * ENTRIES + shape_index(pos.color_occupancy(stm)),
+            stm.idx() * ENTRIES + shape_index(pos.color_occupancy(stm.flip())),
+        )
+    }
+
+    #[must_use]
+    pub fn correct(&self, pos: &Position, eval: Score) -> Score {
+        let (ours, theirs) = Self::indices(pos);
+        let correction =
+            (i32::from(self.ours[ours]) + i32::from(self.theirs[theirs])) / (2 * GRAIN);
+
+        (eval + correction).clamp(-SCORE_WIN + 1, SCORE_WIN - 1)
+    }
+
+    // deeper searches are trusted more, moving entries further towards their result
+    pub fn update(&mut self, pos: &Position, depth: i32, eval: Score, score: Score) {
+        let (ours, theirs) = Self::indices(pos);
+
+        let target = (score - eval) * GRAIN;
+        let weight = (depth + 1).min(MAX_WEIGHT);
+
+        for entry in [&mut self.ours[ours], &mut self.theirs[theirs]] {
+            let value = i32::from(*entry);
+            let value = (value * (WEIGHT_SCALE - weight) + target * weight) / WEIGHT_SCALE;
+            *entry = value.clamp(-MAX_CORRECTION, MAX_CORRECTION) as i16;
+        }
+    }
+}
+
+impl Default for CorrectionHistory {
+    fn default() -> Self {
+        Self {
+            ours: vec![0; ENTRIES * Color::N_COLORS],
+            theirs: vec![0; ENTRIES * Color::N_COLORS],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CorrectionHistory, GRAIN, MAX_CORRECTION};
+    use crate::position::Position;
+
+    #[test]
+    fn correction_moves_towards_search_scores() {
+        let mut correction = CorrectionHistory::default();
+        let pos = Position::startpos();
+
+        assert_eq!(correction.correct(&pos, 50), 50);
+
+        for _ in 0..100 {
+            correction.update(&pos, 10, 50, 80);
+        }
+
+        let corrected = correction.correct(&pos, 50);
+        assert!(corrected > 70 && corrected <= 80);
+
+        for _ in 0..1000 {
+            correction.update(&pos, 20, 0, 10_000);
+        }
+
+        assert!(correction.correct(&pos, 0) <= MAX_CORRECTION / GRAIN);
+    }
+}
@@ -19,13 +19,15 @@
 use crate::ataxx_move::AtaxxMove;
 use crate::bitboard::Bitboard;
 use crate::core::{Color, Score, MAX_DEPTH, SCORE_WIN};
+use crate::error::{Error, Result};
 use crate::limit::SearchLimiter;
 use crate::movegen::{fill_move_list, MoveList};
 use crate::position::{GameResult, Position};
 use crate::search::{SearchContext, Searcher};
 use crate::util::rng::Jsf64Rng;
-use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
+use crate::util::squares::{pack_dense, unpack_dense};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -71,7 +73,11 @@ trait OutputFormat {
     const EXTENSION: &'static str;
 
     fn pack(pos: &Position, red_score: Score) -> Self::Elem;
-    fn write_all_with_outcome(out: &mut impl Write, values: &mut [Self::Elem], outcome: Outcome);
+    fn write_all_with_outcome(
+        out: &mut impl Write,
+        values: &mut [Self::Elem],
+        outcome: Outcome,
+    ) -> std::io::Result<()>;
 }
 
 struct Fen;
@@ -84,7 +90,11 @@ impl OutputFormat for Fen {
         format!("{} | {}", pos.to_fen(), red_score)
     }
 
-    fn write_all_with_outcome(out: &mut impl Write, values: &mut [Self::Elem], outcome: Outcome) {
+    fn write_all_with_outcome(
+        out: &mut impl Write,
+        values: &mut [Self::Elem],
+        outcome: Outcome,
+    ) -> std::io::Result<()> {
         for fen in values {
             writeln!(
                 out,
@@ -95,9 +105,10 @@ impl OutputFormat for Fen {
                     Outcome::Draw => "0.5",
                     Outcome::RedWin => "1.0",
                 }
-            )
-            .unwrap();
+            )?;
         }
+
+        Ok(())
     }
 }
 
@@ -119,35 +130,14 @@ impl OutputFormat for BulletFormat {
     const EXTENSION: &'static str = "bin";
 
     fn pack(pos: &Position, red_score: Score) -> Self {
-        #[allow(clippy::unreadable_literal)]
-        fn to_bullet_bb(board: Bitboard) -> u64 {
-            #[cfg(target_feature = "bmi2")]
-            {
-                use core::arch::x86_64::*;
-                unsafe { _pext_u64(board.raw(), Bitboard::ALL.raw()) }
-            }
-
-            #[cfg(not(target_feature = "bmi2"))]
-            {
-                let bb = board.raw();
-                bb & 0x7f
-                    | (bb & 0x7f00) >> 1
-                    | (bb & 0x7f0000) >> 2
-                    | (bb & 0x7f000000) >> 3
-                    | (bb & 0x7f00000000) >> 4
-                    | (bb & 0x7f0000000000) >> 5
-                    | (bb & 0x7f000000000000) >> 6
-            }
-        }
-
         let (stm_occ, nstm_occ, stm_score) = if pos.side_to_move() == Color::RED {
             (pos.red_occupancy(), pos.blue_occupancy(), red_score)
         } else {
             (pos.blue_occupancy(), pos.red_occupancy(), -red_score)
         };
 
-        let stm_occ = to_bullet_bb(stm_occ);
-        let nstm_occ = to_bullet_bb(nstm_occ);
+        let stm_occ = pack_dense(stm_occ);
+        let nstm_occ = pack_dense(nstm_occ);
 
         Self {
             bbs: [stm_occ, nstm_occ, pos.gaps().raw()],
@@ -160,7 +150,11 @@ impl OutputFormat for BulletFormat {
         }
     }
 
-    fn write_all_with_outcome(out: &mut impl Write, values: &mut [Self::Elem], outcome: Outcome) {
+    fn write_all_with_outcome(
+        out: &mut impl Write,
+        values: &mut [Self::Elem],
+        outcome: Outcome,
+    ) -> std::io::Result<()> {
         for board in values.iter_mut() {
             board.result = if board.stm {
                 // blue
@@ -170,159 +164,347 @@ impl OutputFormat for BulletFormat {
             };
         }
 
-        let written = out
-            .write(unsafe {
-                std::slice::from_raw_parts(
-                    values.as_ptr().cast::<u8>(),
-                    std::mem::size_of_val(values),
-                )
-            })
-            .unwrap();
-        assert_eq!(written, std::mem::size_of_val(values));
+        out.write_all(unsafe {
+            std::slice::from_raw_parts(values.as_ptr().cast::<u8>(), std::mem::size_of_val(values))
+        })
     }
 }
 
-static STOP: AtomicBool = AtomicBool::new(false);
+const _: () = assert!(std::mem::size_of::<BulletFormat>() == 32);
 
-fn run_thread<T: OutputFormat>(id: u32, games: u32, seed: u64, out_dir: &Path) {
-    let out_path = out_dir.join(format!("{}.{}", id, T::EXTENSION));
-    let Ok(out_file) = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(out_path.as_path())
-    else {
-        eprintln!("Failed to open output file {}", out_path.to_str().unwrap());
-        return;
-    };
+// a position read back from datagen output, with the score and result from red's point of view
+#[derive(Debug, Clone)]
+pub struct DataPoint {
+    pub pos: Position,
+    pub red_score: Score,
+    pub result: GameResult,
+}
 
-    let mut out = BufWriter::new(out_file);
+impl DataPoint {
+    // 1.0 for a red win, as in datagen's text output
+    #[must_use]
+    pub fn red_result(&self) -> f64 {
+        match self.result {
+            GameResult::Win(Color::RED) => 1.0,
+            GameResult::Win(_) => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+}
 
-    let mut rng = Jsf64Rng::new(seed);
+#[must_use]
+pub fn is_bulletformat_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext == BulletFormat::EXTENSION)
+}
 
-    let verif_limiter = SearchLimiter::infinite();
-    let limiter = SearchLimiter::fixed_nodes(NODE_LIMIT);
+// streams bulletformat records back into positions, the inverse of BulletFormat::pack
+pub struct BulletFormatReader<R: Read> {
+    reader: R,
+}
 
-    let mut searcher = Searcher::new();
-    searcher.resize_tt(TT_SIZE);
+impl BulletFormatReader<BufReader<File>> {
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
 
-    let mut pos = Position::empty();
-    let mut ctx = SearchContext::new(&mut pos);
+impl<R: Read> BulletFormatReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
 
-    let mut positions = Vec::<T::Elem>::new();
+    // records are written straight from memory, so they are read back in native byte order
+    fn parse(record: &[u8; std::mem::size_of::<BulletFormat>()]) -> Result<DataPoint> {
+        let u64_at = |idx: usize| u64::from_ne_bytes(record[idx..idx + 8].try_into().unwrap());
 
-    let start_time = Instant::now();
+        let stm_occ = unpack_dense(u64_at(0));
+        let nstm_occ = unpack_dense(u64_at(8));
+        let gaps = Bitboard::from_raw(u64_at(16));
 
-    let mut total_positions = 0usize;
+        let stm_score = Score::from(i16::from_ne_bytes([record[24], record[25]]));
 
-    let mut game = 0;
-    while game < games {
-        positions.clear();
-        searcher.new_game();
+        let stm_outcome = match record[26] {
+            0 => Outcome::RedLoss,
+            1 => Outcome::Draw,
+            2 => Outcome::RedWin,
+            other => return Err(Error::invalid("outcome", &other.to_string())),
+        };
 
-        ctx.pos.reset_to_startpos();
+        let fullmoves = u16::from_ne_bytes([record[28], record[29]]);
+        let halfmoves = record[30];
+
+        let (stm, red, blue, red_score, outcome) = match record[27] {
+            0 => (Color::RED, stm_occ, nstm_occ, stm_score, stm_outcome),
+            1 => (
+                Color::BLUE,
+                nstm_occ,
+                stm_occ,
+                -stm_score,
+                stm_outcome.flip(),
+            ),
+            other => return Err(Error::invalid("side to move", &other.to_string())),
+        };
 
-        let move_count = 8 + (rng.next_u32() >> 31);
-        let mut hit_game_over = false;
+        if !(red & blue).is_empty() || !((red | blue) & gaps).is_empty() {
+            return Err(Error::invalid("board", "overlapping bitboards"));
+        }
 
-        for _ in 0..move_count {
-            let mut moves = MoveList::new();
-            fill_move_list(&mut moves, ctx.pos);
+        Ok(DataPoint {
+            pos: Position::from_boards(
+                red,
+                blue,
+                gaps,
+                stm,
+                u16::from(halfmoves),
+                u32::from(fullmoves),
+            ),
+            red_score,
+            result: match outcome {
+                Outcome::RedLoss => GameResult::Win(Color::BLUE),
+                Outcome::Draw => GameResult::Draw,
+                Outcome::RedWin => GameResult::Win(Color::RED),
+            },
+        })
+    }
+}
 
-            if moves.is_empty() {
-                hit_game_over = true;
-                break;
-            }
+impl<R: Read> Iterator for BulletFormatReader<R> {
+    type Item = Result<DataPoint>;
 
-            let mv = moves[rng.next_u32_bounded(moves.len() as u32) as usize];
-            ctx.pos.apply_move::<false, true>(mv, None);
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = [0u8; std::mem::size_of::<BulletFormat>()];
 
-            if ctx.pos.game_over() {
-                hit_game_over = true;
-                break;
-            }
+        match self.reader.read_exact(&mut record) {
+            Ok(()) => Some(Self::parse(&record)),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => Some(Err(err.into())),
         }
+    }
+}
 
-        if hit_game_over {
-            continue;
+static STOP: AtomicBool = AtomicBool::new(false);
+
+// every game attempt gets its own rng stream, so that any single game can be regenerated
+// from the base seed and its stream id alone, whichever thread played it and whatever came before
+#[must_use]
+fn stream_id(thread: u32, attempt: u32) -> u64 {
+    u64::from(thread) << 32 | u64::from(attempt)
+}
+
+#[must_use]
+fn stream_rng(base_seed: u64, stream: u64) -> Jsf64Rng {
+    Jsf64Rng::new(mix(base_seed ^ mix(stream)))
+}
+
+// plays a game from a random opening, returning None if the opening was unusable.
+// the indices of the opening moves in the generated move lists go into `opening`
+fn play_game<T: OutputFormat>(
+    searcher: &mut Searcher,
+    ctx: &mut SearchContext,
+    rng: &mut Jsf64Rng,
+    second_best_percent: u32,
+    positions: &mut Vec<T::Elem>,
+    opening: &mut Vec<u32>,
+) -> Option<Outcome> {
+    positions.clear();
+    opening.clear();
+
+    searcher.new_game();
+    ctx.start_depth = 1;
+
+    ctx.pos.reset_to_startpos();
+
+    let move_count = 8 + (rng.next_u32() >> 31);
+
+    for _ in 0..move_count {
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, ctx.pos);
+
+        if moves.is_empty() {
+            return None;
         }
 
-        let first_score =
-            searcher.run_datagen_search(&mut ctx, verif_limiter.clone(), VERIFICATION_DEPTH);
-        if first_score.abs() > VERIFICATION_SCORE_LIMIT {
-            continue;
+        let idx = rng.next_u32_bounded(moves.len() as u32);
+        opening.push(idx);
+
+        ctx.pos.apply_move::<false, true>(moves[idx as usize], None);
+
+        if ctx.pos.game_over() {
+            return None;
         }
+    }
+
+    let first_score =
+        searcher.run_datagen_search(ctx, SearchLimiter::infinite(), VERIFICATION_DEPTH);
+    if first_score.abs() > VERIFICATION_SCORE_LIMIT {
+        return None;
+    }
+
+    searcher.new_game();
 
-        searcher.new_game();
+    let limiter = SearchLimiter::fixed_nodes(NODE_LIMIT);
+
+    // only one side ever makes mistakes, so the other gets to punish them
+    let weak_side = if rng.next_u32() >> 31 == 0 {
+        Color::RED
+    } else {
+        Color::BLUE
+    };
 
-        let outcome: Outcome;
+    let mut win_plies = 0u32;
+    let mut loss_plies = 0u32;
+    let mut draw_plies = 0u32;
 
-        let mut win_plies = 0u32;
-        let mut loss_plies = 0u32;
-        let mut draw_plies = 0u32;
+    loop {
+        let second_best = second_best_percent > 0
+            && ctx.pos.side_to_move() == weak_side
+            && rng.next_u32_bounded(100) < second_best_percent;
 
-        loop {
-            ctx.nnue_state.reset(ctx.pos);
-            let score = searcher.run_datagen_search(&mut ctx, limiter.clone(), MAX_DEPTH);
-            assert_ne!(ctx.best_move, AtaxxMove::None);
+        if second_best {
+            searcher.set_multi_pv(2);
+        }
+
+        ctx.nnue_state.reset(ctx.pos);
+        let mut score = searcher.run_datagen_search(ctx, limiter.clone(), MAX_DEPTH);
+        assert_ne!(ctx.best_move, AtaxxMove::None);
 
-            if score.abs() > SCORE_WIN {
-                outcome = if score > 0 {
-                    Outcome::RedWin
+        if second_best {
+            searcher.set_multi_pv(1);
+
+            if let Some(line) = ctx.root_lines.get(1) {
+                ctx.best_move = line.mv;
+                score = if ctx.pos.side_to_move() == Color::BLUE {
+                    -line.score
                 } else {
-                    Outcome::RedLoss
+                    line.score
                 };
-                break;
             }
+        }
 
-            if score > WIN_ADJ_MIN_SCORE {
-                win_plies += 1;
-                loss_plies = 0;
-                draw_plies = 0;
-            } else if score < -WIN_ADJ_MIN_SCORE {
-                win_plies = 0;
-                loss_plies += 1;
-                draw_plies = 0;
-            } else if score.abs() < DRAW_ADJ_MAX_SCORE {
-                win_plies = 0;
-                loss_plies = 0;
-                draw_plies += 1;
+        if score.abs() > SCORE_WIN {
+            return Some(if score > 0 {
+                Outcome::RedWin
             } else {
-                win_plies = 0;
-                loss_plies = 0;
-                draw_plies = 0;
-            }
+                Outcome::RedLoss
+            });
+        }
 
-            if win_plies >= WIN_ADJ_MAX_PLIES {
-                outcome = Outcome::RedWin;
-                break;
-            } else if loss_plies >= WIN_ADJ_MAX_PLIES {
-                outcome = Outcome::RedLoss;
-                break;
-            } else if draw_plies >= DRAW_ADJ_MAX_PLIES {
-                outcome = Outcome::Draw;
-                break;
-            }
+        if score > WIN_ADJ_MIN_SCORE {
+            win_plies += 1;
+            loss_plies = 0;
+            draw_plies = 0;
+        } else if score < -WIN_ADJ_MIN_SCORE {
+            win_plies = 0;
+            loss_plies += 1;
+            draw_plies = 0;
+        } else if score.abs() < DRAW_ADJ_MAX_SCORE {
+            win_plies = 0;
+            loss_plies = 0;
+            draw_plies += 1;
+        } else {
+            win_plies = 0;
+            loss_plies = 0;
+            draw_plies = 0;
+        }
 
-            ctx.pos.apply_move::<false, true>(ctx.best_move, None);
+        if win_plies >= WIN_ADJ_MAX_PLIES {
+            return Some(Outcome::RedWin);
+        } else if loss_plies >= WIN_ADJ_MAX_PLIES {
+            return Some(Outcome::RedLoss);
+        } else if draw_plies >= DRAW_ADJ_MAX_PLIES {
+            return Some(Outcome::Draw);
+        }
 
-            if ctx.pos.game_over() {
-                outcome = match ctx.pos.result() {
-                    GameResult::Win(color) => {
-                        if color != ctx.pos.side_to_move() {
-                            Outcome::RedWin
-                        } else {
-                            Outcome::RedLoss
-                        }
-                    }
-                    GameResult::Draw => Outcome::Draw,
-                };
-                break;
-            }
+        ctx.pos.apply_move::<false, true>(ctx.best_move, None);
+
+        // shallow iterations are a big part of a small node budget, and the
+        // previous search already left their results in the tt
+        ctx.start_depth = (ctx.depth_completed() - 1).max(1);
 
-            positions.push(T::pack(ctx.pos, score));
+        if ctx.pos.game_over() {
+            return Some(match ctx.pos.result() {
+                GameResult::Win(color) => {
+                    if color != ctx.pos.side_to_move() {
+                        Outcome::RedWin
+                    } else {
+                        Outcome::RedLoss
+                    }
+                }
+                GameResult::Draw => Outcome::Draw,
+            });
         }
 
-        T::write_all_with_outcome(&mut out, &mut positions, outcome);
+        positions.push(T::pack(ctx.pos, score));
+    }
+}
+
+fn run_thread<T: OutputFormat>(
+    id: u32,
+    games: u32,
+    second_best_percent: u32,
+    base_seed: u64,
+    out_dir: &Path,
+) -> Result<()> {
+    let open = |path: &Path| OpenOptions::new().create(true).append(true).open(path);
+
+    let mut out = BufWriter::new(open(&out_dir.join(format!("{}.{}", id, T::EXTENSION)))?);
+
+    // one line per game: the game index, its rng stream id and the opening move indices
+    let mut meta = BufWriter::new(open(&out_dir.join(format!("{}.meta", id)))?);
+    writeln!(
+        meta,
+        "base seed {} second best {}%",
+        base_seed, second_best_percent
+    )?;
+
+    let mut searcher = Searcher::new();
+    searcher.resize_tt(TT_SIZE);
+
+    let mut pos = Position::empty();
+    let mut ctx = SearchContext::new(&mut pos);
+
+    let mut positions = Vec::<T::Elem>::new();
+    let mut opening = Vec::new();
+
+    let start_time = Instant::now();
+
+    let mut total_positions = 0usize;
+
+    let mut attempt = 0u32;
+
+    let mut game = 0;
+    while game < games {
+        let stream = stream_id(id, attempt);
+        attempt += 1;
+
+        let mut rng = stream_rng(base_seed, stream);
+
+        let Some(outcome) = play_game::<T>(
+            &mut searcher,
+            &mut ctx,
+            &mut rng,
+            second_best_percent,
+            &mut positions,
+            &mut opening,
+        ) else {
+            continue;
+        };
+
+        T::write_all_with_outcome(&mut out, &mut positions, outcome)?;
+
+        writeln!(
+            meta,
+            "game {} stream {} opening {}",
+            game,
+            stream,
+            opening
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        )?;
 
         total_positions += positions.len();
 
@@ -347,7 +529,50 @@ fn run_thread<T: OutputFormat>(id: u32, games: u32, seed: u64, out_dir: &Path) {
         game += 1;
     }
 
-    out.flush().unwrap();
+    out.flush()?;
+    meta.flush()?;
+
+    Ok(())
+}
+
+// regenerates a single game from a datagen run's metadata, printing it as fens
+pub fn replay(base_seed: u64, stream: u64, second_best_percent: u32) -> Result<()> {
+    let mut searcher = Searcher::new();
+    searcher.resize_tt(TT_SIZE);
+
+    let mut pos = Position::empty();
+    let mut ctx = SearchContext::new(&mut pos);
+
+    let mut positions = Vec::new();
+    let mut opening = Vec::new();
+
+    let outcome = play_game::<Fen>(
+        &mut searcher,
+        &mut ctx,
+        &mut stream_rng(base_seed, stream),
+        second_best_percent,
+        &mut positions,
+        &mut opening,
+    );
+
+    println!(
+        "opening {}",
+        opening
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    let Some(outcome) = outcome else {
+        println!("opening discarded");
+        return Ok(());
+    };
+
+    let mut out = std::io::stdout().lock();
+    Fen::write_all_with_outcome(&mut out, &mut positions, outcome)?;
+
+    Ok(())
 }
 
 #[allow(clippy::unreadable_literal)]
@@ -359,7 +584,13 @@ fn mix(mut v: u64) -> u64 {
     v ^ v >> 33
 }
 
-pub fn run(output: &str, write_fens: bool, threads: u32, games: u32) {
+pub fn run(
+    output: &str,
+    write_fens: bool,
+    threads: u32,
+    games: u32,
+    second_best_percent: u32,
+) -> Result<()> {
     // extremely scuffed
     let time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -384,17 +615,74 @@ pub fn run(output: &str, write_fens: bool, threads: u32, games: u32) {
         println!("generating {} games each on {} threads", games, threads);
     }
 
+    if second_best_percent > 0 {
+        println!(
+            "one side plays its second best move {}% of the time",
+            second_best_percent
+        );
+    }
+
     std::thread::scope(|s| {
-        for id in 0..threads {
-            s.spawn(move || {
-                if write_fens {
-                    run_thread::<Fen>(id, games, base_seed + u64::from(id), output_dir);
-                } else {
-                    run_thread::<BulletFormat>(id, games, base_seed + u64::from(id), output_dir);
-                }
-            });
-        }
-    });
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                s.spawn(move || {
+                    if write_fens {
+                        run_thread::<Fen>(id, games, second_best_percent, base_seed, output_dir)
+                    } else {
+                        run_thread::<BulletFormat>(
+                            id,
+                            games,
+                            second_best_percent,
+                            base_seed,
+                            output_dir,
+                        )
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("datagen thread panicked"))
+    })?;
 
     println!("done");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BulletFormat, BulletFormatReader, Outcome, OutputFormat};
+    use crate::core::Color;
+    use crate::position::{GameResult, Position};
+
+    #[test]
+    fn bulletformat_round_trip() {
+        let positions = [
+            Position::from_fen("x5o/7/2-1-2/7/2-1-2/7/o5x x 0 1").unwrap(),
+            Position::from_fen("x5o/1x5/7/3o3/7/7/o5x o 3 7").unwrap(),
+        ];
+
+        let mut records = vec![
+            BulletFormat::pack(&positions[0], 120),
+            BulletFormat::pack(&positions[1], -45),
+        ];
+
+        let mut bytes = Vec::new();
+        BulletFormat::write_all_with_outcome(&mut bytes, &mut records, Outcome::RedWin).unwrap();
+
+        let points = BulletFormatReader::new(bytes.as_slice())
+            .collect::<crate::error::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(points.len(), positions.len());
+
+        for (point, (pos, red_score)) in points.iter().zip(positions.iter().zip([120, -45])) {
+            assert_eq!(point.pos.to_fen(), pos.to_fen());
+            assert_eq!(point.pos.key(), pos.key());
+            assert_eq!(point.red_score, red_score);
+            assert_eq!(point.result, GameResult::Win(Color::RED));
+        }
+    }
 }
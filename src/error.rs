@@ -0,0 +1,88 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::{AtaxxMove, MoveStrError};
+use crate::nnue::NetworkError;
+use crate::position::FenError;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Fen(FenError),
+    Network(NetworkError),
+    InvalidMove(String, MoveStrError),
+    IllegalMove(AtaxxMove),
+    Missing(String),
+    Invalid(String, String),
+    Unknown(String, String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    #[must_use]
+    pub fn missing(what: &str) -> Self {
+        Self::Missing(what.to_string())
+    }
+
+    #[must_use]
+    pub fn invalid(what: &str, value: &str) -> Self {
+        Self::Invalid(what.to_string(), value.to_string())
+    }
+
+    #[must_use]
+    pub fn unknown(what: &str, value: &str) -> Self {
+        Self::Unknown(what.to_string(), value.to_string())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Fen(err) => write!(f, "{}", err),
+            Error::Network(err) => write!(f, "{}", err),
+            Error::InvalidMove(mv, err) => write!(f, "Invalid move '{}': {}", mv, err),
+            Error::IllegalMove(mv) => write!(f, "Illegal move '{}'", mv),
+            Error::Missing(what) => write!(f, "Missing {}", what),
+            Error::Invalid(what, value) => write!(f, "Invalid {} '{}'", what, value),
+            Error::Unknown(what, value) => write!(f, "Unknown {} '{}'", what, value),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<FenError> for Error {
+    fn from(err: FenError) -> Self {
+        Self::Fen(err)
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
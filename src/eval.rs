@@ -21,8 +21,8 @@ use crate::nnue;
 use crate::position::Position;
 
 #[must_use]
-pub fn static_eval(pos: &Position, nnue_state: &nnue::NnueState) -> Score {
-    let eval = nnue_state.evaluate(pos.side_to_move());
+pub fn static_eval(pos: &Position, nnue_state: &mut nnue::NnueState) -> Score {
+    let eval = nnue_state.evaluate(pos);
     eval.clamp(-SCORE_WIN + 1, SCORE_WIN - 1)
 }
 
@@ -0,0 +1,101 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Score;
+
+const ENTRY_BITS: u32 = 14;
+const ENTRIES: usize = 1 << ENTRY_BITS;
+
+const EVAL_BITS: u32 = 16;
+const EVAL_MASK: u64 = (1 << EVAL_BITS) - 1;
+
+// the eval goes in the low bits, and the rest of the key above it.
+// the bits used for the index are stored anyway, as they cost nothing
+#[must_use]
+fn pack(key: u64, eval: Score) -> u64 {
+    (key & !EVAL_MASK) | u64::from(eval as i16 as u16)
+}
+
+// raw evals of recently seen positions, so transpositions skip the network.
+// each thread has its own, so unlike the tt it needs no atomics
+#[derive(Debug, Clone)]
+pub struct EvalCache {
+    entries: Vec<u64>,
+}
+
+impl EvalCache {
+    #[must_use]
+    fn index(key: u64) -> usize {
+        (key >> (64 - ENTRY_BITS)) as usize
+    }
+
+    #[must_use]
+    pub fn probe(&self, key: u64) -> Option<Score> {
+        let entry = self.entries[Self::index(key)];
+
+        // an empty entry only matches a key with no high bits set, which is never stored
+        if entry != 0 && entry & !EVAL_MASK == key & !EVAL_MASK {
+            Some(Score::from(entry as u16 as i16))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&mut self, key: u64, eval: Score) {
+        self.entries[Self::index(key)] = pack(key, eval);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(0);
+    }
+
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        self.entries.len() * std::mem::size_of::<u64>()
+    }
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        Self {
+            entries: vec![0; ENTRIES],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvalCache;
+
+    #[test]
+    fn stored_evals_are_found() {
+        let mut cache = EvalCache::default();
+
+        let key = 0x0123_4567_89ab_cdef;
+        assert_eq!(cache.probe(key), None);
+
+        cache.store(key, -250);
+        assert_eq!(cache.probe(key), Some(-250));
+
+        // same slot, different position
+        assert_eq!(cache.probe(key ^ 0x0000_0100_0000_0000), None);
+
+        cache.store(key ^ 0x0000_0100_0000_0000, 40);
+        assert_eq!(cache.probe(key), None);
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Color;
+use crate::datagen::{is_bulletformat_file, BulletFormatReader};
+use crate::error::{Error, Result};
+use crate::eval::static_eval_once;
+use crate::position::Position;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// converts evals to expected scores, in the same units the nets are trained with
+const EVAL_SCALE: f64 = 400.0;
+
+fn sigmoid(eval: f64) -> f64 {
+    1.0 / (1.0 + (-eval / EVAL_SCALE).exp())
+}
+
+// bulletformat files from datagen are also accepted, by extension.
+// lines are fens followed by '|'-separated fields, the last of which is the
+// game result for red (0.0, 0.5 or 1.0), as written by datagen
+fn parse_line(line: &str) -> Result<(Position, f64)> {
+    let mut fields = line.split('|').map(str::trim);
+
+    let fen = fields.next().unwrap_or_default();
+    let pos = Position::from_fen(fen)?;
+
+    let result = fields.next_back().ok_or_else(|| Error::missing("result"))?;
+    let result = result
+        .parse::<f64>()
+        .ok()
+        .filter(|result| (0.0..=1.0).contains(result))
+        .ok_or_else(|| Error::invalid("result", result))?;
+
+    Ok((pos, result))
+}
+
+#[must_use]
+fn correlation(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x) * (x - mean_x);
+        var_y += (y - mean_y) * (y - mean_y);
+    }
+
+    if var_x == 0.0 || var_y == 0.0 {
+        0.0
+    } else {
+        cov / (var_x * var_y).sqrt()
+    }
+}
+
+// loss is the mean squared error between the sigmoided eval and the result,
+// both from red's point of view
+pub fn run_evalcheck(path: &str) -> Result<()> {
+    let mut positions = Vec::new();
+    let mut skipped = 0usize;
+
+    if is_bulletformat_file(path) {
+        for (idx, point) in BulletFormatReader::open(path)?.enumerate() {
+            match point {
+                Ok(point) => {
+                    let result = point.red_result();
+                    positions.push((point.pos, result));
+                }
+                Err(err) => {
+                    eprintln!("Skipping record {}: {}", idx + 1, err);
+                    skipped += 1;
+                }
+            }
+        }
+    } else {
+        for (idx, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_line(&line) {
+                Ok(position) => positions.push(position),
+                Err(err) => {
+                    eprintln!("Skipping line {}: {}", idx + 1, err);
+                    skipped += 1;
+                }
+            }
+        }
+    }
+
+    let mut evals = Vec::with_capacity(positions.len());
+    let mut results = Vec::with_capacity(positions.len());
+
+    for (pos, result) in positions {
+        let eval = static_eval_once(&pos);
+        let red_eval = if pos.side_to_move() == Color::RED {
+            eval
+        } else {
+            -eval
+        };
+
+        evals.push(f64::from(red_eval));
+        results.push(result);
+    }
+
+    if evals.is_empty() {
+        return Err(Error::missing("positions"));
+    }
+
+    let loss = evals
+        .iter()
+        .zip(&results)
+        .map(|(&eval, &result)| (sigmoid(eval) - result).powi(2))
+        .sum::<f64>()
+        / evals.len() as f64;
+
+    println!("positions: {} ({} skipped)", evals.len(), skipped);
+    println!("loss: {:.6}", loss);
+    println!("correlation: {:.4}", correlation(&evals, &results));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correlation, parse_line};
+
+    #[test]
+    fn datagen_lines_parse() {
+        let (_, result) = parse_line("x5o/7/7/7/7/7/o5x x 0 1 | 25 | 1.0").unwrap();
+        assert!((result - 1.0).abs() < f64::EPSILON);
+
+        assert!(parse_line("x5o/7/7/7/7/7/o5x x 0 1 | 25 | 2.0").is_err());
+        assert!(parse_line("x5o/7/7/7/7/7/o5x x 0 1").is_err());
+    }
+
+    #[test]
+    fn perfectly_correlated() {
+        let xs = [1.0, 2.0, 3.0];
+        let ys = [2.0, 4.0, 6.0];
+        assert!((correlation(&xs, &ys) - 1.0).abs() < 1e-9);
+    }
+}
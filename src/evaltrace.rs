@@ -0,0 +1,94 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::{Color, Square};
+use crate::iolog::uai_println;
+use crate::nnue::{trace_eval, GlobalInput};
+use crate::position::Position;
+use std::fmt::Write;
+
+#[must_use]
+fn global_name(input: GlobalInput) -> &'static str {
+    match input {
+        GlobalInput::StoneDifference => "stone difference",
+        GlobalInput::Empties => "empty squares",
+        GlobalInput::Halfmoves => "halfmove clock",
+    }
+}
+
+// the board with what each square's features add to the eval under its contents.
+// the net is not linear, so these need not sum to the eval
+pub fn run_evaltrace(pos: &Position) {
+    let trace = trace_eval(pos);
+
+    let separator = format!(" {}+", "+-------".repeat(7));
+
+    for rank in (0u32..7).rev() {
+        uai_println!("{}", separator);
+
+        let mut contents = String::new();
+        let mut contributions = String::new();
+
+        for file in 0u32..7 {
+            let sq = Square::from_coords(rank, file);
+
+            let content = if pos.gap_at(sq) {
+                '-'
+            } else {
+                pos.color_at(sq).to_char()
+            };
+
+            let _ = write!(contents, "|   {}   ", content);
+
+            match trace.squares[sq.idx()] {
+                Some(contribution) => {
+                    let _ = write!(contributions, "| {:^+5} ", contribution);
+                }
+                None => contributions.push_str("|       "),
+            }
+        }
+
+        uai_println!(" {}| {}", contents, rank + 1);
+        uai_println!(" {}|", contributions);
+    }
+
+    uai_println!("{}", separator);
+    uai_println!("     a       b       c       d       e       f       g");
+    uai_println!();
+
+    for (input, bucket, contribution) in &trace.globals {
+        uai_println!(
+            "{} bucket {}: {:+}",
+            global_name(*input),
+            bucket,
+            contribution
+        );
+    }
+
+    if !trace.globals.is_empty() {
+        uai_println!();
+    }
+
+    let side = if pos.side_to_move() == Color::RED {
+        "Red"
+    } else {
+        "Blue"
+    };
+
+    uai_println!("NNUE eval: {} ({} to move)", trace.eval, side);
+}
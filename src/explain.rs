@@ -0,0 +1,194 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::ataxx_move::AtaxxMove;
+use crate::core::MAX_DEPTH;
+use crate::iolog::uai_println;
+use crate::limit::SearchLimiter;
+use crate::movegen::{fill_move_list, MoveList};
+use crate::position::Position;
+use crate::search::{format_score, SearchContext, Searcher};
+
+pub const DEFAULT_EXPLAIN_DEPTH: i32 = 8;
+
+// the chosen move and the best alternatives to it
+const EXPLAIN_LINES: usize = 4;
+
+#[must_use]
+fn mobility(pos: &Position) -> usize {
+    let mut moves = MoveList::new();
+    fill_move_list(&mut moves, pos);
+    moves.iter().filter(|&&mv| mv != AtaxxMove::Null).count()
+}
+
+// what a move changes on the board, from the point of view of the side playing it
+struct MoveEffects {
+    captures: u32,
+    material: i32,
+    our_mobility: usize,
+    their_mobility: usize,
+}
+
+impl MoveEffects {
+    fn new(pos: &mut Position, mv: AtaxxMove) -> Self {
+        let us = pos.side_to_move();
+        let captures = pos.flip_count(mv);
+
+        pos.apply_move::<true, true>(mv, None);
+
+        let material = pos.color_occupancy(us).popcount() as i32
+            - pos.color_occupancy(us.flip()).popcount() as i32;
+        let their_mobility = mobility(pos);
+
+        // our options next turn, as if they passed
+        pos.apply_pretend_pass();
+        let our_mobility = mobility(pos);
+        pos.pop_pretend_pass();
+
+        pos.pop_move::<true>(None);
+
+        Self {
+            captures,
+            material,
+            our_mobility,
+            their_mobility,
+        }
+    }
+}
+
+// searches the best few moves and explains in plain words why the first beat the others
+pub fn run_explain(searcher: &mut Searcher, pos: &Position, depth: i32) {
+    let mut pos = pos.clone();
+
+    if pos.game_over() {
+        uai_println!("the game is over, there is nothing to explain");
+        return;
+    }
+
+    // anything here is left over from the previous search of this position
+    let prev_tt_move = searcher.tt_move(&pos);
+
+    let multi_pv = searcher.multi_pv();
+    searcher.set_multi_pv(EXPLAIN_LINES);
+
+    let lines = {
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+
+        let _ = searcher.run_datagen_search(
+            &mut ctx,
+            SearchLimiter::infinite(),
+            depth.clamp(1, MAX_DEPTH),
+        );
+
+        std::mem::take(&mut ctx.root_lines)
+    };
+
+    searcher.set_multi_pv(multi_pv);
+
+    let Some(&best) = lines.first() else {
+        uai_println!("no moves were searched");
+        return;
+    };
+
+    let material_before = pos.color_occupancy(pos.side_to_move()).popcount() as i32
+        - pos.color_occupancy(pos.side_to_move().flip()).popcount() as i32;
+
+    let best_effects = MoveEffects::new(&mut pos, best.mv);
+
+    uai_println!(
+        "best move {} at depth {}, score {}",
+        best.mv,
+        best.depth,
+        format_score(best.score)
+    );
+    uai_println!(
+        "  captures {}, material {:+} -> {:+}, our moves {}, their moves {}",
+        best_effects.captures,
+        material_before,
+        best_effects.material,
+        best_effects.our_mobility,
+        best_effects.their_mobility
+    );
+
+    if prev_tt_move == AtaxxMove::None {
+        uai_println!("  no earlier search of this position was in the tt");
+    } else if prev_tt_move == best.mv {
+        uai_println!(
+            "  the tt already held this move from an earlier search, so it was tried first"
+        );
+    } else {
+        uai_println!(
+            "  the tt held {} from an earlier search, which was searched first and overturned",
+            prev_tt_move
+        );
+    }
+
+    if lines.len() == 1 {
+        uai_println!("  it is the only legal move");
+        return;
+    }
+
+    for line in &lines[1..] {
+        let effects = MoveEffects::new(&mut pos, line.mv);
+
+        let mut reasons = Vec::new();
+
+        if effects.captures < best_effects.captures {
+            reasons.push(format!(
+                "captures {} fewer",
+                best_effects.captures - effects.captures
+            ));
+        } else if effects.captures > best_effects.captures {
+            reasons.push(format!(
+                "captures {} more, but that doesn't hold up",
+                effects.captures - best_effects.captures
+            ));
+        }
+
+        if effects.their_mobility > best_effects.their_mobility {
+            reasons.push(format!(
+                "leaves them {} more moves",
+                effects.their_mobility - best_effects.their_mobility
+            ));
+        }
+
+        if effects.our_mobility < best_effects.our_mobility {
+            reasons.push(format!(
+                "leaves us {} fewer moves",
+                best_effects.our_mobility - effects.our_mobility
+            ));
+        }
+
+        if reasons.is_empty() {
+            reasons.push(String::from("only the deeper search tells them apart"));
+        }
+
+        uai_println!(
+            "alternative {} scores {} ({} worse{}): {}",
+            line.mv,
+            format_score(line.score),
+            best.score - line.score,
+            if line.depth < best.depth {
+                format!(", searched to depth {}", line.depth)
+            } else {
+                String::new()
+            },
+            reasons.join(", ")
+        );
+    }
+}
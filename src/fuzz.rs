@@ -0,0 +1,136 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::bitboard::Bitboard;
+use crate::core::{Color, Square};
+use crate::iolog::uai_println;
+use crate::movegen::{fill_move_list, MoveList};
+use crate::position::Position;
+use crate::skill::skill_seed;
+use crate::util::rng::Jsf64Rng;
+
+pub const DEFAULT_FUZZ_COUNT: usize = 10_000;
+
+// each non-corner square becomes a gap with a chance of one in this many
+const GAP_CHANCE: u32 = 8;
+const MAX_PLAYOUT_PLIES: u32 = 120;
+
+// starting stones on the usual corners, with gaps anywhere else
+fn random_start(rng: &mut Jsf64Rng) -> Position {
+    let red = Square::A7.bit() | Square::G1.bit();
+    let blue = Square::A1.bit() | Square::G7.bit();
+
+    let mut gaps = Bitboard::EMPTY;
+    for sq in Bitboard::ALL & !(red | blue) {
+        if rng.next_u32_bounded(GAP_CHANCE) == 0 {
+            gaps.set(sq);
+        }
+    }
+
+    Position::from_boards(red, blue, gaps, Color::RED, 0, 1)
+}
+
+// returns a description of the first inconsistency found
+fn check(pos: &mut Position) -> Option<String> {
+    let fen = pos.to_fen();
+
+    let Ok(parsed) = Position::from_fen(&fen) else {
+        return Some(String::from("to_fen output does not parse"));
+    };
+
+    if parsed.to_fen() != fen {
+        return Some(format!("round-tripped to {}", parsed.to_fen()));
+    }
+
+    // the parsed key is regenerated from scratch, ours was updated incrementally
+    if parsed.key() != pos.key() {
+        return Some(format!(
+            "incremental key {:16x} != regenerated key {:16x}",
+            pos.key(),
+            parsed.key()
+        ));
+    }
+
+    let key = pos.key();
+
+    let mut moves = MoveList::new();
+    fill_move_list(&mut moves, pos);
+
+    for &mv in &moves {
+        pos.apply_move::<true, true>(mv, None);
+        pos.pop_move::<true>(None);
+
+        if pos.to_fen() != fen || pos.key() != key {
+            return Some(format!("make/unmake of {} changed the position", mv));
+        }
+    }
+
+    None
+}
+
+// plays random games from random gap sets, checking every position along the way
+pub fn run_fuzz_fens(count: usize) {
+    let seed = skill_seed();
+    let mut rng = Jsf64Rng::new(seed);
+
+    uai_println!("fuzzing {} positions with seed {}", count, seed);
+
+    let mut checked = 0usize;
+    let mut failures = 0usize;
+
+    while checked < count {
+        let mut pos = random_start(&mut rng);
+        let plies = rng.next_u32_bounded(MAX_PLAYOUT_PLIES);
+
+        for _ in 0..plies {
+            if checked >= count || pos.game_over() {
+                break;
+            }
+
+            if let Some(failure) = check(&mut pos) {
+                uai_println!("{}: {}", pos.to_fen(), failure);
+                failures += 1;
+            }
+
+            checked += 1;
+
+            let mut moves = MoveList::new();
+            fill_move_list(&mut moves, &pos);
+
+            let mv = moves[rng.next_u32_bounded(moves.len() as u32) as usize];
+            pos.apply_move::<true, true>(mv, None);
+        }
+    }
+
+    uai_println!("checked {} positions, {} failures", checked, failures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, random_start};
+    use crate::util::rng::Jsf64Rng;
+
+    #[test]
+    fn random_starts_are_consistent() {
+        let mut rng = Jsf64Rng::new(1234);
+
+        for _ in 0..100 {
+            let mut pos = random_start(&mut rng);
+            assert_eq!(check(&mut pos), None, "{}", pos.to_fen());
+        }
+    }
+}
@@ -0,0 +1,133 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::error::{Error, Result};
+use crate::iolog::uai_println;
+use crate::movegen::{fill_move_list, MoveList};
+use crate::position::Position;
+use crate::util::rng::Jsf64Rng;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// openbench gives up on a worker that produces nothing, so this only
+// guards against a position that cannot produce anything at all
+const MAX_ATTEMPTS_PER_FEN: usize = 1000;
+
+const MIN_RANDOM_PLIES: u32 = 8;
+
+fn load_book(path: &str) -> Result<Vec<String>> {
+    let fens = BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .map(|line| {
+            line.split('|')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .filter(|fen| !fen.is_empty())
+        .collect::<Vec<_>>();
+
+    if fens.is_empty() {
+        return Err(Error::missing("book positions"));
+    }
+
+    Ok(fens)
+}
+
+// plays 8 or 9 random moves from the start position or a random book position,
+// throwing away any game that ends before then
+pub fn random_fen(start: &Position, rng: &mut Jsf64Rng) -> Option<String> {
+    let mut pos = start.clone();
+
+    let plies = MIN_RANDOM_PLIES + (rng.next_u32() >> 31);
+
+    for _ in 0..plies {
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, &pos);
+
+        if moves.is_empty() {
+            return None;
+        }
+
+        let mv = moves[rng.next_u32_bounded(moves.len() as u32) as usize];
+        pos.apply_move::<false, true>(mv, None);
+
+        if pos.game_over() {
+            return None;
+        }
+    }
+
+    Some(pos.to_fen())
+}
+
+// in the form openbench expects, one "info string genfens <fen>" line per position
+pub fn run_genfens(count: usize, seed: u64, book: Option<&str>) -> Result<()> {
+    let book = book.map(load_book).transpose()?;
+
+    let mut rng = Jsf64Rng::new(seed);
+
+    for _ in 0..count {
+        let mut attempts = 0usize;
+
+        loop {
+            let start = match &book {
+                Some(fens) => {
+                    let idx = rng.next_u32_bounded(fens.len() as u32) as usize;
+                    Position::from_fen(&fens[idx])?
+                }
+                None => Position::startpos(),
+            };
+
+            if let Some(fen) = random_fen(&start, &mut rng) {
+                uai_println!("info string genfens {}", fen);
+                break;
+            }
+
+            attempts += 1;
+            if attempts >= MAX_ATTEMPTS_PER_FEN {
+                return Err(Error::invalid("genfens start position", &start.to_fen()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::random_fen;
+    use crate::position::Position;
+    use crate::util::rng::Jsf64Rng;
+
+    #[test]
+    fn same_seed_same_fens() {
+        let start = Position::startpos();
+
+        let mut first = Jsf64Rng::new(42);
+        let mut second = Jsf64Rng::new(42);
+
+        for _ in 0..8 {
+            let fen = random_fen(&start, &mut first);
+            assert!(fen.is_some());
+            assert_eq!(fen, random_fen(&start, &mut second));
+        }
+    }
+}
@@ -16,16 +16,20 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::ataxx_move::AtaxxMove;
+use crate::bitboard::Bitboard;
 use crate::core::*;
 use crate::util::rng;
 
 const COLOR_SQUARE_SIZE: usize = Color::N_COLORS * Square::N_SQUARES;
 const STM_SIZE: usize = 1;
+const GAP_SIZE: usize = Square::N_SQUARES;
 
-const TOTAL_SIZE: usize = COLOR_SQUARE_SIZE + STM_SIZE;
+const TOTAL_SIZE: usize = COLOR_SQUARE_SIZE + STM_SIZE + GAP_SIZE;
 
 const COLOR_SQUARE_OFFSET: usize = 0;
 const STM_OFFSET: usize = COLOR_SQUARE_OFFSET + COLOR_SQUARE_SIZE;
+const GAP_OFFSET: usize = STM_OFFSET + STM_SIZE;
 
 #[allow(clippy::unreadable_literal)]
 const HASHES: [u64; TOTAL_SIZE] = rng::fill_u64_array(0x22ff7d8af027681b);
@@ -42,3 +46,267 @@ pub fn color_square_key(c: Color, sq: Square) -> u64 {
 pub fn stm_key() -> u64 {
     HASHES[STM_OFFSET]
 }
+
+#[must_use]
+pub fn gap_key(sq: Square) -> u64 {
+    debug_assert!(sq != Square::NONE);
+
+    HASHES[GAP_OFFSET + sq.idx()]
+}
+
+// one of the board's eight symmetries: an optional flip across the
+// a1-g7 diagonal, then optional horizontal and vertical mirrors
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Symmetry(u8);
+
+impl Symmetry {
+    pub const IDENTITY: Self = Self(0);
+
+    const DIAGONAL: u8 = 1;
+    const HORIZONTAL: u8 = 2;
+    const VERTICAL: u8 = 4;
+
+    pub const N_SYMMETRIES: usize = 8;
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..Self::N_SYMMETRIES as u8).map(Self)
+    }
+
+    #[must_use]
+    pub fn idx(self) -> usize {
+        usize::from(self.0)
+    }
+
+    #[must_use]
+    const fn has(self, flag: u8) -> bool {
+        self.0 & flag != 0
+    }
+
+    #[must_use]
+    pub fn apply_bitboard(self, mut bb: Bitboard) -> Bitboard {
+        if self.has(Self::DIAGONAL) {
+            bb = bb.flip_diagonal();
+        }
+        if self.has(Self::HORIZONTAL) {
+            bb = bb.mirror_horizontal();
+        }
+        if self.has(Self::VERTICAL) {
+            bb = bb.mirror_vertical();
+        }
+        bb
+    }
+
+    #[must_use]
+    pub fn apply_square(self, sq: Square) -> Square {
+        APPLIED_SQUARES[self.idx()][sq.bit_idx()]
+    }
+
+    #[must_use]
+    const fn flip_square(self, mut sq: Square) -> Square {
+        if self.has(Self::DIAGONAL) {
+            sq = sq.flip_diagonal();
+        }
+        if self.has(Self::HORIZONTAL) {
+            sq = sq.flip_horizontal();
+        }
+        if self.has(Self::VERTICAL) {
+            sq = sq.flip_vertical();
+        }
+        sq
+    }
+
+    // every step undoes itself, so only the order changes
+    #[must_use]
+    fn invert_square(self, mut sq: Square) -> Square {
+        if self.has(Self::VERTICAL) {
+            sq = sq.flip_vertical();
+        }
+        if self.has(Self::HORIZONTAL) {
+            sq = sq.flip_horizontal();
+        }
+        if self.has(Self::DIAGONAL) {
+            sq = sq.flip_diagonal();
+        }
+        sq
+    }
+
+    #[must_use]
+    fn map_move(mv: AtaxxMove, f: impl Fn(Square) -> Square) -> AtaxxMove {
+        match mv {
+            AtaxxMove::Single(to) => AtaxxMove::Single(f(to)),
+            AtaxxMove::Double(from, to) => AtaxxMove::Double(f(from), f(to)),
+            other => other,
+        }
+    }
+
+    #[must_use]
+    pub fn apply_move(self, mv: AtaxxMove) -> AtaxxMove {
+        Self::map_move(mv, |sq| self.apply_square(sq))
+    }
+
+    #[must_use]
+    pub fn invert_move(self, mv: AtaxxMove) -> AtaxxMove {
+        Self::map_move(mv, |sq| self.invert_square(sq))
+    }
+}
+
+// every square through every symmetry by bit index, as nnue features map squares on
+// each accumulator update and a lookup is much cheaper than up to three flips
+const APPLIED_SQUARES: [[Square; 64]; Symmetry::N_SYMMETRIES] = {
+    let mut table = [[Square::NONE; 64]; Symmetry::N_SYMMETRIES];
+
+    let mut sym = 0;
+    while sym < Symmetry::N_SYMMETRIES {
+        let mut rank = 0;
+        while rank < 7 {
+            let mut file = 0;
+            while file < 7 {
+                let sq = Square::from_coords(rank, file);
+                table[sym][sq.bit_idx()] = Symmetry(sym as u8).flip_square(sq);
+                file += 1;
+            }
+            rank += 1;
+        }
+        sym += 1;
+    }
+
+    table
+};
+
+#[must_use]
+fn key_from_parts(red: Bitboard, blue: Bitboard, gaps: Bitboard, stm: Color) -> u64 {
+    let mut key = 0;
+
+    for sq in red {
+        key ^= color_square_key(Color::RED, sq);
+    }
+
+    for sq in blue {
+        key ^= color_square_key(Color::BLUE, sq);
+    }
+
+    for sq in gaps {
+        key ^= gap_key(sq);
+    }
+
+    if stm == Color::BLUE {
+        key ^= stm_key();
+    }
+
+    key
+}
+
+// the smallest key over every symmetry of the board, and over swapping the colours
+// along with the side to move, which leaves side-to-move relative scores unchanged.
+// moves in the position map into the keyed frame through the returned symmetry
+#[must_use]
+pub fn canonical_key(red: Bitboard, blue: Bitboard, gaps: Bitboard, stm: Color) -> (u64, Symmetry) {
+    Symmetry::all()
+        .flat_map(|sym| {
+            let (red, blue, gaps) = (
+                sym.apply_bitboard(red),
+                sym.apply_bitboard(blue),
+                sym.apply_bitboard(gaps),
+            );
+
+            [
+                (key_from_parts(red, blue, gaps, stm), sym),
+                (key_from_parts(blue, red, gaps, stm.flip()), sym),
+            ]
+        })
+        .min_by_key(|&(key, _)| key)
+        .unwrap()
+}
+
+// the symmetry taking the board to its canonical orientation, for nnue features. gaps
+// decide first, as they never change during a game, so while they are asymmetric
+// neither does the orientation. stones only break ties, and colours are not swapped
+#[must_use]
+pub fn canonical_orientation(red: Bitboard, blue: Bitboard, gaps: Bitboard) -> Symmetry {
+    Symmetry::all()
+        .min_by_key(|sym| {
+            (
+                sym.apply_bitboard(gaps).raw(),
+                sym.apply_bitboard(red | blue).raw(),
+                sym.apply_bitboard(red).raw(),
+            )
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_key, canonical_orientation, key_from_parts, Symmetry};
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::Square;
+    use crate::position::Position;
+
+    #[test]
+    fn mirrored_positions_share_canonical_key() {
+        let pos = Position::from_fen("x5o/7/2-4/3x3/1o5/7/o5x x 0 1").unwrap();
+        let mirrored = Position::from_fen("o5x/7/4-2/3x3/5o1/7/x5o x 0 1").unwrap();
+        let swapped = Position::from_fen("o5x/7/2-4/3o3/1x5/7/x5o o 0 1").unwrap();
+
+        let parts = |pos: &Position| {
+            (
+                pos.red_occupancy(),
+                pos.blue_occupancy(),
+                pos.gaps(),
+                pos.side_to_move(),
+            )
+        };
+
+        let (red, blue, gaps, stm) = parts(&pos);
+        assert_eq!(key_from_parts(red, blue, gaps, stm), pos.key());
+
+        let (key, _) = canonical_key(red, blue, gaps, stm);
+
+        for other in [&mirrored, &swapped] {
+            let (red, blue, gaps, stm) = parts(other);
+            assert_eq!(canonical_key(red, blue, gaps, stm).0, key);
+        }
+    }
+
+    #[test]
+    fn moves_round_trip() {
+        let mv = AtaxxMove::Double(Square::B2, Square::D3);
+
+        for sym in Symmetry::all() {
+            assert_eq!(sym.invert_move(sym.apply_move(mv)), mv);
+        }
+
+        let sym = Symmetry(Symmetry::DIAGONAL | Symmetry::HORIZONTAL);
+        assert_eq!(
+            sym.apply_move(mv),
+            AtaxxMove::Double(Square::F2, Square::E4)
+        );
+        assert_eq!(sym.apply_move(AtaxxMove::Null), AtaxxMove::Null);
+    }
+
+    #[test]
+    fn mirrored_positions_share_orientation() {
+        let pos = Position::from_fen("x5o/7/2-4/3x3/1o5/7/o5x x 0 1").unwrap();
+
+        let oriented = |red, blue, gaps| {
+            let sym = canonical_orientation(red, blue, gaps);
+            (
+                sym.apply_bitboard(red),
+                sym.apply_bitboard(blue),
+                sym.apply_bitboard(gaps),
+            )
+        };
+
+        let canonical = oriented(pos.red_occupancy(), pos.blue_occupancy(), pos.gaps());
+
+        for sym in Symmetry::all() {
+            assert_eq!(
+                oriented(
+                    sym.apply_bitboard(pos.red_occupancy()),
+                    sym.apply_bitboard(pos.blue_occupancy()),
+                    sym.apply_bitboard(pos.gaps()),
+                ),
+                canonical
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::ataxx_move::AtaxxMove;
+use crate::core::{Color, Square};
+
+pub const MAX_HISTORY: i32 = 16384;
+
+const HISTORY_BONUS_SCALE: i32 = 300;
+const HISTORY_BONUS_OFFSET: i32 = 250;
+const MAX_HISTORY_BONUS: i32 = 2500;
+
+#[must_use]
+pub fn history_bonus(depth: i32) -> i32 {
+    (depth * HISTORY_BONUS_SCALE - HISTORY_BONUS_OFFSET).clamp(0, MAX_HISTORY_BONUS)
+}
+
+// a move can flip at most every square around its destination
+const MAX_FLIPS: usize = 8;
+
+// quiets use butterfly history, indexed by side to move and the move's source and destination,
+// with singles using their destination as the source too. captures are indexed by destination
+// and how many stones they flip instead, so moves that keep winning material stand out
+#[derive(Debug, Clone)]
+pub struct HistoryTable {
+    quiets: [[[i16; Square::N_SQUARES]; Square::N_SQUARES]; Color::N_COLORS],
+    captures: [[[i16; MAX_FLIPS]; Square::N_SQUARES]; Color::N_COLORS],
+}
+
+impl HistoryTable {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    #[must_use]
+    fn squares(mv: AtaxxMove) -> Option<(Square, Square)> {
+        match mv {
+            AtaxxMove::Single(to) => Some((to, to)),
+            AtaxxMove::Double(from, to) => Some((from, to)),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, stm: Color, mv: AtaxxMove, flips: u32) -> i32 {
+        Self::squares(mv).map_or(0, |(from, to)| {
+            i32::from(if flips == 0 {
+                self.quiets[stm.idx()][from.idx()][to.idx()]
+            } else {
+                self.captures[stm.idx()][to.idx()][flips as usize - 1]
+            })
+        })
+    }
+
+    // gravity keeps entries within the limit, with big values moving less
+    pub fn update(&mut self, stm: Color, mv: AtaxxMove, flips: u32, bonus: i32) {
+        let Some((from, to)) = Self::squares(mv) else {
+            return;
+        };
+
+        let entry = if flips == 0 {
+            &mut self.quiets[stm.idx()][from.idx()][to.idx()]
+        } else {
+            &mut self.captures[stm.idx()][to.idx()][flips as usize - 1]
+        };
+
+        let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+        let value = i32::from(*entry);
+
+        *entry = (value + bonus - value * bonus.abs() / MAX_HISTORY) as i16;
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self {
+            quiets: [[[0; Square::N_SQUARES]; Square::N_SQUARES]; Color::N_COLORS],
+            captures: [[[0; MAX_FLIPS]; Square::N_SQUARES]; Color::N_COLORS],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history_bonus, HistoryTable, MAX_HISTORY};
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::{Color, Square};
+
+    #[test]
+    fn history_stays_bounded() {
+        let mut history = HistoryTable::default();
+        let mv = AtaxxMove::Double(Square::A7, Square::C5);
+
+        for _ in 0..1000 {
+            history.update(Color::RED, mv, 0, history_bonus(20));
+        }
+
+        assert!(history.get(Color::RED, mv, 0) <= MAX_HISTORY);
+        assert!(history.get(Color::RED, mv, 0) > 0);
+        assert_eq!(history.get(Color::BLUE, mv, 0), 0);
+
+        for _ in 0..1000 {
+            history.update(Color::RED, mv, 0, -history_bonus(20));
+        }
+
+        assert!(history.get(Color::RED, mv, 0) >= -MAX_HISTORY);
+        assert!(history.get(Color::RED, mv, 0) < 0);
+    }
+
+    #[test]
+    fn captures_are_kept_apart_by_flip_count() {
+        let mut history = HistoryTable::default();
+        let mv = AtaxxMove::Single(Square::D4);
+
+        history.update(Color::RED, mv, 3, history_bonus(8));
+
+        assert!(history.get(Color::RED, mv, 3) > 0);
+        assert_eq!(history.get(Color::RED, mv, 2), 0);
+        assert_eq!(history.get(Color::RED, mv, 0), 0);
+        assert_eq!(
+            history.get(Color::RED, AtaxxMove::Double(Square::B2, Square::D4), 3),
+            history.get(Color::RED, mv, 3)
+        );
+    }
+}
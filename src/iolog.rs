@@ -0,0 +1,83 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static LOG_FILE: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+// prints a line to the gui, and records it in the log file if there is one
+macro_rules! uai_println {
+    () => {
+        uai_println!("")
+    };
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        println!("{}", line);
+        $crate::iolog::log_sent(&line);
+    }};
+}
+
+pub(crate) use uai_println;
+
+// an existing log is appended to, so that several sessions can share one
+pub fn set_log_file(path: Option<&str>) -> std::io::Result<()> {
+    let file = path
+        .map(|path| File::options().create(true).append(true).open(path))
+        .transpose()?;
+
+    *LOG_FILE.lock().unwrap() = file.map(BufWriter::new);
+
+    Ok(())
+}
+
+pub fn log_received(line: &str) {
+    log_line("<<", line);
+}
+
+pub fn log_sent(line: &str) {
+    log_line(">>", line);
+}
+
+// flushed after every line, as the log is most useful when the engine hangs or crashes
+fn log_line(direction: &str, line: &str) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+
+    let Some(out) = log_file.as_mut() else {
+        return;
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+
+    for line in line.lines() {
+        let _ = writeln!(
+            out,
+            "[{}.{:03}] {} {}",
+            time.as_secs(),
+            time.subsec_millis(),
+            direction,
+            line
+        );
+    }
+
+    let _ = out.flush();
+}
@@ -16,19 +16,79 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::ataxx_move::{AtaxxMove, PackedMove};
+use crate::params::SearchParams;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone)]
-enum SearchLimiterType {
-    Infinite,
-    FixedNodes(usize),
-    MoveTime(Instant),
-    Tournament(TimeManager),
+// the clock is read once this many nodes have passed since the last read
+const CLOCK_CHECK_INTERVAL: usize = 2048;
+
+// flags shared between a running search and whoever is driving it
+#[derive(Debug, Default)]
+pub struct SearchSignals {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+    // the main thread's best move from its last finished iteration
+    best_move: AtomicU16,
+    bestmove_sent: AtomicBool,
+}
+
+impl SearchSignals {
+    pub fn reset(&self, pondering: bool) {
+        self.stop.store(false, Ordering::SeqCst);
+        self.pondering.store(pondering, Ordering::SeqCst);
+        self.best_move
+            .store(PackedMove::NONE.raw(), Ordering::SeqCst);
+        self.bestmove_sent.store(false, Ordering::SeqCst);
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    #[must_use]
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+
+    #[must_use]
+    pub fn pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    pub fn set_best_move(&self, mv: AtaxxMove) {
+        self.best_move.store(mv.pack().raw(), Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn best_move(&self) -> AtaxxMove {
+        PackedMove::from_raw(self.best_move.load(Ordering::Relaxed)).unpack()
+    }
+
+    // exactly one bestmove goes out per search, whether from the search or the watchdog
+    #[must_use]
+    pub fn claim_bestmove(&self) -> bool {
+        !self.bestmove_sent.swap(true, Ordering::SeqCst)
+    }
+
+    #[must_use]
+    pub fn bestmove_sent(&self) -> bool {
+        self.bestmove_sent.load(Ordering::SeqCst)
+    }
 }
 
+// independent constraints, whichever triggers first ends the search
 #[derive(Debug, Clone)]
 pub struct SearchLimiter {
-    limiter: SearchLimiterType,
+    node_limit: Option<usize>,
+    end_time: Option<Instant>,
+    time_manager: Option<TimeManager>,
+    next_clock_check: usize,
     stopped: bool,
 }
 
@@ -36,61 +96,140 @@ impl SearchLimiter {
     #[must_use]
     pub fn infinite() -> Self {
         Self {
-            limiter: SearchLimiterType::Infinite,
+            node_limit: None,
+            end_time: None,
+            time_manager: None,
+            next_clock_check: 0,
             stopped: false,
         }
     }
 
     #[must_use]
     pub fn fixed_nodes(nodes: usize) -> Self {
-        Self {
-            limiter: SearchLimiterType::FixedNodes(nodes),
-            stopped: false,
-        }
+        Self::infinite().with_nodes(nodes)
+    }
+
+    #[must_use]
+    pub fn tournament(
+        our_time_ms: u64,
+        our_inc_ms: u64,
+        moves_to_go: u64,
+        move_overhead_ms: u64,
+        params: &SearchParams,
+    ) -> Self {
+        Self::infinite().with_time_manager(TimeManager::new(
+            our_time_ms,
+            our_inc_ms,
+            moves_to_go,
+            move_overhead_ms,
+            params,
+        ))
+    }
+
+    // tightens the node limit, keeping the smaller one if already set
+    #[must_use]
+    pub fn with_nodes(mut self, nodes: usize) -> Self {
+        self.node_limit = Some(self.node_limit.map_or(nodes, |limit| limit.min(nodes)));
+        self
     }
 
     #[must_use]
-    pub fn move_time(ms: u64) -> Self {
+    pub fn with_move_time(mut self, ms: u64) -> Self {
         let end = Instant::now() + Duration::from_millis(ms);
-        Self {
-            limiter: SearchLimiterType::MoveTime(end),
-            stopped: false,
-        }
+        self.end_time = Some(self.end_time.map_or(end, |time| time.min(end)));
+        self
     }
 
     #[must_use]
-    pub fn tournament(our_time_ms: u64, our_inc_ms: u64, moves_to_go: u64) -> Self {
-        Self {
-            limiter: SearchLimiterType::Tournament(TimeManager::new(
-                our_time_ms,
-                our_inc_ms,
-                moves_to_go,
-            )),
-            stopped: false,
-        }
+    pub fn with_time_manager(mut self, time_manager: TimeManager) -> Self {
+        self.time_manager = Some(time_manager);
+        self
     }
 
     #[must_use]
     pub fn should_stop(&mut self, nodes: usize) -> bool {
         if self.stopped() {
             return true;
-        } else if !matches!(self.limiter, SearchLimiterType::FixedNodes(_)) && nodes % 2048 != 0 {
+        } else if self.node_limit.is_some_and(|limit| nodes >= limit) {
+            self.stopped = true;
+            return true;
+        } else if nodes < self.next_clock_check {
             return false;
         }
 
-        let should_stop = match &self.limiter {
-            SearchLimiterType::Infinite => false,
-            SearchLimiterType::FixedNodes(node_limit) => nodes >= *node_limit,
-            SearchLimiterType::MoveTime(end_time) => Instant::now() >= *end_time,
-            SearchLimiterType::Tournament(time_manager) => time_manager.should_stop(),
-        };
+        self.next_clock_check = nodes + CLOCK_CHECK_INTERVAL;
+        self.check_clock(nodes)
+    }
+
+    // reads the clock regardless of how many nodes have passed
+    #[must_use]
+    pub fn check_clock(&mut self, nodes: usize) -> bool {
+        let should_stop = self.end_time.is_some_and(|end| Instant::now() >= end)
+            || self
+                .time_manager
+                .as_ref()
+                .is_some_and(|time_manager| time_manager.should_stop(nodes));
 
         if should_stop {
             self.stopped = true;
-            return true;
         }
 
-        false
+        should_stop
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    #[must_use]
+    pub fn is_infinite(&self) -> bool {
+        self.node_limit.is_none() && self.end_time.is_none() && self.time_manager.is_none()
+    }
+
+    // checked between iterations only, as stopping one midway wastes it
+    #[must_use]
+    pub fn should_stop_soft(
+        &mut self,
+        nodes: usize,
+        best_move_stability: u32,
+        best_move_nodes: f64,
+    ) -> bool {
+        let should_stop = self.time_manager.as_mut().is_some_and(|time_manager| {
+            time_manager.should_stop_soft(nodes, best_move_stability, best_move_nodes)
+        });
+
+        if should_stop {
+            self.stopped = true;
+        }
+
+        should_stop
+    }
+
+    // how long the time manager decided to spend, if it is in charge
+    #[must_use]
+    pub fn time_budget_ms(&self) -> Option<f64> {
+        self.time_manager
+            .as_ref()
+            .map(|time_manager| time_manager.soft_time * 1000.0)
+    }
+
+    // the longest the search may run on the clock, if anything limits it
+    #[must_use]
+    pub fn time_limit_ms(&self) -> Option<f64> {
+        let move_time = self
+            .end_time
+            .map(|end| end.saturating_duration_since(Instant::now()).as_secs_f64() * 1000.0);
+
+        let hard_time = self
+            .time_manager
+            .as_ref()
+            .filter(|time_manager| time_manager.nodes_per_ms.is_none())
+            .map(|time_manager| time_manager.max_time * 1000.0);
+
+        match (move_time, hard_time) {
+            (Some(move_time), Some(budget)) => Some(move_time.min(budget)),
+            (move_time, budget) => move_time.or(budget),
+        }
     }
 
     #[must_use]
@@ -99,40 +238,194 @@ impl SearchLimiter {
     }
 }
 
+// the soft limit is checked between iterations, and the hard limit
+// inside the search, where stopping throws away the unfinished iteration
 #[derive(Debug, Clone)]
 pub struct TimeManager {
     start: Instant,
+    soft_time: f64,
     max_time: f64,
+    // when the previous soft check ran, which is where the last iteration ended
+    last_iteration_end: f64,
+    // if set, time passes by nodes searched instead of on the clock,
+    // so results do not depend on how fast the machine is
+    nodes_per_ms: Option<u64>,
+    params: SearchParams,
 }
 
 impl TimeManager {
-    const DEFAULT_MOVES_TO_GO: u64 = 30;
-    const INCREMENT_MULTIPLIER: f64 = 0.5;
+    pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+    pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
 
     #[must_use]
-    pub fn new(our_time_ms: u64, our_inc_ms: u64, moves_to_go: u64) -> Self {
+    pub fn new(
+        our_time_ms: u64,
+        our_inc_ms: u64,
+        moves_to_go: u64,
+        move_overhead_ms: u64,
+        params: &SearchParams,
+    ) -> Self {
         let start = Instant::now();
 
         let divisor = if moves_to_go == 0 {
-            Self::DEFAULT_MOVES_TO_GO
+            params.tm_default_moves_to_go
         } else {
             moves_to_go
         } as f64;
 
         let our_time = our_time_ms as f64 / 1000.0;
         let our_inc = our_inc_ms as f64 / 1000.0;
+        let move_overhead = move_overhead_ms as f64 / 1000.0;
+
+        // the overhead is lost on every move, so it comes
+        // off both the allocation and what can be spent at most
+        let time = (our_time / divisor + our_inc * params.tm_increment_multiplier - move_overhead)
+            .min(our_time - move_overhead)
+            .max(0.0);
 
-        let time = our_time / divisor + our_inc * Self::INCREMENT_MULTIPLIER;
+        let max_time = (time * params.tm_hard_limit_scale)
+            .min((our_time - move_overhead) * params.tm_max_time_fraction)
+            .max(time);
 
         Self {
             start,
-            max_time: time,
+            soft_time: time,
+            max_time,
+            last_iteration_end: 0.0,
+            nodes_per_ms: None,
+            params: *params,
         }
     }
 
+    // the times passed to new are read as node counts divided by the rate
+    #[must_use]
+    pub fn with_nodes_time(mut self, nodes_per_ms: u64) -> Self {
+        self.nodes_per_ms = Some(nodes_per_ms.max(1));
+        self
+    }
+
+    // in seconds
+    #[must_use]
+    fn elapsed(&self, nodes: usize) -> f64 {
+        match self.nodes_per_ms {
+            Some(rate) => nodes as f64 / rate as f64 / 1000.0,
+            None => self.start.elapsed().as_secs_f64(),
+        }
+    }
+
+    #[must_use]
+    pub fn should_stop(&self, nodes: usize) -> bool {
+        self.elapsed(nodes) >= self.max_time
+    }
+
+    // best_move_nodes is the fraction of the last iteration's root nodes spent on the best move
+    #[must_use]
+    fn soft_limit(&self, best_move_stability: u32, best_move_nodes: f64) -> f64 {
+        let scales = &self.params.tm_stability_scales;
+        let idx = (best_move_stability as usize).min(scales.len() - 1);
+        let node_scale = (self.params.tm_node_fraction_base - best_move_nodes.clamp(0.0, 1.0))
+            * self.params.tm_node_fraction_scale;
+
+        (self.soft_time * scales[idx] * node_scale).min(self.max_time)
+    }
+
+    // also stops once the next iteration could not finish before the hard limit anyway
     #[must_use]
-    pub fn should_stop(&self) -> bool {
-        let total_time = self.start.elapsed().as_secs_f64();
-        total_time >= self.max_time
+    pub fn should_stop_soft(
+        &mut self,
+        nodes: usize,
+        best_move_stability: u32,
+        best_move_nodes: f64,
+    ) -> bool {
+        let elapsed = self.elapsed(nodes);
+
+        let iteration_time = elapsed - self.last_iteration_end;
+        self.last_iteration_end = elapsed;
+
+        elapsed >= self.soft_limit(best_move_stability, best_move_nodes)
+            || elapsed + iteration_time * self.params.tm_iteration_growth >= self.max_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_limits_stop_on_first() {
+        let mut limiter = SearchLimiter::fixed_nodes(5000)
+            .with_move_time(60_000)
+            .with_nodes(3000);
+
+        assert!(!limiter.is_infinite());
+        assert!(!limiter.should_stop(2999));
+        assert!(limiter.should_stop(3000));
+
+        let mut limiter = SearchLimiter::infinite()
+            .with_nodes(1_000_000)
+            .with_move_time(0);
+
+        assert!(limiter.should_stop(2048));
+    }
+
+    #[test]
+    fn clock_checked_after_skipped_cadence() {
+        let mut limiter = SearchLimiter::infinite().with_move_time(0);
+
+        // the search does not check at every node, so node counts
+        // that are never a multiple of the interval still read the clock
+        assert!(limiter.should_stop(3001));
+
+        let mut limiter = SearchLimiter::infinite().with_move_time(60_000);
+        assert!(!limiter.should_stop(0));
+        assert!(!limiter.should_stop(2047));
+        assert!(!limiter.check_clock(0));
+    }
+
+    #[test]
+    fn stable_best_move_shortens_allocation() {
+        let params = SearchParams::default();
+        let time_manager = TimeManager::new(60_000, 0, 0, 0, &params);
+
+        assert!(time_manager.soft_limit(0, 0.5) > time_manager.soft_limit(2, 0.5));
+        assert!(time_manager.soft_limit(100, 0.5) < time_manager.soft_limit(3, 0.5));
+        assert!(time_manager.soft_limit(0, 0.0) <= time_manager.max_time);
+        assert!(time_manager.max_time <= 60.0 * params.tm_max_time_fraction);
+    }
+
+    #[test]
+    fn dominant_best_move_shortens_allocation() {
+        let params = SearchParams::default();
+        let time_manager = TimeManager::new(60_000, 0, 0, 0, &params);
+
+        assert!(time_manager.soft_limit(2, 0.9) < time_manager.soft_time);
+        assert!(time_manager.soft_limit(2, 0.2) > time_manager.soft_time);
+        assert!(time_manager.soft_limit(2, 0.9) < time_manager.soft_limit(2, 0.5));
+    }
+
+    #[test]
+    fn iteration_that_cannot_finish_is_skipped() {
+        let params = SearchParams::default();
+        let mut time_manager = TimeManager::new(60_000, 0, 0, 0, &params);
+
+        // a single iteration has taken most of the hard limit, without reaching the soft one
+        let elapsed = time_manager.max_time * 0.6;
+        assert!(elapsed < time_manager.soft_limit(0, 0.0));
+
+        time_manager.start -= Duration::from_secs_f64(elapsed);
+        assert!(time_manager.should_stop_soft(0, 0, 0.0));
+    }
+
+    #[test]
+    fn nodes_time_counts_nodes() {
+        let params = SearchParams::default();
+        let time_manager = TimeManager::new(10_000, 0, 1, 0, &params).with_nodes_time(100);
+
+        // a whole second of node time is 100k nodes, however long they take
+        assert!(!time_manager.should_stop(0));
+        assert!(time_manager.should_stop(1_000_000));
+
+        let limiter = SearchLimiter::infinite().with_time_manager(time_manager);
+        assert!(limiter.time_limit_ms().is_none());
     }
 }
@@ -23,35 +23,101 @@
 #![allow(clippy::cast_sign_loss)]
 #![allow(clippy::if_not_else)]
 #![allow(clippy::inline_always)]
-#![allow(clippy::match_on_vec_items)]
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::similar_names)]
 #![allow(clippy::too_many_lines)]
 #![allow(clippy::uninlined_format_args)]
 #![allow(clippy::wildcard_imports)]
 
-use crate::bench::{run_bench, DEFAULT_BENCH_DEPTH};
+use crate::analyze::{run_analyze_fens, run_puzzles, AnalysisLimits};
+use crate::bench::{run_bench, run_bench_smp, DEFAULT_BENCH_DEPTH, DEFAULT_SMP_BENCH_MAX_THREADS};
+use crate::convertnet::run_convertnet;
+use crate::core::MAX_DEPTH;
+use crate::limit::TimeManager;
+use crate::nnue::NetworkLayout;
+use crate::quantcheck::run_quantcheck;
 use crate::search::Searcher;
+use crate::simulate::{run_tc_simulation, TimeControl};
 use std::env;
 use std::process::exit;
 
+mod analyze;
 mod ataxx_move;
 mod attacks;
 mod bench;
 mod bitboard;
+mod convertnet;
 mod core;
+mod correction;
 mod datagen;
+mod error;
 mod eval;
+mod evalcache;
+mod evalcheck;
+mod evaltrace;
+mod explain;
+mod fuzz;
+mod genfens;
 mod hash;
+mod history;
+mod iolog;
 mod limit;
 mod movegen;
+mod movepick;
 mod nnue;
+mod numa;
+mod params;
 mod perft;
 mod position;
+mod pretty;
+mod quantcheck;
 mod search;
+mod selfplay;
+mod simulate;
+mod skill;
+mod solver;
+mod stats;
+mod trace;
 mod ttable;
 mod uai;
 mod util;
+mod watchdog;
+mod wdl;
+
+fn exit_on_error(result: error::Result<()>) {
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
+
+fn parse_analysis_limits(args: &[String]) -> AnalysisLimits {
+    let mut limits = AnalysisLimits::default();
+
+    for pair in args.chunks(2) {
+        let [name, value] = pair else {
+            eprintln!("missing value for {}", pair[0]);
+            exit(1);
+        };
+
+        let Ok(value) = value.parse::<usize>() else {
+            eprintln!("invalid {} {}", name, value);
+            exit(1);
+        };
+
+        match name.as_str() {
+            "depth" => limits.depth = value.min(MAX_DEPTH as usize) as i32,
+            "nodes" => limits.nodes = Some(value),
+            "threads" => limits.threads = value,
+            _ => {
+                eprintln!("unknown limit {}", name);
+                exit(1);
+            }
+        }
+    }
+
+    limits
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -60,13 +126,150 @@ fn main() {
         match args[1].as_str() {
             "bench" => {
                 let mut searcher = Searcher::new();
-                run_bench(&mut searcher, DEFAULT_BENCH_DEPTH);
+                if args.get(2).is_some_and(|arg| arg == "smp") {
+                    exit_on_error(run_bench_smp(
+                        &mut searcher,
+                        DEFAULT_BENCH_DEPTH,
+                        DEFAULT_SMP_BENCH_MAX_THREADS,
+                    ));
+                } else {
+                    exit_on_error(run_bench(&mut searcher, DEFAULT_BENCH_DEPTH));
+                }
+                return;
+            }
+            "analyzefens" | "puzzles" => {
+                if args.len() < 4 {
+                    eprintln!(
+                        "usage: {} {} <fen file> <output file> [depth <depth>] [nodes <nodes>] [threads <threads>]",
+                        args[0], args[1]
+                    );
+                    exit(1);
+                }
+
+                let limits = parse_analysis_limits(&args[4..]);
+
+                exit_on_error(if args[1] == "puzzles" {
+                    run_puzzles(&args[2], &args[3], &limits)
+                } else {
+                    run_analyze_fens(&args[2], &args[3], &limits)
+                });
+                return;
+            }
+            "quantcheck" => {
+                if args.len() < 3 {
+                    eprintln!("usage: {} quantcheck <float net> [quantised net]", args[0]);
+                    exit(1);
+                }
+
+                exit_on_error(run_quantcheck(&args[2], args.get(3).map(String::as_str)));
+                return;
+            }
+            "convertnet" => {
+                if args.len() < 4 {
+                    eprintln!(
+                        "usage: {} convertnet <bare net> <output file> [output buckets] [activation] [feature set] [hidden size] [global inputs]",
+                        args[0]
+                    );
+                    exit(1);
+                }
+
+                let parse_arg = |idx: usize, default: u32| {
+                    args.get(idx).map_or(default, |arg| {
+                        if let Ok(value) = arg.parse::<u32>() {
+                            value
+                        } else {
+                            eprintln!("invalid value {}", arg);
+                            eprintln!(
+                                "usage: {} convertnet <bare net> <output file> [output buckets] [activation] [feature set] [hidden size] [global inputs]",
+                                args[0]
+                            );
+                            exit(1);
+                        }
+                    })
+                };
+
+                let defaults = NetworkLayout::default();
+                let layout = NetworkLayout {
+                    output_buckets: parse_arg(4, defaults.output_buckets),
+                    activation: parse_arg(5, defaults.activation),
+                    feature_set: parse_arg(6, defaults.feature_set),
+                    hidden_size: parse_arg(7, defaults.hidden_size),
+                    global_inputs: parse_arg(8, defaults.global_inputs),
+                };
+
+                exit_on_error(run_convertnet(&args[2], &args[3], layout));
+                return;
+            }
+            "simulate-tc" => {
+                if args.len() < 5 {
+                    eprintln!(
+                        "usage: {} simulate-tc <games file> <time ms> <inc ms> [moves to go] [move overhead ms]",
+                        args[0]
+                    );
+                    exit(1);
+                }
+
+                let parse_arg = |idx: usize, default: u64| {
+                    args.get(idx).map_or(default, |arg| {
+                        if let Ok(value) = arg.parse::<u64>() {
+                            value
+                        } else {
+                            eprintln!("invalid value {}", arg);
+                            eprintln!(
+                                "usage: {} simulate-tc <games file> <time ms> <inc ms> [moves to go] [move overhead ms]",
+                                args[0]
+                            );
+                            exit(1);
+                        }
+                    })
+                };
+
+                let tc = TimeControl {
+                    time_ms: parse_arg(3, 0),
+                    inc_ms: parse_arg(4, 0),
+                    moves_to_go: parse_arg(5, 0),
+                    move_overhead_ms: parse_arg(6, TimeManager::DEFAULT_MOVE_OVERHEAD_MS),
+                };
+
+                let mut searcher = Searcher::new();
+                exit_on_error(run_tc_simulation(&mut searcher, &args[2], &tc));
+                return;
+            }
+            "datagen-replay" => {
+                if args.len() < 4 {
+                    eprintln!(
+                        "usage: {} datagen-replay <base seed> <stream> [second best move %]",
+                        args[0]
+                    );
+                    exit(1);
+                }
+
+                let parse_arg = |idx: usize, default: u64| {
+                    args.get(idx).map_or(default, |arg| {
+                        if let Ok(value) = arg.parse::<u64>() {
+                            value
+                        } else {
+                            eprintln!("invalid value {}", arg);
+                            eprintln!(
+                                "usage: {} datagen-replay <base seed> <stream> [second best move %]",
+                                args[0]
+                            );
+                            exit(1);
+                        }
+                    })
+                };
+
+                exit_on_error(datagen::replay(
+                    parse_arg(2, 0),
+                    parse_arg(3, 0),
+                    parse_arg(4, 0).min(100) as u32,
+                ));
                 return;
             }
             "datagen" => {
                 if args.len() < 4 {
                     eprintln!(
-                        "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread]",
+                        "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread] [second best move %]",
                         args[0]
                     );
                     exit(1);
@@ -78,7 +281,7 @@ fn main() {
                     _ => {
                         eprintln!("invalid output format {}", args[3]);
                         eprintln!(
-                            "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread]",
+                            "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread] [second best move %]",
                             args[0]
                         );
                         exit(1);
@@ -93,7 +296,7 @@ fn main() {
                         } else {
                             eprintln!("invalid number of threads {}", arg);
                             eprintln!(
-                                "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread]",
+                                "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread] [second best move %]",
                                 args[0]
                             );
                             exit(1);
@@ -108,19 +311,38 @@ fn main() {
                         } else {
                             eprintln!("invalid number of games {}", arg);
                             eprintln!(
-                                "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread]",
+                                "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread] [second best move %]",
                                 args[0]
                             );
                             exit(1);
                         }
                     });
 
-                datagen::run(args[3].as_str(), write_fens, threads, games);
+                let second_best_percent = args.get(6).map_or(0, |arg| {
+                    if let Ok(percent) = arg.parse::<u32>() {
+                        percent.min(100)
+                    } else {
+                        eprintln!("invalid second best move percentage {}", arg);
+                        eprintln!(
+                            "usage: {} datagen <fens|bulletformat> <path> [threads] [game limit per thread] [second best move %]",
+                            args[0]
+                        );
+                        exit(1);
+                    }
+                });
+
+                exit_on_error(datagen::run(
+                    args[3].as_str(),
+                    write_fens,
+                    threads,
+                    games,
+                    second_best_percent,
+                ));
                 return;
             }
             _ => {}
         }
     }
 
-    uai::run();
+    uai::run(&args[1..]);
 }
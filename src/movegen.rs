@@ -17,13 +17,29 @@
  */
 
 use crate::ataxx_move::AtaxxMove;
-use crate::attacks::DOUBLES;
+use crate::attacks::{DOUBLES, SINGLES};
+use crate::bitboard::Bitboard;
 use crate::position::Position;
 
 pub type MoveList = arrayvec::ArrayVec<AtaxxMove, 200>;
 pub type ScoredMoveList = arrayvec::ArrayVec<(AtaxxMove, i32), 200>;
 
-fn generate_moves<Callback>(pos: &Position, mut callback: Callback)
+// squares that the opponent can never move to, however the game continues.
+// our pieces count as reachable, as they could be captured on the way
+#[must_use]
+fn sealed_region(pos: &Position) -> Bitboard {
+    // nothing can be sealed off without gaps to wall it in
+    if pos.gaps().is_empty() {
+        return Bitboard::EMPTY;
+    }
+
+    let theirs = pos.color_occupancy(pos.side_to_move().flip());
+    let reachable = theirs.flood_fill_with_jumps(Bitboard::ALL & !pos.gaps());
+
+    !reachable & !pos.gaps()
+}
+
+fn generate_moves<const PRUNE_DEAD_ZONES: bool, Callback>(pos: &Position, mut callback: Callback)
 where
     Callback: FnMut(AtaxxMove),
 {
@@ -43,11 +59,29 @@ where
         must_pass = false;
     }
 
+    // a double within a sealed-off region that a single could also reach is
+    // strictly worse - it flips nothing extra and vacates a square only we can use.
+    // from outside, the vacated square may be one the opponent can take
+    let sealed = if PRUNE_DEAD_ZONES {
+        sealed_region(pos)
+    } else {
+        Bitboard::EMPTY
+    };
+    let pointless = sealed & singles;
+
     for from in ours {
         let attacks = DOUBLES[from.bit_idx()] & empty;
+        let skipped = if sealed.get(from) {
+            pointless
+        } else {
+            Bitboard::EMPTY
+        };
+
         for to in attacks {
-            callback(AtaxxMove::Double(from, to));
             must_pass = false;
+            if !skipped.get(to) {
+                callback(AtaxxMove::Double(from, to));
+            }
         }
     }
 
@@ -57,9 +91,192 @@ where
 }
 
 pub fn fill_move_list(moves: &mut MoveList, pos: &Position) {
-    generate_moves(pos, |m| moves.push(m));
+    generate_moves::<false, _>(pos, |m| moves.push(m));
+}
+
+// immediate material gain, as a double leaves its source square behind
+#[must_use]
+pub fn move_gain(pos: &Position, mv: AtaxxMove) -> i32 {
+    let vacated = i32::from(matches!(mv, AtaxxMove::Double(_, _)));
+    pos.flip_count(mv) as i32 - vacated
 }
 
+// for search, leaving out moves that can never be better than another one generated.
+// moves are scored by their gain
 pub fn fill_scored_move_list(moves: &mut ScoredMoveList, pos: &Position) {
-    generate_moves(pos, |m| moves.push((m, 0)));
+    generate_moves::<true, _>(pos, |m| moves.push((m, move_gain(pos, m))));
+}
+
+// captures flipping at least this many stones, scored by their flips. a double
+// is only generated when no single reaches its destination, as it would flip the
+// same stones and leave a square behind
+pub fn fill_noisy_move_list(moves: &mut ScoredMoveList, pos: &Position, min_flips: u32) {
+    if pos.game_over() {
+        return;
+    }
+
+    let ours = pos.color_occupancy(pos.side_to_move());
+    let theirs = pos.color_occupancy(pos.side_to_move().flip());
+    let empty = pos.empty_squares();
+
+    let singles = ours.expand() & empty;
+    let reachable = ours.expand().expand() & empty;
+
+    for to in reachable & theirs.expand() {
+        let flips = (SINGLES[to.bit_idx()] & theirs).popcount();
+        if flips < min_flips {
+            continue;
+        }
+
+        if singles.get(to) {
+            moves.push((AtaxxMove::Single(to), flips as i32));
+        } else {
+            for from in DOUBLES[to.bit_idx()] & ours {
+                moves.push((AtaxxMove::Double(from, to), flips as i32));
+            }
+        }
+    }
+}
+
+// whether a pass is the only move, without generating anything
+#[must_use]
+pub fn must_pass(pos: &Position) -> bool {
+    let ours = pos.color_occupancy(pos.side_to_move());
+    !pos.game_over() && (ours.expand().expand() & pos.empty_squares()).is_empty()
+}
+
+// for moves from untrusted sources like the tt, without generating the full list
+#[must_use]
+pub fn is_legal(pos: &Position, mv: AtaxxMove) -> bool {
+    if pos.game_over() {
+        return false;
+    }
+
+    let ours = pos.color_occupancy(pos.side_to_move());
+    let empty = pos.empty_squares();
+
+    match mv {
+        AtaxxMove::Single(to) => empty.get(to) && !(SINGLES[to.bit_idx()] & ours).is_empty(),
+        AtaxxMove::Double(from, to) => {
+            ours.get(from) && empty.get(to) && DOUBLES[from.bit_idx()].get(to)
+        }
+        AtaxxMove::Null => must_pass(pos),
+        AtaxxMove::None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::Square;
+    use crate::movegen::{
+        fill_move_list, fill_noisy_move_list, fill_scored_move_list, is_legal, move_gain,
+        must_pass, MoveList, ScoredMoveList,
+    };
+    use crate::position::Position;
+
+    #[test]
+    fn dead_zone_doubles_pruned() {
+        // red's corner is walled off by two files and ranks of gaps
+        let pos = Position::from_fen("x2--2/1x1--2/-----2/-----2/7/7/6o x 0 1").unwrap();
+
+        let mut all = MoveList::new();
+        fill_move_list(&mut all, &pos);
+
+        let mut pruned = ScoredMoveList::new();
+        fill_scored_move_list(&mut pruned, &pos);
+
+        assert_eq!(all.len(), 6);
+        assert_eq!(pruned.len(), 4);
+        assert!(pruned
+            .iter()
+            .all(|(mv, _)| matches!(mv, AtaxxMove::Single(_))));
+
+        // a stone outside the walls keeps its doubles, while the corner's are still pruned
+        let pos = Position::from_fen("x2--2/1x1--2/-----2/-----2/7/7/x5o x 0 1").unwrap();
+
+        let mut all = MoveList::new();
+        fill_move_list(&mut all, &pos);
+
+        let mut pruned = ScoredMoveList::new();
+        fill_scored_move_list(&mut pruned, &pos);
+
+        let outside_doubles = |mv: &AtaxxMove| matches!(mv, AtaxxMove::Double(Square::A1, _));
+
+        assert_eq!(all.len() - pruned.len(), 2);
+        assert_eq!(
+            pruned.iter().filter(|(mv, _)| outside_doubles(mv)).count(),
+            all.iter().filter(|mv| outside_doubles(mv)).count()
+        );
+        assert!(pruned.iter().any(|(mv, _)| outside_doubles(mv)));
+    }
+
+    #[test]
+    fn reachable_doubles_kept() {
+        let pos = Position::startpos();
+
+        let mut all = MoveList::new();
+        fill_move_list(&mut all, &pos);
+
+        let mut pruned = ScoredMoveList::new();
+        fill_scored_move_list(&mut pruned, &pos);
+
+        assert_eq!(all.len(), pruned.len());
+    }
+
+    #[test]
+    fn legality_matches_movegen() {
+        let pos = Position::startpos();
+
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, &pos);
+
+        assert!(moves.iter().all(|&mv| is_legal(&pos, mv)));
+
+        assert!(!is_legal(&pos, AtaxxMove::Null));
+        assert!(!is_legal(&pos, AtaxxMove::None));
+        assert!(!is_legal(&pos, AtaxxMove::Single(Square::D4)));
+        assert!(!is_legal(&pos, AtaxxMove::Double(Square::G7, Square::E5)));
+        assert!(!is_legal(&pos, AtaxxMove::Double(Square::A7, Square::A4)));
+    }
+
+    #[test]
+    fn scored_by_gain() {
+        let pos = Position::from_fen("7/7/2ooo2/7/2x4/7/7 x 0 1").unwrap();
+
+        let mut moves = ScoredMoveList::new();
+        fill_scored_move_list(&mut moves, &pos);
+
+        assert!(moves.contains(&(AtaxxMove::Single(Square::D4), 3)));
+        assert!(moves.contains(&(AtaxxMove::Double(Square::C3, Square::E4), 1)));
+        assert!(moves.contains(&(AtaxxMove::Double(Square::C3, Square::A1), -1)));
+        assert!(moves.iter().all(|&(mv, gain)| gain == move_gain(&pos, mv)));
+    }
+
+    #[test]
+    fn noisy_moves_flip_enough() {
+        let pos = Position::from_fen("7/7/2ooo2/7/2x4/7/7 x 0 1").unwrap();
+
+        let mut noisy = ScoredMoveList::new();
+        fill_noisy_move_list(&mut noisy, &pos, 2);
+
+        assert!(noisy.contains(&(AtaxxMove::Single(Square::D4), 3)));
+        assert!(noisy.contains(&(AtaxxMove::Double(Square::C3, Square::E4), 2)));
+        assert!(noisy.iter().all(|&(mv, flips)| {
+            is_legal(&pos, mv) && pos.flip_count(mv) == flips as u32 && flips >= 2
+        }));
+        assert!(!noisy
+            .iter()
+            .any(|&(mv, _)| matches!(mv, AtaxxMove::Double(_, to) if to == Square::D4)));
+    }
+
+    #[test]
+    fn pass_only_legal_when_forced() {
+        // red's only stone is walled in by gaps
+        let pos = Position::from_fen("x--4/---4/---4/7/7/7/6o x 0 1").unwrap();
+
+        assert!(must_pass(&pos));
+        assert!(is_legal(&pos, AtaxxMove::Null));
+        assert!(!must_pass(&Position::startpos()));
+    }
 }
@@ -0,0 +1,275 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::history::{HistoryTable, MAX_HISTORY};
+use crate::movegen::{fill_scored_move_list, is_legal, move_gain, ScoredMoveList};
+use crate::position::Position;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Stage {
+    TtMove,
+    Generate,
+    Remaining,
+}
+
+// gain from movegen is the base ordering. each stone of gain is worth about
+// as much as a few cutoffs of history, so moves it has learned can still overtake
+const GAIN_SCALE: i32 = MAX_HISTORY / 16;
+
+// hands out the tt move before anything is generated, so a tt
+// cutoff costs no movegen, then the rest best-first by gain and history
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: AtaxxMove,
+    // ordered right behind the tt move
+    hint: AtaxxMove,
+    // and these behind the hint
+    killers: [AtaxxMove; 2],
+    tt_move_played: bool,
+    moves: ScoredMoveList,
+    idx: usize,
+}
+
+impl MovePicker {
+    #[must_use]
+    pub fn new(tt_move: AtaxxMove, hint: AtaxxMove, killers: [AtaxxMove; 2]) -> Self {
+        Self {
+            stage: Stage::TtMove,
+            tt_move,
+            hint,
+            killers,
+            tt_move_played: false,
+            moves: ScoredMoveList::new(),
+            idx: 0,
+        }
+    }
+
+    // picks only from the given moves, which are assumed legal
+    #[must_use]
+    pub fn with_moves(moves: &[AtaxxMove], tt_move: AtaxxMove, hint: AtaxxMove) -> Self {
+        Self {
+            stage: Stage::Generate,
+            tt_move,
+            hint,
+            killers: [AtaxxMove::None; 2],
+            tt_move_played: false,
+            moves: moves.iter().map(|&mv| (mv, 0)).collect(),
+            idx: 0,
+        }
+    }
+
+    pub fn next(&mut self, pos: &Position, history: &HistoryTable) -> Option<AtaxxMove> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::Generate;
+
+                    // a pass only ever comes from movegen, where it is the sole move
+                    if matches!(self.tt_move, AtaxxMove::Single(_) | AtaxxMove::Double(_, _))
+                        && is_legal(pos, self.tt_move)
+                    {
+                        self.tt_move_played = true;
+                        return Some(self.tt_move);
+                    }
+                }
+                Stage::Generate => {
+                    self.stage = Stage::Remaining;
+
+                    // already filled when picking from given moves, but not yet scored
+                    if self.moves.is_empty() {
+                        fill_scored_move_list(&mut self.moves, pos);
+                    } else {
+                        for (mv, gain) in &mut self.moves {
+                            *gain = move_gain(pos, *mv);
+                        }
+                    }
+
+                    self.score(pos, history);
+                }
+                Stage::Remaining => {
+                    let &(mv, _) = self.moves.get(self.idx)?;
+                    self.idx += 1;
+
+                    if !(self.tt_move_played && mv == self.tt_move) {
+                        return Some(mv);
+                    }
+                }
+            }
+        }
+    }
+
+    fn score(&mut self, pos: &Position, history: &HistoryTable) {
+        let stm = pos.side_to_move();
+
+        for (mv, score) in &mut self.moves {
+            *score = if *mv == self.tt_move {
+                i32::MAX
+            } else if *mv == self.hint {
+                i32::MAX - 1
+            } else if *mv == self.killers[0] {
+                i32::MAX - 2
+            } else if *mv == self.killers[1] {
+                i32::MAX - 3
+            } else {
+                *score * GAIN_SCALE + history.get(stm, *mv, pos.flip_count(*mv))
+            };
+        }
+
+        self.moves
+            .sort_unstable_by(|(_, a_score), (_, b_score)| b_score.cmp(a_score));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::{Color, Square};
+    use crate::history::{history_bonus, HistoryTable};
+    use crate::movegen::{fill_scored_move_list, ScoredMoveList};
+    use crate::movepick::MovePicker;
+    use crate::position::Position;
+
+    fn pick_all(picker: &mut MovePicker, pos: &Position, history: &HistoryTable) -> Vec<AtaxxMove> {
+        std::iter::from_fn(|| picker.next(pos, history)).collect()
+    }
+
+    #[test]
+    fn every_move_picked_once() {
+        let pos = Position::startpos();
+        let history = HistoryTable::default();
+
+        let mut legal = ScoredMoveList::new();
+        fill_scored_move_list(&mut legal, &pos);
+
+        let tt_move = AtaxxMove::Double(Square::A7, Square::C5);
+        let picked = pick_all(
+            &mut MovePicker::new(tt_move, AtaxxMove::None, [AtaxxMove::None; 2]),
+            &pos,
+            &history,
+        );
+
+        assert_eq!(picked[0], tt_move);
+        assert_eq!(picked.len(), legal.len());
+        assert!(legal.iter().all(|(mv, _)| picked.contains(mv)));
+    }
+
+    #[test]
+    fn illegal_tt_move_skipped() {
+        let pos = Position::startpos();
+        let history = HistoryTable::default();
+
+        let mut legal = ScoredMoveList::new();
+        fill_scored_move_list(&mut legal, &pos);
+
+        let tt_move = AtaxxMove::Single(Square::D4);
+        let picked = pick_all(
+            &mut MovePicker::new(tt_move, AtaxxMove::None, [AtaxxMove::None; 2]),
+            &pos,
+            &history,
+        );
+
+        assert!(!picked.contains(&tt_move));
+        assert_eq!(picked.len(), legal.len());
+    }
+
+    #[test]
+    fn hint_then_history_order() {
+        let pos = Position::startpos();
+        let mut history = HistoryTable::default();
+
+        let good = AtaxxMove::Single(Square::B6);
+        history.update(Color::RED, good, 0, history_bonus(10));
+
+        let hint = AtaxxMove::Double(Square::G1, Square::E3);
+        let picked = pick_all(
+            &mut MovePicker::new(AtaxxMove::None, hint, [AtaxxMove::None; 2]),
+            &pos,
+            &history,
+        );
+
+        assert_eq!(picked[0], hint);
+        assert_eq!(picked[1], good);
+    }
+
+    #[test]
+    fn killers_after_hint() {
+        let pos = Position::startpos();
+        let mut history = HistoryTable::default();
+
+        history.update(
+            Color::RED,
+            AtaxxMove::Single(Square::B6),
+            0,
+            history_bonus(10),
+        );
+
+        let hint = AtaxxMove::Double(Square::G1, Square::E3);
+        let killers = [
+            AtaxxMove::Double(Square::A7, Square::C5),
+            AtaxxMove::Single(Square::F2),
+        ];
+        let picked = pick_all(
+            &mut MovePicker::new(AtaxxMove::None, hint, killers),
+            &pos,
+            &history,
+        );
+
+        assert_eq!(picked[..3], [hint, killers[0], killers[1]]);
+    }
+
+    #[test]
+    fn gain_before_history() {
+        let pos = Position::from_fen("7/7/2ooo2/7/2x4/7/7 x 0 1").unwrap();
+        let mut history = HistoryTable::default();
+
+        let quiet = AtaxxMove::Single(Square::B2);
+        history.update(Color::RED, quiet, 0, history_bonus(3));
+
+        let picked = pick_all(
+            &mut MovePicker::new(AtaxxMove::None, AtaxxMove::None, [AtaxxMove::None; 2]),
+            &pos,
+            &history,
+        );
+
+        assert_eq!(picked[0], AtaxxMove::Single(Square::D4));
+        assert!(
+            picked.iter().position(|&mv| mv == quiet)
+                > picked
+                    .iter()
+                    .position(|&mv| mv == AtaxxMove::Single(Square::C4))
+        );
+    }
+
+    #[test]
+    fn given_moves_only() {
+        let pos = Position::startpos();
+        let history = HistoryTable::default();
+
+        let moves = [
+            AtaxxMove::Single(Square::B6),
+            AtaxxMove::Double(Square::A7, Square::C5),
+        ];
+        let mut picker = MovePicker::with_moves(&moves, moves[1], AtaxxMove::None);
+
+        assert_eq!(
+            pick_all(&mut picker, &pos, &history),
+            vec![moves[1], moves[0]]
+        );
+    }
+}
@@ -18,6 +18,7 @@
 
 #![allow(dead_code)]
 
+use crate::nnue::network::{L1_Q, L1_SIZE};
 use crate::util::simd;
 
 pub struct ReLU;
@@ -37,14 +38,76 @@ impl<const MAX: i16> ClippedReLU<MAX> {
     }
 }
 
-pub struct SquaredClippedReLU<const MAX: i16>;
-impl<const MAX: i16> SquaredClippedReLU<MAX> {
-    const MAX_OVERFLOW: () = assert!((MAX as i32) * (MAX as i32) <= i16::MAX as i32);
+type Clip = ClippedReLU<{ L1_Q as i16 }>;
 
+// what the accumulators go through before the output layer, chosen by each network.
+// squares and pairwise products do not fit in an i16, so those multiply one side by
+// the weight first, then widen while multiplying by the other. this needs weights
+// below 128 in magnitude, and leaves the sum with an extra factor of L1_Q
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Activation {
+    ClippedReLU,
+    SquaredClippedReLU,
+    // each value in the first half of an accumulator times its partner in the second half
+    PairwiseClippedReLU,
+}
+
+impl Activation {
+    #[must_use]
+    pub const fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::ClippedReLU),
+            1 => Some(Self::SquaredClippedReLU),
+            2 => Some(Self::PairwiseClippedReLU),
+            _ => None,
+        }
+    }
+
+    // output layer inputs per perspective
+    #[must_use]
+    pub const fn outputs(self) -> usize {
+        match self {
+            Self::PairwiseClippedReLU => L1_SIZE / 2,
+            _ => L1_SIZE,
+        }
+    }
+
+    // the factor of L1_Q the output layer's sum carries on top of L1_Q * OUTPUT_Q
+    #[must_use]
+    pub const fn extra_scale(self) -> i32 {
+        match self {
+            Self::ClippedReLU => 1,
+            _ => L1_Q,
+        }
+    }
+
+    // one perspective's accumulator activated and multiplied by its output weights
     #[inline(always)]
-    pub fn activate(v: simd::Register16) -> simd::Register16 {
-        let max = simd::set1_i16(MAX);
-        let clipped = simd::clamp_i16(v, simd::zero16(), max);
-        simd::mul_i16(clipped, clipped)
+    pub fn dot(
+        self,
+        values: impl Fn(usize) -> simd::Register16,
+        weights: impl Fn(usize) -> simd::Register16,
+    ) -> simd::Register32 {
+        let mut sum = simd::zero32();
+
+        for i in (0..self.outputs()).step_by(simd::CHUNK_SIZE_I16) {
+            let activated = Clip::activate(values(i));
+            let weights = weights(i);
+
+            let product = match self {
+                Self::ClippedReLU => simd::mul_add_adj_i16(activated, weights),
+                Self::SquaredClippedReLU => {
+                    simd::mul_add_adj_i16(simd::mul_i16(activated, weights), activated)
+                }
+                Self::PairwiseClippedReLU => {
+                    let partner = Clip::activate(values(i + L1_SIZE / 2));
+                    simd::mul_add_adj_i16(simd::mul_i16(activated, weights), partner)
+                }
+            };
+
+            sum = simd::add_i32(product, sum);
+        }
+
+        sum
     }
 }
@@ -0,0 +1,193 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Square;
+use crate::hash::{canonical_orientation, Symmetry};
+use crate::position::Position;
+
+// how each network maps squares to its inputs
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FeatureSet {
+    Plain,
+    // squares are taken through the board's canonical orientation first, so mirror
+    // images of a position share their features and a net learns from all eight at once
+    SymmetryCanonical,
+}
+
+impl FeatureSet {
+    #[must_use]
+    pub const fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::Plain),
+            1 => Some(Self::SymmetryCanonical),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn orientation(self, pos: &Position) -> Symmetry {
+        match self {
+            Self::Plain => Symmetry::IDENTITY,
+            Self::SymmetryCanonical => {
+                canonical_orientation(pos.red_occupancy(), pos.blue_occupancy(), pos.gaps())
+            }
+        }
+    }
+}
+
+// whole-board values a net can take as inputs on top of the squares, each one-hot
+// over buckets of the value. their features come after the squares', in this order
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GlobalInput {
+    // our stones minus theirs, clamped
+    StoneDifference,
+    // empty squares, in equal ranges
+    Empties,
+    // the halfmove clock in tens, the last bucket taking everything above
+    Halfmoves,
+}
+
+const STONE_DIFFERENCE_LIMIT: i32 = 8;
+const EMPTIES_BUCKETS: usize = 8;
+const HALFMOVE_BUCKETS: usize = 10;
+
+impl GlobalInput {
+    pub const ALL: [Self; 3] = [Self::StoneDifference, Self::Empties, Self::Halfmoves];
+
+    #[must_use]
+    pub const fn buckets(self) -> usize {
+        match self {
+            Self::StoneDifference => 2 * STONE_DIFFERENCE_LIMIT as usize + 1,
+            Self::Empties => EMPTIES_BUCKETS,
+            Self::Halfmoves => HALFMOVE_BUCKETS,
+        }
+    }
+
+    // where this input's features start among all the global ones
+    #[must_use]
+    pub const fn offset(self) -> usize {
+        let mut offset = 0;
+
+        let mut i = 0;
+        while i < self as usize {
+            offset += Self::ALL[i].buckets();
+            i += 1;
+        }
+
+        offset
+    }
+
+    // the bucket red's and blue's accumulators see
+    #[must_use]
+    pub fn bucket(self, pos: &Position) -> [usize; 2] {
+        match self {
+            Self::StoneDifference => {
+                let red = pos.red_occupancy().popcount() as i32;
+                let blue = pos.blue_occupancy().popcount() as i32;
+
+                let bucket = |ours: i32, theirs: i32| {
+                    ((ours - theirs).clamp(-STONE_DIFFERENCE_LIMIT, STONE_DIFFERENCE_LIMIT)
+                        + STONE_DIFFERENCE_LIMIT) as usize
+                };
+
+                [bucket(red, blue), bucket(blue, red)]
+            }
+            Self::Empties => {
+                let empties = pos.empty_squares().popcount() as usize;
+                let bucket = empties * EMPTIES_BUCKETS / (Square::N_SQUARES + 1);
+                [bucket, bucket]
+            }
+            Self::Halfmoves => {
+                let bucket = (pos.halfmoves() as usize / 10).min(HALFMOVE_BUCKETS - 1);
+                [bucket, bucket]
+            }
+        }
+    }
+}
+
+pub const GLOBAL_INPUT_SIZE: usize =
+    GlobalInput::Halfmoves.offset() + GlobalInput::Halfmoves.buckets();
+
+// the global inputs a net uses, a bit for each in the order above
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct GlobalInputs(u32);
+
+impl GlobalInputs {
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Option<Self> {
+        if bits >> GlobalInput::ALL.len() == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub const fn contains(self, input: GlobalInput) -> bool {
+        self.0 & (1 << input as u32) != 0
+    }
+
+    // features a net with these stores weights for
+    #[must_use]
+    pub const fn size(self) -> usize {
+        let mut size = 0;
+
+        let mut i = 0;
+        while i < GlobalInput::ALL.len() {
+            if self.contains(GlobalInput::ALL[i]) {
+                size += GlobalInput::ALL[i].buckets();
+            }
+            i += 1;
+        }
+
+        size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GlobalInput, GLOBAL_INPUT_SIZE};
+    use crate::position::Position;
+
+    #[test]
+    fn global_buckets_in_range() {
+        for fen in [
+            "x5o/7/7/7/7/7/o5x x 0 1",
+            "xxxxxxx/xxxxxxx/xxxxxxx/xxxxxxx/7/7/o6 o 0 1",
+            "7/7/7/7/ooooooo/ooooooo/ooooooo x 99 1",
+            "xxxxxxx/xxxxxxx/xxxxxxx/xxxxxxx/ooooooo/ooooooo/ooooooo x 100 1",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+
+            for input in GlobalInput::ALL {
+                for bucket in input.bucket(&pos) {
+                    assert!(bucket < input.buckets());
+                }
+            }
+        }
+
+        let pos = Position::from_fen("xxx4/7/7/7/7/7/o6 x 31 1").unwrap();
+
+        // each side sees its own lead
+        assert_eq!(GlobalInput::StoneDifference.bucket(&pos), [10, 6]);
+        assert_eq!(GlobalInput::Empties.bucket(&pos), [7, 7]);
+        assert_eq!(GlobalInput::Halfmoves.bucket(&pos), [3, 3]);
+
+        assert_eq!(GLOBAL_INPUT_SIZE, 35);
+    }
+}
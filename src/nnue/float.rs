@@ -0,0 +1,234 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Color;
+use crate::nnue::activation::Activation;
+use crate::nnue::features::{GlobalInput, GLOBAL_INPUT_SIZE};
+use crate::nnue::network::{network, FormatError, NetworkError, INPUT_SIZE, L1_SIZE, SCALE};
+use crate::nnue::{global_features, Input};
+use crate::position::Position;
+
+// a net as trainers have it before quantising, for measuring what quantising costs.
+// the file is every parameter of the loaded net's layout as a little endian f32, in
+// the same order and unscaled, with no header
+pub struct FloatNetwork {
+    // laid out like the loaded net's, so unused global inputs are 0
+    ft_weights: Vec<f32>,
+    ft_biases: Vec<f32>,
+    // by bucket, then neuron, then input
+    hidden_weights: Vec<f32>,
+    hidden_biases: Vec<f32>,
+    // by bucket, then input
+    output_weights: Vec<f32>,
+    output_biases: Vec<f32>,
+}
+
+impl FloatNetwork {
+    pub fn load(path: &str) -> Result<Self, NetworkError> {
+        let bytes = std::fs::read(path).map_err(NetworkError::Io)?;
+        Self::from_bytes(&bytes).map_err(NetworkError::Format)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        let network = network();
+
+        let expected = network.parameter_count() * 4;
+        if bytes.len() != expected {
+            return Err(FormatError::WrongSize {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let mut values = bytes
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()));
+        let mut read = |count: usize| values.by_ref().take(count).collect::<Vec<_>>();
+
+        let mut ft_weights = read(INPUT_SIZE * L1_SIZE);
+        ft_weights.resize((INPUT_SIZE + GLOBAL_INPUT_SIZE) * L1_SIZE, 0.0);
+
+        for input in GlobalInput::ALL {
+            if network.global_inputs.contains(input) {
+                let start = (INPUT_SIZE + input.offset()) * L1_SIZE;
+                let weights = read(input.buckets() * L1_SIZE);
+
+                ft_weights[start..start + weights.len()].copy_from_slice(&weights);
+            }
+        }
+
+        let ft_biases = read(L1_SIZE);
+
+        let inputs = 2 * network.activation.outputs();
+        let buckets = network.output_buckets;
+        let hidden_size = network.hidden_size;
+
+        let hidden_weights = read(buckets * hidden_size * inputs);
+        let hidden_biases = read(buckets * hidden_size);
+
+        let output_inputs = if hidden_size == 0 {
+            inputs
+        } else {
+            hidden_size
+        };
+
+        let output_weights = read(buckets * output_inputs);
+        let output_biases = read(buckets);
+
+        Ok(Self {
+            ft_weights,
+            ft_biases,
+            hidden_weights,
+            hidden_biases,
+            output_weights,
+            output_biases,
+        })
+    }
+
+    // the same as the loaded net's eval, from the side to move's point of view,
+    // without any rounding
+    #[must_use]
+    pub fn evaluate(&self, pos: &Position) -> f64 {
+        let network = network();
+        let symmetry = network.features.orientation(pos);
+
+        let mut accs = [[0f32; L1_SIZE]; 2];
+        for acc in &mut accs {
+            acc.copy_from_slice(&self.ft_biases);
+        }
+
+        let features = pos
+            .gaps()
+            .into_iter()
+            .map(Input::Gap)
+            .chain(
+                pos.red_occupancy()
+                    .into_iter()
+                    .map(|sq| Input::Stone(Color::RED, sq)),
+            )
+            .chain(
+                pos.blue_occupancy()
+                    .into_iter()
+                    .map(|sq| Input::Stone(Color::BLUE, sq)),
+            )
+            .map(|input| input.features(symmetry))
+            .chain(global_features(pos).into_iter().flatten());
+
+        for pair in features {
+            for (acc, feature) in accs.iter_mut().zip(pair) {
+                let weights = &self.ft_weights[feature * L1_SIZE..(feature + 1) * L1_SIZE];
+
+                for (value, weight) in acc.iter_mut().zip(weights) {
+                    *value += weight;
+                }
+            }
+        }
+
+        let (ours, theirs) = if pos.side_to_move() == Color::RED {
+            (&accs[0], &accs[1])
+        } else {
+            (&accs[1], &accs[0])
+        };
+
+        let clip = |v: f32| v.clamp(0.0, 1.0);
+
+        let activated = [ours, theirs]
+            .into_iter()
+            .flat_map(|acc| {
+                (0..network.activation.outputs()).map(move |i| match network.activation {
+                    Activation::ClippedReLU => clip(acc[i]),
+                    Activation::SquaredClippedReLU => clip(acc[i]) * clip(acc[i]),
+                    Activation::PairwiseClippedReLU => clip(acc[i]) * clip(acc[i + L1_SIZE / 2]),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let dot = |weights: &[f32], inputs: &[f32]| {
+            weights.iter().zip(inputs).map(|(w, v)| w * v).sum::<f32>()
+        };
+
+        let bucket = network.output_bucket(pos.occupancy().popcount());
+        let hidden_size = network.hidden_size;
+
+        let inputs = if hidden_size == 0 {
+            activated
+        } else {
+            let neurons = activated.len();
+
+            (0..hidden_size)
+                .map(|neuron| {
+                    let idx = bucket * hidden_size + neuron;
+                    let weights = &self.hidden_weights[idx * neurons..(idx + 1) * neurons];
+                    clip(dot(weights, &activated) + self.hidden_biases[idx])
+                })
+                .collect()
+        };
+
+        let weights = &self.output_weights[bucket * inputs.len()..(bucket + 1) * inputs.len()];
+        let output = dot(weights, &inputs) + self.output_biases[bucket];
+
+        f64::from(output) * f64::from(SCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FloatNetwork;
+    use crate::nnue::evaluate_once;
+    use crate::nnue::network::{network, EMBEDDED_NETWORK_BYTES, L1_Q, L1_SIZE, OUTPUT_Q};
+    use crate::position::Position;
+
+    #[test]
+    fn dequantised_net_matches() {
+        // the embedded net scaled back, which only differs from it by rounding
+        let ft_parameters = network().parameter_count() - 2 * L1_SIZE - 1;
+
+        let bytes = EMBEDDED_NETWORK_BYTES[64..]
+            .chunks_exact(2)
+            .enumerate()
+            .flat_map(|(idx, value)| {
+                let value = f32::from(i16::from_le_bytes([value[0], value[1]]));
+
+                let scale = if idx < ft_parameters {
+                    L1_Q
+                } else if idx < ft_parameters + 2 * L1_SIZE {
+                    OUTPUT_Q
+                } else {
+                    L1_Q * OUTPUT_Q
+                };
+
+                (value / scale as f32).to_le_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        let float = FloatNetwork::from_bytes(&bytes).unwrap();
+
+        assert!(FloatNetwork::from_bytes(&bytes[4..]).is_err());
+
+        for fen in [
+            "x5o/7/7/7/7/7/o5x x 0 1",
+            "x5o/1xx4/2ooo2/3x3/2-4/o6/6x o 0 1",
+            "xxxxxxx/ooooooo/xxxxxxx/3-3/7/ooooooo/7 x 0 1",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+
+            let error = float.evaluate(&pos) - f64::from(evaluate_once(&pos));
+            assert!(error.abs() < 2.0, "{} {}", fen, error);
+        }
+    }
+}
@@ -16,13 +16,30 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::bitboard::Bitboard;
 use crate::core::*;
+use crate::hash::Symmetry;
 use crate::nnue::network::*;
 use crate::position::Position;
-use crate::util::simd;
+use crate::util::{rng, simd};
+use arrayvec::ArrayVec;
 
 mod activation;
+mod features;
+mod float;
 mod network;
+mod trace;
+
+pub use features::GlobalInput;
+pub use float::FloatNetwork;
+pub use network::{
+    add_header, load_network, network_hash, network_size, use_embedded_network, NetworkError,
+    NetworkLayout,
+};
+pub use trace::trace_eval;
+
+#[cfg(test)]
+pub use network::{halfmove_network_bytes, use_thread_network};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(align(64))]
@@ -34,66 +51,56 @@ struct Accumulator {
 }
 
 impl Accumulator {
-    fn value_ptr(&self, idx: usize) -> *const simd::Register16 {
-        debug_assert!(idx < L1_SIZE);
-        unsafe { self.values.0.as_ptr().add(idx).cast() }
-    }
-
-    fn value_ptr_mut(&mut self, idx: usize) -> *mut simd::Register16 {
-        debug_assert!(idx < L1_SIZE);
-        unsafe { self.values.0.as_mut_ptr().add(idx).cast() }
-    }
-
     fn activate_feature(&mut self, feature: usize) {
-        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
-            let ptr = self.value_ptr_mut(i);
+        let ft = &network().feature_transformer;
 
-            let values = unsafe { simd::load16(ptr) };
-            let weights =
-                unsafe { simd::load16(NETWORK.feature_transformer.weight_ptr(feature, i)) };
+        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
+            let values = simd::load16(&self.values.0[i..]);
+            let weights = simd::load16(ft.weights_from(feature, i));
 
             let new = simd::add_i16(values, weights);
 
-            unsafe {
-                simd::store16(ptr, new);
-            }
+            simd::store16(&mut self.values.0[i..], new);
         }
     }
 
     fn deactivate_feature(&mut self, feature: usize) {
-        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
-            let ptr = self.value_ptr_mut(i);
+        let ft = &network().feature_transformer;
 
-            let values = unsafe { simd::load16(ptr) };
-            let weights =
-                unsafe { simd::load16(NETWORK.feature_transformer.weight_ptr(feature, i)) };
+        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
+            let values = simd::load16(&self.values.0[i..]);
+            let weights = simd::load16(ft.weights_from(feature, i));
 
             let new = simd::sub_i16(values, weights);
 
-            unsafe {
-                simd::store16(ptr, new);
-            }
+            simd::store16(&mut self.values.0[i..], new);
         }
     }
 
-    #[allow(unused)]
-    fn move_feature(&mut self, src_feature: usize, dst_feature: usize) {
-        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
-            let ptr = self.value_ptr_mut(i);
-
-            let values = unsafe { simd::load16(ptr) };
+    // the parent's values with every update applied, in one pass over the accumulator
+    fn apply_updates(
+        &mut self,
+        parent: &Accumulator,
+        added: &[FeaturePair],
+        removed: &[FeaturePair],
+        side: usize,
+    ) {
+        let ft = &network().feature_transformer;
 
-            let src_weights =
-                unsafe { simd::load16(NETWORK.feature_transformer.weight_ptr(src_feature, i)) };
-            let dst_weights =
-                unsafe { simd::load16(NETWORK.feature_transformer.weight_ptr(dst_feature, i)) };
+        for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
+            let mut values = simd::load16(&parent.values.0[i..]);
 
-            let new = simd::sub_i16(values, src_weights);
-            let new = simd::add_i16(new, dst_weights);
+            for features in added {
+                let weights = simd::load16(ft.weights_from(features[side], i));
+                values = simd::add_i16(values, weights);
+            }
 
-            unsafe {
-                simd::store16(ptr, new);
+            for features in removed {
+                let weights = simd::load16(ft.weights_from(features[side], i));
+                values = simd::sub_i16(values, weights);
             }
+
+            simd::store16(&mut self.values.0[i..], values);
         }
     }
 }
@@ -108,40 +115,192 @@ impl Default for Accumulator {
 
 const COLOR_STRIDE: usize = 49;
 
-fn gap_idx(sq: Square) -> usize {
-    2 * COLOR_STRIDE + sq.idx()
+// a feature's index in the red and blue accumulators
+type FeaturePair = [usize; 2];
+
+// squares are in the orientation the features are laid out in
+fn gap_indices(sq: Square) -> FeaturePair {
+    let idx = 2 * COLOR_STRIDE + sq.idx();
+    [idx, idx]
 }
 
-fn piece_indices(c: Color, sq: Square) -> (usize, usize) {
-    (
+fn piece_indices(c: Color, sq: Square) -> FeaturePair {
+    [
         c.idx() * COLOR_STRIDE + sq.idx(),
         c.flip().idx() * COLOR_STRIDE + sq.idx(),
-    )
+    ]
+}
+
+fn global_indices(input: GlobalInput, buckets: [usize; 2]) -> FeaturePair {
+    buckets.map(|bucket| INPUT_SIZE + input.offset() + bucket)
+}
+
+// what a feature index stands for, from the accumulator's own perspective
+#[cfg(debug_assertions)]
+#[must_use]
+fn describe_feature(feature: usize) -> String {
+    let square = |idx: usize| Square::from_coords((idx / 7) as u32, (idx % 7) as u32);
+
+    match feature / COLOR_STRIDE {
+        0 => format!("own stone on {}", square(feature)),
+        1 => format!("opponent stone on {}", square(feature - COLOR_STRIDE)),
+        2 => format!("gap on {}", square(feature - 2 * COLOR_STRIDE)),
+        _ => {
+            let global = feature - INPUT_SIZE;
+            let input = GlobalInput::ALL
+                .into_iter()
+                .rfind(|input| input.offset() <= global)
+                .unwrap();
+
+            format!("{:?} bucket {}", input, global - input.offset())
+        }
+    }
+}
+
+// the feature of each global input a net uses
+type GlobalFeatures = [Option<FeaturePair>; GlobalInput::ALL.len()];
+
+#[must_use]
+fn global_features(pos: &Position) -> GlobalFeatures {
+    let inputs = network().global_inputs;
+
+    GlobalInput::ALL.map(|input| {
+        inputs
+            .contains(input)
+            .then(|| global_indices(input, input.bucket(pos)))
+    })
+}
+
+// something on the board, before it is mapped to features for some orientation
+#[derive(Debug, Copy, Clone)]
+enum Input {
+    Stone(Color, Square),
+    Gap(Square),
+}
+
+impl Input {
+    #[must_use]
+    fn features(self, symmetry: Symmetry) -> FeaturePair {
+        match self {
+            Self::Stone(c, sq) => piece_indices(c, symmetry.apply_square(sq)),
+            Self::Gap(sq) => gap_indices(symmetry.apply_square(sq)),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Default)]
 struct AccumulatorPair {
     accs: [Accumulator; 2],
+    // the global features accs includes, which can lag behind the position
+    globals: GlobalFeatures,
 }
 
 impl AccumulatorPair {
-    fn reset(&mut self, pos: &Position) {
-        let biases = NETWORK.feature_transformer.biases.0.as_slice();
+    fn clear(&mut self) {
+        let biases = network().feature_transformer.biases.0.as_slice();
 
         self.red_mut().values.0.copy_from_slice(biases);
         self.blue_mut().values.0.copy_from_slice(biases);
 
+        self.globals = GlobalFeatures::default();
+    }
+
+    fn reset(&mut self, pos: &Position, symmetry: Symmetry) {
+        self.clear();
+
         for sq in pos.gaps() {
-            self.activate_gap(sq);
+            self.activate(Input::Gap(sq).features(symmetry));
         }
 
         for sq in pos.red_occupancy() {
-            self.activate_feature(Color::RED, sq);
+            self.activate(Input::Stone(Color::RED, sq).features(symmetry));
         }
 
         for sq in pos.blue_occupancy() {
-            self.activate_feature(Color::BLUE, sq);
+            self.activate(Input::Stone(Color::BLUE, sq).features(symmetry));
+        }
+
+        self.update_globals(pos);
+    }
+
+    // global inputs depend on the whole position rather than changing with squares,
+    // so they are brought up to date only when accs is about to be used
+    fn update_globals(&mut self, pos: &Position) {
+        let globals = global_features(pos);
+
+        for (old, new) in self.globals.into_iter().zip(globals) {
+            if old != new {
+                if let Some(features) = old {
+                    self.deactivate(features);
+                }
+
+                if let Some(features) = new {
+                    self.activate(features);
+                }
+            }
+        }
+
+        self.globals = globals;
+    }
+
+    // how these differ from what they should be, in terms of any single feature that would
+    // explain each side's difference. None if they match
+    #[cfg(debug_assertions)]
+    #[must_use]
+    fn divergence(&self, expected: &AccumulatorPair) -> Option<String> {
+        use std::fmt::Write;
+
+        let ft = &network().feature_transformer;
+        let mut report = String::new();
+
+        for (side, (acc, expected)) in self.accs.iter().zip(&expected.accs).enumerate() {
+            let diff: Vec<i16> = acc
+                .values
+                .0
+                .iter()
+                .zip(&expected.values.0)
+                .map(|(&v, &e)| v.wrapping_sub(e))
+                .collect();
+
+            if diff.iter().all(|&d| d == 0) {
+                continue;
+            }
+
+            let name = if side == 0 { "red" } else { "blue" };
+            let differing = diff.iter().filter(|&&d| d != 0).count();
+
+            let _ = write!(
+                report,
+                "{}: {} of {} values differ,",
+                name, differing, L1_SIZE
+            );
+
+            let mut explained = false;
+
+            for feature in 0..INPUT_SIZE + features::GLOBAL_INPUT_SIZE {
+                let weights = &ft.weights.0[feature * L1_SIZE..(feature + 1) * L1_SIZE];
+
+                if weights.iter().zip(&diff).all(|(&w, &d)| w == d) {
+                    let _ = write!(report, " extra {}", describe_feature(feature));
+                    explained = true;
+                } else if weights
+                    .iter()
+                    .zip(&diff)
+                    .all(|(&w, &d)| w.wrapping_neg() == d)
+                {
+                    let _ = write!(report, " missing {}", describe_feature(feature));
+                    explained = true;
+                }
+            }
+
+            if !explained {
+                report.push_str(" not explained by any single feature");
+            }
+
+            report.push('\n');
         }
+
+        (!report.is_empty()).then_some(report)
     }
 
     fn red(&self) -> &Accumulator {
@@ -160,54 +319,213 @@ impl AccumulatorPair {
         &mut self.accs[1]
     }
 
-    fn activate_gap(&mut self, sq: Square) {
-        let idx = gap_idx(sq);
+    fn activate(&mut self, [red_idx, blue_idx]: FeaturePair) {
+        self.red_mut().activate_feature(red_idx);
+        self.blue_mut().activate_feature(blue_idx);
+    }
 
-        self.red_mut().activate_feature(idx);
-        self.blue_mut().activate_feature(idx);
+    fn deactivate(&mut self, [red_idx, blue_idx]: FeaturePair) {
+        self.red_mut().deactivate_feature(red_idx);
+        self.blue_mut().deactivate_feature(blue_idx);
     }
 
-    pub fn activate_feature(&mut self, c: Color, sq: Square) {
-        let (red_idx, blue_idx) = piece_indices(c, sq);
+    fn apply_updates(
+        &mut self,
+        parent: &AccumulatorPair,
+        updates: &PendingUpdates,
+        symmetry: Symmetry,
+    ) {
+        let features = |inputs: &ArrayVec<Input, MAX_PENDING_UPDATES>| {
+            inputs
+                .iter()
+                .map(|input| input.features(symmetry))
+                .collect::<ArrayVec<_, MAX_PENDING_UPDATES>>()
+        };
+
+        let added = features(&updates.added);
+        let removed = features(&updates.removed);
+
+        for (side, (acc, parent)) in self.accs.iter_mut().zip(&parent.accs).enumerate() {
+            acc.apply_updates(parent, &added, &removed, side);
+        }
 
-        self.red_mut().activate_feature(red_idx);
-        self.blue_mut().activate_feature(blue_idx);
+        self.globals = parent.globals;
     }
+}
 
-    pub fn deactivate_feature(&mut self, c: Color, sq: Square) {
-        let (red_idx, blue_idx) = piece_indices(c, sq);
+// a move changes at most 9 inputs each way, editing the board can change any number
+const MAX_PENDING_UPDATES: usize = 32;
 
-        self.red_mut().deactivate_feature(red_idx);
-        self.blue_mut().deactivate_feature(blue_idx);
+// inputs changed since the parent ply
+#[derive(Debug, Clone, Default)]
+struct PendingUpdates {
+    added: ArrayVec<Input, MAX_PENDING_UPDATES>,
+    removed: ArrayVec<Input, MAX_PENDING_UPDATES>,
+}
+
+impl PendingUpdates {
+    fn clear(&mut self) {
+        self.added.clear();
+        self.removed.clear();
     }
+}
 
-    #[allow(unused)]
-    pub fn move_feature(&mut self, c: Color, src_sq: Square, dst_sq: Square) {
-        let (red_src_idx, blue_src_idx) = piece_indices(c, src_sq);
-        let (red_dst_idx, blue_dst_idx) = piece_indices(c, dst_sq);
+#[derive(Debug, Clone, Default)]
+struct StackEntry {
+    accs: AccumulatorPair,
+    updates: PendingUpdates,
+    // whether accs is up to date. if not, it is the parent's with updates
+    // applied, which is only done once this ply is actually evaluated
+    computed: bool,
+    // the orientation accs has its features in, once computed
+    symmetry: Symmetry,
+}
+
+const REFRESH_BUCKETS: usize = 8;
+
+#[derive(Debug, Copy, Clone)]
+struct RefreshEntry {
+    accs: AccumulatorPair,
+    // the position accs are up to date with
+    colors: [Bitboard; 2],
+    gaps: Bitboard,
+}
+
+impl RefreshEntry {
+    // inputs that differ from the position's
+    #[must_use]
+    fn changes(&self, pos: &Position) -> u32 {
+        (self.colors[0] ^ pos.red_occupancy()).popcount()
+            + (self.colors[1] ^ pos.blue_occupancy()).popcount()
+            + (self.gaps ^ pos.gaps()).popcount()
+    }
+
+    fn update(&mut self, pos: &Position, symmetry: Symmetry) {
+        for color in [Color::RED, Color::BLUE] {
+            let old = self.colors[color.idx()];
+            let new = pos.color_occupancy(color);
+
+            for sq in old & !new {
+                self.accs
+                    .deactivate(Input::Stone(color, sq).features(symmetry));
+            }
+
+            for sq in new & !old {
+                self.accs
+                    .activate(Input::Stone(color, sq).features(symmetry));
+            }
+
+            self.colors[color.idx()] = new;
+        }
+
+        for sq in self.gaps & !pos.gaps() {
+            self.accs.deactivate(Input::Gap(sq).features(symmetry));
+        }
+
+        for sq in pos.gaps() & !self.gaps {
+            self.accs.activate(Input::Gap(sq).features(symmetry));
+        }
+
+        self.gaps = pos.gaps();
+
+        self.accs.update_globals(pos);
+    }
+}
 
-        self.red_mut().move_feature(red_src_idx, red_dst_idx);
-        self.blue_mut().move_feature(blue_src_idx, blue_dst_idx);
+impl Default for RefreshEntry {
+    fn default() -> Self {
+        Self {
+            accs: AccumulatorPair::default(),
+            colors: [Bitboard::EMPTY; 2],
+            gaps: Bitboard::EMPTY,
+        }
+    }
+}
+
+// the accumulators recent resets ended up with, bucketed by stone count and orientation.
+// a reset starts from its bucket's and applies only what differs, which between
+// consecutive searches in a game is a few stones rather than the whole board
+#[derive(Debug, Clone, Default)]
+pub struct RefreshCache {
+    entries: [[RefreshEntry; REFRESH_BUCKETS]; Symmetry::N_SYMMETRIES],
+    // of the network the entries were built with
+    network_hash: Option<u64>,
+}
+
+impl RefreshCache {
+    fn refresh(&mut self, accs: &mut AccumulatorPair, pos: &Position, symmetry: Symmetry) {
+        let hash = network().hash;
+
+        if self.network_hash != Some(hash) {
+            let mut empty = RefreshEntry::default();
+            empty.accs.clear();
+
+            self.entries.fill([empty; REFRESH_BUCKETS]);
+            self.network_hash = Some(hash);
+        }
+
+        let stones = pos.occupancy().popcount() as usize;
+        let entry =
+            &mut self.entries[symmetry.idx()][stones * REFRESH_BUCKETS / (Square::N_SQUARES + 1)];
+
+        // starting over is cheaper when the cached position is too different
+        if entry.changes(pos) > pos.occupancy().popcount() + pos.gaps().popcount() {
+            entry.accs.reset(pos, symmetry);
+            entry.colors = [pos.red_occupancy(), pos.blue_occupancy()];
+            entry.gaps = pos.gaps();
+        } else {
+            entry.update(pos, symmetry);
+        }
+
+        *accs = entry.accs;
     }
 }
 
 const STACK_SIZE: usize = MAX_DEPTH as usize + 1;
 
+// debug builds rebuild every this many evaluations' accumulators from scratch,
+// and stop if the incrementally updated ones differ
+#[cfg(debug_assertions)]
+const VERIFY_INTERVAL: u64 = 64;
+
 pub struct NnueState {
-    stack: [AccumulatorPair; STACK_SIZE],
+    stack: [StackEntry; STACK_SIZE],
     idx: usize,
+    refresh_cache: RefreshCache,
+    #[cfg(debug_assertions)]
+    evaluations: u64,
 }
 
 impl NnueState {
     pub fn reset(&mut self, pos: &Position) {
         assert_eq!(self.idx, 0);
         self.idx = 0;
-        self.stack[0].reset(pos);
+
+        self.refresh(pos, network().features.orientation(pos));
+    }
+
+    // the current ply from scratch, or as near as the refresh cache gets
+    fn refresh(&mut self, pos: &Position, symmetry: Symmetry) {
+        let entry = &mut self.stack[self.idx];
+
+        self.refresh_cache.refresh(&mut entry.accs, pos, symmetry);
+        entry.updates.clear();
+        entry.computed = true;
+        entry.symmetry = symmetry;
+    }
+
+    // for keeping the cache when this state is not kept itself
+    pub fn swap_refresh_cache(&mut self, cache: &mut RefreshCache) {
+        std::mem::swap(&mut self.refresh_cache, cache);
     }
 
     pub fn push(&mut self) {
-        self.stack[self.idx + 1] = self.stack[self.idx];
         self.idx += 1;
+
+        let entry = &mut self.stack[self.idx];
+
+        entry.updates.clear();
+        entry.computed = false;
     }
 
     pub fn pop(&mut self) -> bool {
@@ -218,74 +536,299 @@ impl NnueState {
         true
     }
 
+    // applied straight away if this ply is already up to date, otherwise left for evaluate
+    fn update(&mut self, input: Input, add: bool) {
+        let entry = &mut self.stack[self.idx];
+
+        if !entry.computed {
+            let updates = if add {
+                &mut entry.updates.added
+            } else {
+                &mut entry.updates.removed
+            };
+
+            if updates.try_push(input).is_ok() {
+                return;
+            }
+
+            self.catch_up();
+        }
+
+        let entry = &mut self.stack[self.idx];
+        let features = input.features(entry.symmetry);
+
+        if add {
+            entry.accs.activate(features);
+        } else {
+            entry.accs.deactivate(features);
+        }
+    }
+
+    #[must_use]
+    fn last_computed(&self) -> usize {
+        let mut idx = self.idx;
+        while !self.stack[idx].computed {
+            idx -= 1;
+        }
+        idx
+    }
+
+    // brings every ply since the last evaluated one up to date, in that one's orientation
+    fn catch_up(&mut self) {
+        let first = self.last_computed();
+        let symmetry = self.stack[first].symmetry;
+
+        for idx in first + 1..=self.idx {
+            let (parents, entries) = self.stack.split_at_mut(idx);
+            let entry = &mut entries[0];
+
+            entry
+                .accs
+                .apply_updates(&parents[idx - 1].accs, &entry.updates, symmetry);
+            entry.updates.clear();
+            entry.computed = true;
+            entry.symmetry = symmetry;
+        }
+    }
+
     pub fn activate_feature(&mut self, c: Color, sq: Square) {
-        let accs = &mut self.stack[self.idx];
-        accs.activate_feature(c, sq);
+        self.update(Input::Stone(c, sq), true);
+    }
+
+    pub fn activate_gap(&mut self, sq: Square) {
+        self.update(Input::Gap(sq), true);
+    }
+
+    pub fn deactivate_gap(&mut self, sq: Square) {
+        self.update(Input::Gap(sq), false);
     }
 
     pub fn deactivate_feature(&mut self, c: Color, sq: Square) {
-        let accs = &mut self.stack[self.idx];
-        accs.deactivate_feature(c, sq);
+        self.update(Input::Stone(c, sq), false);
     }
 
     #[allow(unused)]
     pub fn move_feature(&mut self, c: Color, src_sq: Square, dst_sq: Square) {
-        let accs = &mut self.stack[self.idx];
-        accs.move_feature(c, src_sq, dst_sq);
+        self.deactivate_feature(c, src_sq);
+        self.activate_feature(c, dst_sq);
     }
 
-    pub fn evaluate(&self, stm: Color) -> Score {
-        let accs = &self.stack[self.idx];
-        evaluate(accs, stm)
+    pub fn evaluate(&mut self, pos: &Position) -> Score {
+        let symmetry = network().features.orientation(pos);
+
+        // features laid out for another orientation cannot be updated into this one
+        if self.stack[self.last_computed()].symmetry == symmetry {
+            self.catch_up();
+        } else {
+            self.refresh(pos, symmetry);
+        }
+
+        self.stack[self.idx].accs.update_globals(pos);
+
+        #[cfg(debug_assertions)]
+        self.verify(pos);
+
+        evaluate(&self.stack[self.idx].accs, pos)
+    }
+
+    #[cfg(debug_assertions)]
+    fn verify(&mut self, pos: &Position) {
+        self.evaluations += 1;
+
+        if !self.evaluations.is_multiple_of(VERIFY_INTERVAL) {
+            return;
+        }
+
+        let entry = &self.stack[self.idx];
+
+        let mut expected = AccumulatorPair::default();
+        expected.reset(pos, entry.symmetry);
+
+        if let Some(report) = entry.accs.divergence(&expected) {
+            panic!(
+                "incrementally updated accumulators diverged at ply {} of {}\n{}",
+                self.idx,
+                pos.to_fen(),
+                report
+            );
+        }
     }
 }
 
 impl Default for NnueState {
+    #[allow(clippy::large_stack_arrays)]
     fn default() -> Self {
         Self {
-            stack: [AccumulatorPair::default(); STACK_SIZE],
+            stack: std::array::from_fn(|_| StackEntry::default()),
             idx: 0,
+            refresh_cache: RefreshCache::default(),
+            #[cfg(debug_assertions)]
+            evaluations: 0,
         }
     }
 }
 
+#[allow(clippy::unreadable_literal)]
+const HALFMOVE_KEYS: [u64; GlobalInput::Halfmoves.buckets()] =
+    rng::fill_u64_array(0x5bd1e9955bd1e995);
+
+// the position's key, plus anything else the net sees that the key leaves out,
+// for caching evals by. only the halfmove clock is missing from the key
+#[must_use]
+pub fn eval_key(pos: &Position) -> u64 {
+    if network().global_inputs.contains(GlobalInput::Halfmoves) {
+        let [bucket, _] = GlobalInput::Halfmoves.bucket(pos);
+        pos.key() ^ HALFMOVE_KEYS[bucket]
+    } else {
+        pos.key()
+    }
+}
+
 pub fn evaluate_once(pos: &Position) -> Score {
     let mut accumulator = AccumulatorPair::default();
-    accumulator.reset(pos);
+    accumulator.reset(pos, network().features.orientation(pos));
 
-    evaluate(&accumulator, pos.side_to_move())
+    evaluate(&accumulator, pos)
 }
 
-fn evaluate(accs: &AccumulatorPair, stm: Color) -> Score {
-    let (ours, theirs) = if stm == Color::RED {
+fn evaluate(accs: &AccumulatorPair, pos: &Position) -> Score {
+    evaluate_with(network(), accs, pos)
+}
+
+fn evaluate_with(network: &Network, accs: &AccumulatorPair, pos: &Position) -> Score {
+    let (ours, theirs) = if pos.side_to_move() == Color::RED {
         (accs.red(), accs.blue())
     } else {
         (accs.blue(), accs.red())
     };
 
-    let mut sum = simd::zero32();
+    let bucket = network.output_bucket(pos.occupancy().popcount());
+    let output = &network.output[bucket];
+    let activation = network.activation;
+
+    // both perspectives activated and multiplied by one neuron's weights
+    let dot = |weights: &[i16]| {
+        let perspective = |acc: &Accumulator, weight_offset: usize| {
+            activation.dot(
+                |i| simd::load16(&acc.values.0[i..]),
+                |i| simd::load16(&weights[weight_offset + i..]),
+            )
+        };
+
+        let sum = simd::add_i32(
+            perspective(ours, 0),
+            perspective(theirs, activation.outputs()),
+        );
+        simd::horizontal_sum_i32(sum) / activation.extra_scale()
+    };
+
+    let sum = if network.hidden_size == 0 {
+        dot(&output.weights.0)
+    } else {
+        let hidden = &network.hidden[bucket];
+
+        (0..network.hidden_size)
+            .map(|neuron| {
+                let value = dot(hidden.output_weights(neuron)) + i32::from(hidden.biases.0[neuron]);
+                (value / OUTPUT_Q).clamp(0, L1_Q) * i32::from(output.weights.0[neuron])
+            })
+            .sum()
+    };
+
+    (sum + i32::from(output.biases.0[0])) * SCALE / (L1_Q * OUTPUT_Q)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::movegen::{fill_move_list, MoveList};
+    use crate::nnue::{evaluate_once, NnueState};
+    use crate::position::Position;
+
+    #[test]
+    fn refresh_cache_matches_full_reset() {
+        let mut nnue = Box::<NnueState>::default();
+        let mut pos = Position::startpos();
+
+        // each reset is a few stones away from the last one in its bucket
+        for ply in 0..40 {
+            nnue.reset(&pos);
+            assert_eq!(nnue.evaluate(&pos), evaluate_once(&pos));
+
+            let mut moves = MoveList::new();
+            fill_move_list(&mut moves, &pos);
 
-    for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
-        let values = unsafe { simd::load16(ours.value_ptr(i)) };
-        let activated = Activation::activate(values);
+            pos.apply_move::<false, false>(moves[(ply * 3) % moves.len()], None);
+        }
 
-        let weights = unsafe { simd::load16(NETWORK.l1.weight_ptr(0, i)) };
+        // same bucket, but nothing in common, so it starts over
+        for fen in [
+            "xxxxxxx/xxxxxxx/7/7/7/7/7 o 0 1",
+            "7/7/7/7/7/ooooooo/ooooooo x 0 1",
+            "7/7/2-1-2/7/2-1-2/ooooooo/ooooooo x 0 1",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
 
-        let product = simd::mul_add_adj_i16(activated, weights);
+            nnue.reset(&pos);
+            assert_eq!(nnue.evaluate(&pos), evaluate_once(&pos));
+        }
 
-        sum = simd::add_i32(product, sum);
+        let gapped = Position::from_fen("7/7/2-1-2/7/2-1-2/ooooooo/ooooooo x 0 1").unwrap();
+        assert!(nnue
+            .refresh_cache
+            .entries
+            .iter()
+            .flatten()
+            .any(|entry| entry.gaps == gapped.gaps()));
     }
 
-    for i in (0..L1_SIZE).step_by(simd::CHUNK_SIZE_I16) {
-        let values = unsafe { simd::load16(theirs.value_ptr(i)) };
-        let activated = Activation::activate(values);
+    #[test]
+    #[cfg(debug_assertions)]
+    fn divergence_names_features() {
+        use crate::core::{Color, Square};
+        use crate::hash::Symmetry;
+        use crate::nnue::{AccumulatorPair, Input};
+
+        let pos = Position::from_fen("x5o/7/7/7/7/7/o5x x 0 1").unwrap();
 
-        let weights = unsafe { simd::load16(NETWORK.l1.weight_ptr(L1_SIZE, i)) };
+        let mut expected = AccumulatorPair::default();
+        expected.reset(&pos, Symmetry::IDENTITY);
+        assert_eq!(expected.divergence(&expected), None);
 
-        let product = simd::mul_add_adj_i16(activated, weights);
+        let mut accs = expected;
+        accs.activate(Input::Stone(Color::RED, Square::D4).features(Symmetry::IDENTITY));
 
-        sum = simd::add_i32(product, sum);
+        let report = accs.divergence(&expected).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("red:") && lines[0].ends_with("extra own stone on d4"));
+        assert!(lines[1].starts_with("blue:") && lines[1].ends_with("extra opponent stone on d4"));
+
+        let mut accs = expected;
+        accs.red_mut().deactivate_feature(
+            Input::Stone(Color::BLUE, Square::G7).features(Symmetry::IDENTITY)[0],
+        );
+        let report = accs.divergence(&expected).unwrap();
+        assert!(report.starts_with("red:"));
+        assert!(report.ends_with("missing opponent stone on g7\n"));
     }
 
-    simd::horizontal_sum_i32(sum) * SCALE / (L1_Q * OUTPUT_Q)
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "extra own stone on d4")]
+    fn verification_catches_divergence() {
+        use crate::core::{Color, Square};
+
+        let mut nnue = Box::<NnueState>::default();
+        let pos = Position::startpos();
+
+        nnue.reset(&pos);
+
+        // a stone the position does not have
+        nnue.activate_feature(Color::RED, Square::D4);
+
+        for _ in 0..super::VERIFY_INTERVAL {
+            let _ = nnue.evaluate(&pos);
+        }
+    }
 }
@@ -16,8 +16,13 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::nnue::{activation, Align64};
+use crate::core::Square;
+use crate::nnue::activation::Activation;
+use crate::nnue::features::{FeatureSet, GlobalInput, GlobalInputs, GLOBAL_INPUT_SIZE};
+use crate::nnue::Align64;
 use crate::util::simd;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicPtr, Ordering};
 
 pub const L1_Q: i32 = 255;
 pub const OUTPUT_Q: i32 = 64;
@@ -27,7 +32,11 @@ pub const SCALE: i32 = 400;
 pub const INPUT_SIZE: usize = 147;
 pub const L1_SIZE: usize = 64;
 
-pub type Activation = activation::ClippedReLU<{ L1_Q as i16 }>;
+// the net decides how many it uses, up to this
+pub const MAX_OUTPUT_BUCKETS: usize = 8;
+
+// likewise, with 0 for no hidden layer at all
+pub const MAX_HIDDEN_SIZE: usize = 32;
 
 #[repr(C)]
 pub struct Layer<T, const INPUTS: usize, const WEIGHTS: usize, const OUTPUTS: usize> {
@@ -38,18 +47,904 @@ pub struct Layer<T, const INPUTS: usize, const WEIGHTS: usize, const OUTPUTS: us
 impl<T, const INPUTS: usize, const WEIGHTS: usize, const OUTPUTS: usize>
     Layer<T, INPUTS, WEIGHTS, OUTPUTS>
 {
-    pub fn weight_ptr(&self, feature: usize, idx: usize) -> *const simd::Register16 {
-        debug_assert!(feature * OUTPUTS + idx < WEIGHTS);
+    // a feature's weights, starting from the idx'th
+    pub fn weights_from(&self, feature: usize, idx: usize) -> &[T] {
         debug_assert_eq!(idx % simd::CHUNK_SIZE_I16, 0);
+        &self.weights.0[feature * OUTPUTS + idx..]
+    }
 
-        unsafe { self.weights.0.as_ptr().add(feature * OUTPUTS + idx).cast() }
+    // one output's weights, for layers stored output by output
+    pub fn output_weights(&self, output: usize) -> &[T] {
+        &self.weights.0[output * INPUTS..(output + 1) * INPUTS]
     }
 }
 
 #[repr(C)]
 pub struct Network {
-    pub feature_transformer: Layer<i16, INPUT_SIZE, { INPUT_SIZE * L1_SIZE }, L1_SIZE>,
-    pub l1: Layer<i16, L1_SIZE, { L1_SIZE * 2 }, 1>,
+    // square inputs, then every global input whether the net uses it or not
+    pub feature_transformer: Layer<
+        i16,
+        { INPUT_SIZE + GLOBAL_INPUT_SIZE },
+        { (INPUT_SIZE + GLOBAL_INPUT_SIZE) * L1_SIZE },
+        L1_SIZE,
+    >,
+    // everything past the accumulators is per bucket, only the first output_buckets are used
+    pub hidden: [Layer<i16, { L1_SIZE * 2 }, { L1_SIZE * 2 * MAX_HIDDEN_SIZE }, MAX_HIDDEN_SIZE>;
+        MAX_OUTPUT_BUCKETS],
+    pub output: [Layer<i16, { L1_SIZE * 2 }, { L1_SIZE * 2 }, 1>; MAX_OUTPUT_BUCKETS],
+    pub output_buckets: usize,
+    // the first hidden_size neurons of each hidden layer are used, or none of it if 0
+    pub hidden_size: usize,
+    pub activation: Activation,
+    pub features: FeatureSet,
+    pub global_inputs: GlobalInputs,
+    // fnv-1a over the parameters as stored in the file, to tell nets apart in output
+    pub hash: u64,
+}
+
+impl Network {
+    // buckets split the possible stone counts into equal ranges
+    #[must_use]
+    pub fn output_bucket(&self, stones: u32) -> usize {
+        stones as usize * self.output_buckets / (Square::N_SQUARES + 1)
+    }
+
+    // as stored in a file, header aside
+    #[must_use]
+    pub fn parameter_count(&self) -> usize {
+        let size = file_size(
+            self.output_buckets,
+            self.activation,
+            self.hidden_size,
+            self.global_inputs,
+        );
+        (size - HEADER_SIZE) / 2
+    }
+}
+
+// the format is also described in docs/network-format.md.
+// a network file is a fixed size header, then every parameter as a little endian
+// i16 in the order they are declared above, without padding. each later layer's
+// weights come bucket by bucket, followed by all of its biases. pairwise activation
+// halves the inputs of the layer after the accumulators, so only that many weights
+// are stored for it. hidden layer weights are stored neuron by neuron, quantised by
+// OUTPUT_Q with biases by L1_Q * OUTPUT_Q, and its outputs are clipped to [0, 1] in
+// units of L1_Q so the output layer sees the same scale as without one. only the
+// feature transformer weights of global inputs the net uses are stored, after the
+// square inputs' and in their usual order.
+// the header is:
+//   0  magic
+//   8  format version
+//  12  input size, l1 size, output buckets, l1 q, output q and eval scale, u32 each
+//  36  activation, a u32 id
+//  40  fnv-1a of the parameters as a u64, or 0 to skip checking it
+//  48  feature set, a u32 id
+//  52  hidden layer size, a u32, or 0 for none
+//  56  global inputs, a u32 with a bit for each
+//  60  reserved up to the header size
+// all numbers are little endian, and reserved bytes are 0
+const MAGIC: [u8; 8] = *b"SPHXNNUE";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+
+const OUTPUT_BUCKETS_OFFSET: usize = 20;
+
+const ACTIVATION_OFFSET: usize = 36;
+
+#[must_use]
+const fn file_size(
+    output_buckets: usize,
+    activation: Activation,
+    hidden_size: usize,
+    global_inputs: GlobalInputs,
+) -> usize {
+    let inputs = 2 * activation.outputs();
+    let layers = if hidden_size == 0 {
+        inputs + 1
+    } else {
+        (inputs + 1) * hidden_size + hidden_size + 1
+    };
+
+    let parameters =
+        (INPUT_SIZE + global_inputs.size()) * L1_SIZE + L1_SIZE + layers * output_buckets;
+    HEADER_SIZE + parameters * 2
+}
+
+// must match this build's architecture exactly
+const ARCHITECTURE: [(&str, usize, u32); 5] = [
+    ("input size", 12, INPUT_SIZE as u32),
+    ("l1 size", 16, L1_SIZE as u32),
+    ("l1 quantisation", 24, L1_Q as u32),
+    ("output quantisation", 28, OUTPUT_Q as u32),
+    ("eval scale", 32, SCALE as u32),
+];
+
+const CHECKSUM_OFFSET: usize = 40;
+
+const FEATURE_SET_OFFSET: usize = 48;
+
+const HIDDEN_SIZE_OFFSET: usize = 52;
+
+const GLOBAL_INPUTS_OFFSET: usize = 56;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FormatError {
+    TooShort(usize),
+    BadMagic,
+    UnsupportedVersion(u32),
+    UnsupportedOutputBuckets(u32),
+    UnknownActivation(u32),
+    UnknownFeatureSet(u32),
+    UnsupportedHiddenSize(u32),
+    UnknownGlobalInputs(u32),
+    WrongArchitecture {
+        field: &'static str,
+        expected: u32,
+        found: u32,
+    },
+    WrongSize {
+        expected: usize,
+        found: usize,
+    },
+    ChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::TooShort(size) => {
+                write!(f, "File too short for a network header ({} bytes)", size)
+            }
+            FormatError::BadMagic => write!(f, "Not a network file"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported network format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            FormatError::UnsupportedOutputBuckets(buckets) => write!(
+                f,
+                "Unsupported network output bucket count {} (expected 1 to {})",
+                buckets, MAX_OUTPUT_BUCKETS
+            ),
+            FormatError::UnknownActivation(id) => {
+                write!(f, "Unknown network activation {}", id)
+            }
+            FormatError::UnknownFeatureSet(id) => {
+                write!(f, "Unknown network feature set {}", id)
+            }
+            FormatError::UnsupportedHiddenSize(size) => write!(
+                f,
+                "Unsupported network hidden layer size {} (expected 0 to {})",
+                size, MAX_HIDDEN_SIZE
+            ),
+            FormatError::UnknownGlobalInputs(bits) => {
+                write!(f, "Unknown network global inputs {:#x}", bits)
+            }
+            FormatError::WrongArchitecture {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Wrong network {} {} (expected {})",
+                field, found, expected
+            ),
+            FormatError::WrongSize { expected, found } => {
+                write!(f, "Wrong network size {} (expected {})", found, expected)
+            }
+            FormatError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Network checksum {:016x} does not match header {:016x}",
+                found, expected
+            ),
+        }
+    }
+}
+
+const fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+const fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    (read_u32(bytes, offset) as u64) | ((read_u32(bytes, offset + 4) as u64) << 32)
+}
+
+// advances offset past what was read
+const fn read_i16s(bytes: &[u8], offset: &mut usize, values: &mut [i16]) {
+    let mut i = 0;
+    while i < values.len() {
+        values[i] = i16::from_le_bytes([bytes[*offset], bytes[*offset + 1]]);
+        *offset += 2;
+        i += 1;
+    }
+}
+
+const fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+
+    hash
+}
+
+// const, so the embedded network is checked at compile time
+#[allow(clippy::large_stack_arrays)]
+const fn parse_network(bytes: &[u8]) -> Result<Network, FormatError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(FormatError::TooShort(bytes.len()));
+    }
+
+    let mut i = 0;
+    while i < MAGIC.len() {
+        if bytes[i] != MAGIC[i] {
+            return Err(FormatError::BadMagic);
+        }
+        i += 1;
+    }
+
+    let version = read_u32(bytes, MAGIC.len());
+    if version != FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+
+    let mut i = 0;
+    while i < ARCHITECTURE.len() {
+        let (field, offset, expected) = ARCHITECTURE[i];
+        let found = read_u32(bytes, offset);
+
+        if found != expected {
+            return Err(FormatError::WrongArchitecture {
+                field,
+                expected,
+                found,
+            });
+        }
+
+        i += 1;
+    }
+
+    let output_buckets = read_u32(bytes, OUTPUT_BUCKETS_OFFSET);
+    if output_buckets == 0 || output_buckets as usize > MAX_OUTPUT_BUCKETS {
+        return Err(FormatError::UnsupportedOutputBuckets(output_buckets));
+    }
+
+    let output_buckets = output_buckets as usize;
+
+    let activation_id = read_u32(bytes, ACTIVATION_OFFSET);
+    let Some(activation) = Activation::from_id(activation_id) else {
+        return Err(FormatError::UnknownActivation(activation_id));
+    };
+
+    let feature_set_id = read_u32(bytes, FEATURE_SET_OFFSET);
+    let Some(features) = FeatureSet::from_id(feature_set_id) else {
+        return Err(FormatError::UnknownFeatureSet(feature_set_id));
+    };
+
+    let hidden_size = read_u32(bytes, HIDDEN_SIZE_OFFSET);
+    if hidden_size as usize > MAX_HIDDEN_SIZE {
+        return Err(FormatError::UnsupportedHiddenSize(hidden_size));
+    }
+
+    let hidden_size = hidden_size as usize;
+
+    let global_bits = read_u32(bytes, GLOBAL_INPUTS_OFFSET);
+    let Some(global_inputs) = GlobalInputs::from_bits(global_bits) else {
+        return Err(FormatError::UnknownGlobalInputs(global_bits));
+    };
+
+    let expected_size = file_size(output_buckets, activation, hidden_size, global_inputs);
+    if bytes.len() != expected_size {
+        return Err(FormatError::WrongSize {
+            expected: expected_size,
+            found: bytes.len(),
+        });
+    }
+
+    let (_, parameters) = bytes.split_at(HEADER_SIZE);
+    let hash = fnv1a(parameters);
+
+    let expected = read_u64(bytes, CHECKSUM_OFFSET);
+    if expected != 0 && expected != hash {
+        return Err(FormatError::ChecksumMismatch {
+            expected,
+            found: hash,
+        });
+    }
+
+    let mut network = Network {
+        feature_transformer: Layer {
+            weights: Align64([0; (INPUT_SIZE + GLOBAL_INPUT_SIZE) * L1_SIZE]),
+            biases: Align64([0; L1_SIZE]),
+        },
+        hidden: [const {
+            Layer {
+                weights: Align64([0; L1_SIZE * 2 * MAX_HIDDEN_SIZE]),
+                biases: Align64([0; MAX_HIDDEN_SIZE]),
+            }
+        }; MAX_OUTPUT_BUCKETS],
+        output: [const {
+            Layer {
+                weights: Align64([0; L1_SIZE * 2]),
+                biases: Align64([0; 1]),
+            }
+        }; MAX_OUTPUT_BUCKETS],
+        output_buckets,
+        hidden_size,
+        activation,
+        features,
+        global_inputs,
+        hash,
+    };
+
+    let mut offset = HEADER_SIZE;
+
+    let (squares, globals) = network
+        .feature_transformer
+        .weights
+        .0
+        .split_at_mut(INPUT_SIZE * L1_SIZE);
+    read_i16s(bytes, &mut offset, squares);
+
+    let mut i = 0;
+    while i < GlobalInput::ALL.len() {
+        let input = GlobalInput::ALL[i];
+
+        if global_inputs.contains(input) {
+            let (_, weights) = globals.split_at_mut(input.offset() * L1_SIZE);
+            let (weights, _) = weights.split_at_mut(input.buckets() * L1_SIZE);
+            read_i16s(bytes, &mut offset, weights);
+        }
+
+        i += 1;
+    }
+
+    read_i16s(
+        bytes,
+        &mut offset,
+        &mut network.feature_transformer.biases.0,
+    );
+
+    let output_inputs = if hidden_size == 0 {
+        2 * activation.outputs()
+    } else {
+        let mut bucket = 0;
+        while bucket < output_buckets {
+            let mut neuron = 0;
+            while neuron < hidden_size {
+                let (_, weights) = network.hidden[bucket]
+                    .weights
+                    .0
+                    .split_at_mut(neuron * L1_SIZE * 2);
+                let (weights, _) = weights.split_at_mut(2 * activation.outputs());
+                read_i16s(bytes, &mut offset, weights);
+                neuron += 1;
+            }
+            bucket += 1;
+        }
+
+        let mut bucket = 0;
+        while bucket < output_buckets {
+            let (biases, _) = network.hidden[bucket].biases.0.split_at_mut(hidden_size);
+            read_i16s(bytes, &mut offset, biases);
+            bucket += 1;
+        }
+
+        hidden_size
+    };
+
+    let mut bucket = 0;
+    while bucket < output_buckets {
+        let (weights, _) = network.output[bucket].weights.0.split_at_mut(output_inputs);
+        read_i16s(bytes, &mut offset, weights);
+        bucket += 1;
+    }
+
+    let mut bucket = 0;
+    while bucket < output_buckets {
+        read_i16s(bytes, &mut offset, &mut network.output[bucket].biases.0);
+        bucket += 1;
+    }
+
+    Ok(network)
+}
+
+// what goes in the header of a net converted from bare parameters, as ids and
+// counts in the header's own terms. the defaults describe nets from before the header
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NetworkLayout {
+    pub output_buckets: u32,
+    pub activation: u32,
+    pub feature_set: u32,
+    pub hidden_size: u32,
+    pub global_inputs: u32,
+}
+
+impl Default for NetworkLayout {
+    fn default() -> Self {
+        Self {
+            output_buckets: 1,
+            activation: 0,
+            feature_set: 0,
+            hidden_size: 0,
+            global_inputs: 0,
+        }
+    }
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// puts a header with a checksum in front of bare parameters, and only
+// returns the result if it would load as a network
+pub fn add_header(parameters: &[u8], layout: NetworkLayout) -> Result<Vec<u8>, FormatError> {
+    let mut bytes = vec![0u8; HEADER_SIZE];
+
+    bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+    write_u32(&mut bytes, MAGIC.len(), FORMAT_VERSION);
+
+    for (_, offset, value) in ARCHITECTURE {
+        write_u32(&mut bytes, offset, value);
+    }
+
+    write_u32(&mut bytes, OUTPUT_BUCKETS_OFFSET, layout.output_buckets);
+    write_u32(&mut bytes, ACTIVATION_OFFSET, layout.activation);
+    write_u32(&mut bytes, FEATURE_SET_OFFSET, layout.feature_set);
+    write_u32(&mut bytes, HIDDEN_SIZE_OFFSET, layout.hidden_size);
+    write_u32(&mut bytes, GLOBAL_INPUTS_OFFSET, layout.global_inputs);
+
+    bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].copy_from_slice(&fnv1a(parameters).to_le_bytes());
+
+    bytes.extend_from_slice(parameters);
+
+    parse_network(&bytes)?;
+
+    Ok(bytes)
+}
+
+// chosen at build time, see build.rs
+pub(super) const EMBEDDED_NETWORK_BYTES: &[u8] = include_bytes!(env!("SPHX_EMBEDDED_NET"));
+
+static EMBEDDED_NETWORK: Network = match parse_network(EMBEDDED_NETWORK_BYTES) {
+    Ok(network) => network,
+    Err(_) => panic!("invalid embedded network"),
+};
+
+// networks loaded at runtime are leaked, as accumulators elsewhere may still refer to
+// them. this only happens when the user loads a net, so the leak stays tiny
+static NETWORK: AtomicPtr<Network> =
+    AtomicPtr::new(std::ptr::addr_of!(EMBEDDED_NETWORK).cast_mut());
+
+// tests can swap in a net for just the thread they run on, leaving the
+// embedded one to every other test running alongside
+#[cfg(test)]
+thread_local! {
+    static THREAD_NETWORK: std::cell::Cell<Option<&'static Network>> =
+        const { std::cell::Cell::new(None) };
+}
+
+#[must_use]
+pub fn network() -> &'static Network {
+    #[cfg(test)]
+    if let Some(network) = THREAD_NETWORK.get() {
+        return network;
+    }
+
+    // SAFETY: always points to either the embedded network or a leaked loaded one
+    unsafe { &*NETWORK.load(Ordering::Relaxed) }
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(std::io::Error),
+    Format(FormatError),
 }
 
-pub const NETWORK: Network = unsafe { std::mem::transmute(*include_bytes!("net004.nnue")) };
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Io(err) => write!(f, "Failed to read network: {}", err),
+            NetworkError::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// nothing is replaced unless the whole file is valid
+pub fn load_network(path: &str) -> Result<(), NetworkError> {
+    let bytes = std::fs::read(path).map_err(NetworkError::Io)?;
+    let network = parse_network(&bytes).map_err(NetworkError::Format)?;
+
+    NETWORK.store(Box::leak(Box::new(network)), Ordering::Relaxed);
+
+    Ok(())
+}
+
+#[must_use]
+pub fn network_hash() -> u64 {
+    network().hash
+}
+
+#[must_use]
+pub const fn network_size() -> usize {
+    std::mem::size_of::<Network>()
+}
+
+#[cfg(test)]
+pub fn use_thread_network(bytes: &[u8]) {
+    let network = parse_network(bytes).unwrap();
+    THREAD_NETWORK.set(Some(Box::leak(Box::new(network))));
+}
+
+// the embedded net with the halfmove clock as an extra input,
+// each bucket's weights all that bucket's index plus one
+#[cfg(test)]
+#[must_use]
+pub fn halfmove_network_bytes() -> Vec<u8> {
+    let squares_end = HEADER_SIZE + INPUT_SIZE * L1_SIZE * 2;
+
+    let mut bytes = EMBEDDED_NETWORK_BYTES[..squares_end].to_vec();
+    bytes[GLOBAL_INPUTS_OFFSET] = 1 << GlobalInput::Halfmoves as u8;
+    bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].fill(0);
+
+    for bucket in 0..GlobalInput::Halfmoves.buckets() as i16 {
+        for _ in 0..L1_SIZE {
+            bytes.extend_from_slice(&(bucket + 1).to_le_bytes());
+        }
+    }
+
+    bytes.extend_from_slice(&EMBEDDED_NETWORK_BYTES[squares_end..]);
+    bytes
+}
+
+pub fn use_embedded_network() {
+    NETWORK.store(
+        std::ptr::addr_of!(EMBEDDED_NETWORK).cast_mut(),
+        Ordering::Relaxed,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_header, file_size, halfmove_network_bytes, parse_network, Activation, FeatureSet,
+        FormatError, GlobalInput, GlobalInputs, NetworkLayout, ACTIVATION_OFFSET, CHECKSUM_OFFSET,
+        FEATURE_SET_OFFSET, GLOBAL_INPUTS_OFFSET, HIDDEN_SIZE_OFFSET, INPUT_SIZE, L1_Q, L1_SIZE,
+        MAX_HIDDEN_SIZE, OUTPUT_BUCKETS_OFFSET, OUTPUT_Q, SCALE,
+    };
+    use crate::core::Color;
+    use crate::hash::Symmetry;
+    use crate::nnue::{evaluate_once, evaluate_with, AccumulatorPair};
+    use crate::position::Position;
+
+    const EMBEDDED: &[u8] = super::EMBEDDED_NETWORK_BYTES;
+
+    #[test]
+    fn header_validated() {
+        assert!(parse_network(EMBEDDED).is_ok());
+
+        assert_eq!(
+            parse_network(&EMBEDDED[..10]).err(),
+            Some(FormatError::TooShort(10))
+        );
+
+        let mut bytes = EMBEDDED.to_vec();
+        bytes[0] ^= 1;
+        assert_eq!(parse_network(&bytes).err(), Some(FormatError::BadMagic));
+
+        // a network with a different l1 size
+        let mut bytes = EMBEDDED.to_vec();
+        bytes[16] = 128;
+        assert!(matches!(
+            parse_network(&bytes),
+            Err(FormatError::WrongArchitecture {
+                field: "l1 size",
+                found: 128,
+                ..
+            })
+        ));
+
+        let mut bytes = EMBEDDED.to_vec();
+        bytes.push(0);
+        assert_eq!(
+            parse_network(&bytes).err(),
+            Some(FormatError::WrongSize {
+                expected: EMBEDDED.len(),
+                found: EMBEDDED.len() + 1
+            })
+        );
+
+        let mut bytes = EMBEDDED.to_vec();
+        bytes[OUTPUT_BUCKETS_OFFSET] = 0;
+        assert_eq!(
+            parse_network(&bytes).err(),
+            Some(FormatError::UnsupportedOutputBuckets(0))
+        );
+    }
+
+    #[test]
+    fn buckets_read_from_header() {
+        const BUCKETS: usize = 4;
+
+        // the embedded net's single head in every bucket, each with its own bias
+        let head_start =
+            file_size(1, Activation::ClippedReLU, 0, GlobalInputs::default()) - 2 - 4 * L1_SIZE;
+        let weights = &EMBEDDED
+            [head_start..file_size(1, Activation::ClippedReLU, 0, GlobalInputs::default()) - 2];
+
+        let mut bytes = EMBEDDED[..head_start].to_vec();
+        bytes[OUTPUT_BUCKETS_OFFSET] = BUCKETS as u8;
+        bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].fill(0);
+
+        for _ in 0..BUCKETS {
+            bytes.extend_from_slice(weights);
+        }
+
+        for bucket in 0..BUCKETS as i16 {
+            bytes.extend_from_slice(&(bucket * 100).to_le_bytes());
+        }
+
+        let network = parse_network(&bytes).unwrap();
+
+        assert_eq!(network.output_buckets, BUCKETS);
+        assert_eq!(network.output[3].weights.0, network.output[0].weights.0);
+        assert_eq!(network.output[3].biases.0[0], 300);
+
+        assert_eq!(network.output_bucket(0), 0);
+        assert_eq!(network.output_bucket(12), 0);
+        assert_eq!(network.output_bucket(13), 1);
+        assert_eq!(network.output_bucket(49), BUCKETS - 1);
+    }
+
+    #[test]
+    fn checksum_optional() {
+        let mut bytes = EMBEDDED.to_vec();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            parse_network(&bytes),
+            Err(FormatError::ChecksumMismatch { .. })
+        ));
+
+        bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].fill(0);
+        assert!(parse_network(&bytes).is_ok());
+    }
+
+    #[test]
+    fn header_added_to_bare_parameters() {
+        let bare = &EMBEDDED[super::HEADER_SIZE..];
+
+        let converted = add_header(bare, NetworkLayout::default()).unwrap();
+        assert_eq!(converted, EMBEDDED);
+
+        let layout = NetworkLayout {
+            output_buckets: 2,
+            ..NetworkLayout::default()
+        };
+        assert!(matches!(
+            add_header(bare, layout),
+            Err(FormatError::WrongSize { .. })
+        ));
+    }
+
+    // the embedded net's parameters with another activation, which makes no
+    // sense as a net but goes through the same arithmetic as a real one
+    fn with_activation(id: u32) -> Vec<u8> {
+        let activation = Activation::from_id(id).unwrap();
+        let head_start =
+            file_size(1, Activation::ClippedReLU, 0, GlobalInputs::default()) - 2 - 4 * L1_SIZE;
+
+        let mut bytes = EMBEDDED[..head_start].to_vec();
+        bytes[ACTIVATION_OFFSET] = id as u8;
+        bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].fill(0);
+
+        bytes.extend_from_slice(&EMBEDDED[head_start..head_start + 4 * activation.outputs()]);
+        bytes.extend_from_slice(&EMBEDDED[EMBEDDED.len() - 2..]);
+
+        bytes
+    }
+
+    #[test]
+    fn activations_match_scalar() {
+        let pos = Position::from_fen("x5o/1xx4/2ooo2/3x3/7/o6/6x x 0 1").unwrap();
+
+        let mut accs = AccumulatorPair::default();
+        accs.reset(&pos, Symmetry::IDENTITY);
+
+        let clip = |v: i16| i32::from(v.clamp(0, L1_Q as i16));
+
+        for id in 0..3 {
+            let network = parse_network(&with_activation(id)).unwrap();
+            let activation = network.activation;
+            let weight = |i: usize| i32::from(network.output[0].weights.0[i]);
+
+            let perspective = |values: &[i16; L1_SIZE], offset: usize| -> i32 {
+                match activation {
+                    Activation::ClippedReLU => (0..L1_SIZE)
+                        .map(|i| clip(values[i]) * weight(offset + i))
+                        .sum(),
+                    Activation::SquaredClippedReLU => (0..L1_SIZE)
+                        .map(|i| clip(values[i]) * clip(values[i]) * weight(offset + i))
+                        .sum(),
+                    Activation::PairwiseClippedReLU => (0..L1_SIZE / 2)
+                        .map(|i| {
+                            clip(values[i]) * clip(values[i + L1_SIZE / 2]) * weight(offset + i)
+                        })
+                        .sum(),
+                }
+            };
+
+            let sum = (perspective(&accs.red().values.0, 0)
+                + perspective(&accs.blue().values.0, activation.outputs()))
+                / activation.extra_scale();
+            let expected =
+                (sum + i32::from(network.output[0].biases.0[0])) * SCALE / (L1_Q * OUTPUT_Q);
+
+            assert_eq!(evaluate_with(&network, &accs, &pos), expected);
+
+            if activation == Activation::ClippedReLU {
+                assert_eq!(expected, evaluate_once(&pos));
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_features_ignore_symmetry() {
+        let mut bytes = EMBEDDED.to_vec();
+
+        bytes[FEATURE_SET_OFFSET] = 2;
+        assert_eq!(
+            parse_network(&bytes).err(),
+            Some(FormatError::UnknownFeatureSet(2))
+        );
+
+        bytes[FEATURE_SET_OFFSET] = 1;
+        let network = parse_network(&bytes).unwrap();
+        assert_eq!(network.features, FeatureSet::SymmetryCanonical);
+
+        let pos = Position::from_fen("x5o/1xx4/2ooo2/3x3/2-4/o6/6x x 0 1").unwrap();
+
+        let evals = Symmetry::all()
+            .map(|sym| {
+                let mirrored = Position::from_boards(
+                    sym.apply_bitboard(pos.red_occupancy()),
+                    sym.apply_bitboard(pos.blue_occupancy()),
+                    sym.apply_bitboard(pos.gaps()),
+                    Color::RED,
+                    0,
+                    1,
+                );
+
+                let mut accs = AccumulatorPair::default();
+                accs.reset(&mirrored, network.features.orientation(&mirrored));
+
+                evaluate_with(&network, &accs, &mirrored)
+            })
+            .collect::<Vec<_>>();
+
+        assert!(evals.iter().all(|&eval| eval == evals[0]));
+    }
+
+    #[test]
+    fn hidden_layer_matches_scalar() {
+        const HIDDEN_SIZE: usize = 16;
+
+        let mut bytes = EMBEDDED.to_vec();
+        bytes[HIDDEN_SIZE_OFFSET] = MAX_HIDDEN_SIZE as u8 + 1;
+        assert_eq!(
+            parse_network(&bytes).err(),
+            Some(FormatError::UnsupportedHiddenSize(
+                MAX_HIDDEN_SIZE as u32 + 1
+            ))
+        );
+
+        // the embedded net's accumulators feeding arbitrary but deterministic layers
+        let head_start =
+            file_size(1, Activation::ClippedReLU, 0, GlobalInputs::default()) - 2 - 4 * L1_SIZE;
+
+        let mut bytes = EMBEDDED[..head_start].to_vec();
+        bytes[HIDDEN_SIZE_OFFSET] = HIDDEN_SIZE as u8;
+        bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].fill(0);
+
+        let mut seed = 0x1234_5678_u32;
+        let mut next = |range: i16| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as i16 % range
+        };
+
+        let parameters = HIDDEN_SIZE * (2 * L1_SIZE + 1) + HIDDEN_SIZE + 1;
+        for _ in 0..parameters {
+            bytes.extend_from_slice(&next(64).to_le_bytes());
+        }
+
+        let network = parse_network(&bytes).unwrap();
+        assert_eq!(network.hidden_size, HIDDEN_SIZE);
+
+        let hidden = &network.hidden[0];
+        let output = &network.output[0];
+
+        for fen in [
+            "x5o/1xx4/2ooo2/3x3/7/o6/6x x 0 1",
+            "x5o/1xx4/2ooo2/3x3/7/o6/6x o 0 1",
+            "xxxxxxx/ooooooo/xxxxxxx/3-3/7/ooooooo/7 x 0 1",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+
+            let mut accs = AccumulatorPair::default();
+            accs.reset(&pos, Symmetry::IDENTITY);
+
+            let (ours, theirs) = if pos.side_to_move() == Color::RED {
+                (accs.red(), accs.blue())
+            } else {
+                (accs.blue(), accs.red())
+            };
+
+            let inputs = ours.values.0.iter().chain(theirs.values.0.iter());
+
+            let sum: i32 = (0..HIDDEN_SIZE)
+                .map(|neuron| {
+                    let weights = hidden.output_weights(neuron);
+                    let value = inputs
+                        .clone()
+                        .zip(weights)
+                        .map(|(&v, &w)| i32::from(v.clamp(0, L1_Q as i16)) * i32::from(w))
+                        .sum::<i32>()
+                        + i32::from(hidden.biases.0[neuron]);
+
+                    (value / OUTPUT_Q).clamp(0, L1_Q) * i32::from(output.weights.0[neuron])
+                })
+                .sum();
+
+            let expected = (sum + i32::from(output.biases.0[0])) * SCALE / (L1_Q * OUTPUT_Q);
+            assert_eq!(evaluate_with(&network, &accs, &pos), expected);
+        }
+    }
+
+    #[test]
+    fn global_inputs_read_from_header() {
+        let mut bytes = EMBEDDED.to_vec();
+        bytes[GLOBAL_INPUTS_OFFSET] = 8;
+        assert_eq!(
+            parse_network(&bytes).err(),
+            Some(FormatError::UnknownGlobalInputs(8))
+        );
+
+        let network = parse_network(&halfmove_network_bytes()).unwrap();
+        let weights = &network.feature_transformer.weights.0;
+
+        assert!(network.global_inputs.contains(GlobalInput::Halfmoves));
+        assert!(!network.global_inputs.contains(GlobalInput::Empties));
+
+        let halfmoves_start = (INPUT_SIZE + GlobalInput::Halfmoves.offset()) * L1_SIZE;
+        assert!(weights[INPUT_SIZE * L1_SIZE..halfmoves_start]
+            .iter()
+            .all(|&w| w == 0));
+        assert_eq!(weights[halfmoves_start], 1);
+        assert_eq!(
+            *weights.last().unwrap(),
+            GlobalInput::Halfmoves.buckets() as i16
+        );
+
+        assert_eq!(
+            network.feature_transformer.biases.0,
+            parse_network(EMBEDDED)
+                .unwrap()
+                .feature_transformer
+                .biases
+                .0
+        );
+    }
+}
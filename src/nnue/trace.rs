@@ -0,0 +1,127 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::{Color, Score, Square};
+use crate::nnue::features::GlobalInput;
+use crate::nnue::network::network;
+use crate::nnue::{evaluate_with, AccumulatorPair, FeaturePair, Input};
+use crate::position::Position;
+use arrayvec::ArrayVec;
+
+// what each input adds to a position's eval, from the side to move's point of view
+pub struct EvalTrace {
+    pub eval: Score,
+    // how much the eval drops without a square's features, by Square::idx. None for
+    // empty squares, which have none
+    pub squares: [Option<Score>; Square::N_SQUARES],
+    // each global input the net uses, with the side to move's bucket
+    pub globals: ArrayVec<(GlobalInput, usize, Score), { GlobalInput::ALL.len() }>,
+}
+
+// features are removed from the accumulators rather than the board, so the rest of
+// the position keeps its orientation and output bucket
+#[must_use]
+pub fn trace_eval(pos: &Position) -> EvalTrace {
+    let network = network();
+
+    let symmetry = network.features.orientation(pos);
+
+    let mut accs = AccumulatorPair::default();
+    accs.reset(pos, symmetry);
+
+    let eval = evaluate_with(network, &accs, pos);
+
+    let contribution = |features: FeaturePair| {
+        let mut without = accs;
+        without.deactivate(features);
+        eval - evaluate_with(network, &without, pos)
+    };
+
+    let squares = std::array::from_fn(|idx| {
+        let sq = Square::from_coords((idx / 7) as u32, (idx % 7) as u32);
+
+        let input = if pos.gap_at(sq) {
+            Input::Gap(sq)
+        } else {
+            match pos.color_at(sq) {
+                Color::NONE => return None,
+                c => Input::Stone(c, sq),
+            }
+        };
+
+        Some(contribution(input.features(symmetry)))
+    });
+
+    let globals = GlobalInput::ALL
+        .into_iter()
+        .zip(accs.globals)
+        .filter_map(|(input, features)| {
+            let bucket = input.bucket(pos)[pos.side_to_move().idx()];
+            features.map(|features| (input, bucket, contribution(features)))
+        })
+        .collect();
+
+    EvalTrace {
+        eval,
+        squares,
+        globals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::trace_eval;
+    use crate::core::Square;
+    use crate::nnue::evaluate_once;
+    use crate::position::Position;
+
+    #[test]
+    fn contributions_match_removing_stones() {
+        let pos = Position::from_fen("x5o/1xx4/2ooo2/3x3/2-4/o6/6x o 0 1").unwrap();
+        let trace = trace_eval(&pos);
+
+        assert_eq!(trace.eval, evaluate_once(&pos));
+        assert!(trace.globals.is_empty());
+
+        for idx in 0..Square::N_SQUARES {
+            let sq = Square::from_coords((idx / 7) as u32, (idx % 7) as u32);
+
+            if pos.occupancy().get(sq) {
+                // the embedded net has one output bucket and no symmetry, so
+                // taking the stone off the board is the same as removing its feature
+                let without = Position::from_boards(
+                    pos.red_occupancy() & !sq.bit(),
+                    pos.blue_occupancy() & !sq.bit(),
+                    pos.gaps(),
+                    pos.side_to_move(),
+                    0,
+                    1,
+                );
+
+                assert_eq!(
+                    trace.squares[idx],
+                    Some(trace.eval - evaluate_once(&without))
+                );
+            } else if pos.gap_at(sq) {
+                assert!(trace.squares[idx].is_some());
+            } else {
+                assert_eq!(trace.squares[idx], None);
+            }
+        }
+    }
+}
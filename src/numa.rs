@@ -0,0 +1,137 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// everything here is best effort. on anything but linux, or when the kernel
+// refuses, threads and memory are simply left wherever the os puts them
+
+#[cfg(target_os = "linux")]
+const MPOL_INTERLEAVE: libc::c_int = 3;
+
+// parses sysfs cpu and node lists such as "0-3,8-11"
+#[must_use]
+fn parse_list(list: &str) -> Vec<usize> {
+    list.trim()
+        .split(',')
+        .filter(|range| !range.is_empty())
+        .filter_map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Some(start.parse::<usize>().ok()?..=end.parse::<usize>().ok()?)
+        })
+        .flatten()
+        .collect()
+}
+
+#[must_use]
+fn read_list(path: &str) -> Option<Vec<usize>> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|list| parse_list(&list))
+        .filter(|list| !list.is_empty())
+}
+
+#[must_use]
+pub fn numa_nodes() -> Vec<usize> {
+    read_list("/sys/devices/system/node/online").unwrap_or_else(|| vec![0])
+}
+
+// cpus to pin search threads to, in order. consecutive threads go to different
+// nodes, so that a handful of threads spreads over all memory controllers
+#[must_use]
+pub fn thread_cpus() -> Vec<usize> {
+    let node_cpus = numa_nodes()
+        .into_iter()
+        .filter_map(|node| read_list(&format!("/sys/devices/system/node/node{}/cpulist", node)))
+        .collect::<Vec<_>>();
+
+    let max_cpus = node_cpus.iter().map(Vec::len).max().unwrap_or(0);
+
+    (0..max_cpus)
+        .flat_map(|idx| {
+            node_cpus
+                .iter()
+                .filter_map(move |cpus| cpus.get(idx).copied())
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) {
+    // SAFETY: cpu_set_t is plain data, and the set is only read by the kernel
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        let _ = libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw const set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) {}
+
+// spreads the pages of an allocation over all nodes. must be called before they are touched
+#[cfg(target_os = "linux")]
+pub fn interleave<T>(memory: &[T]) {
+    const MAX_NODES: usize = 64;
+
+    let nodes = numa_nodes();
+    if nodes.len() < 2 {
+        return;
+    }
+
+    let mask = nodes
+        .iter()
+        .filter(|&&node| node < MAX_NODES)
+        .fold(0u64, |mask, &node| mask | (1 << node));
+
+    // mbind wants page aligned addresses
+    let page_size = 4096usize;
+    let start = memory.as_ptr() as usize;
+    let aligned_start = start.next_multiple_of(page_size);
+    let end = start + std::mem::size_of_val(memory);
+
+    if end <= aligned_start {
+        return;
+    }
+
+    // SAFETY: the range lies within the allocation, and mbind only changes its placement
+    unsafe {
+        let _ = libc::syscall(
+            libc::SYS_mbind,
+            aligned_start,
+            end - aligned_start,
+            MPOL_INTERLEAVE,
+            &raw const mask,
+            MAX_NODES + 1,
+            0,
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn interleave<T>(_memory: &[T]) {}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_list;
+
+    #[test]
+    fn sysfs_lists_parse() {
+        assert_eq!(parse_list("0\n"), vec![0]);
+        assert_eq!(parse_list("0-3,8-9"), vec![0, 1, 2, 3, 8, 9]);
+        assert_eq!(parse_list(""), Vec::<usize>::new());
+    }
+}
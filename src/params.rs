@@ -0,0 +1,462 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Score;
+use std::str::FromStr;
+
+// every knob of the search and time management, owned by the searcher
+#[derive(Debug, Copy, Clone)]
+pub struct SearchParams {
+    pub aspiration_min_depth: i32,
+    pub aspiration_initial_delta: Score,
+
+    // nodes this far per ply of depth below alpha are handed to the qsearch,
+    // and given up on if it agrees that nothing gets back to alpha
+    pub razor_max_depth: i32,
+    pub razor_margin: Score,
+
+    // passing is tried at reduced depth in nodes that already look good, as long as
+    // the board isn't so full that moving could be worse than passing
+    pub nmp_min_depth: i32,
+    pub nmp_base_reduction: i32,
+    pub nmp_depth_divisor: i32,
+    pub nmp_min_empties: u32,
+
+    // captureless moves are skipped near the leaves when even this much
+    // per ply of depth left on top of the static eval can't raise alpha
+    pub fp_max_depth: i32,
+    pub fp_margin: Score,
+
+    // captures that beat beta by this margin in a reduced search are taken
+    // as proof that the full search would cut off too
+    pub probcut_min_depth: i32,
+    pub probcut_margin: Score,
+    pub probcut_reduction: i32,
+    pub probcut_min_flips: u32,
+
+    // leaves keep resolving captures this big, as the eval swings too hard on them to trust.
+    // crowded boards always have more of them, so chains are cut off after a few plies
+    pub qs_min_flips: u32,
+    pub qs_max_depth: i32,
+
+    // secondary multipv lines lose a ply of depth for every this many cp they trail the best line by
+    pub multi_pv_reduction_margin: Score,
+    pub multi_pv_max_reduction: i32,
+
+    pub tm_default_moves_to_go: u64,
+    pub tm_increment_multiplier: f64,
+    // the allocation is only a target between iterations, the search itself may run this much longer
+    pub tm_hard_limit_scale: f64,
+    // and never past this much of what is left on the clock
+    pub tm_max_time_fraction: f64,
+    // scales the allocation by how many iterations in a row the best move has survived.
+    // a move that keeps changing gets extra time, a settled one lets the search stop early
+    pub tm_stability_scales: [f64; 5],
+    // scales the allocation by the share of root nodes spent on the best move, from
+    // this much for a move that took every node, up to its inverse for one that took none
+    pub tm_node_fraction_base: f64,
+    pub tm_node_fraction_scale: f64,
+    // each iteration takes about this many times as long as the one before it
+    pub tm_iteration_growth: f64,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Preset::Default.params()
+    }
+}
+
+// groups of tunables for users who don't want to set them individually.
+// narrower windows and shorter thinking trade accuracy for speed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Preset {
+    Fast,
+    Default,
+    Thorough,
+}
+
+impl Preset {
+    pub const ALL: [Self; 3] = [Self::Fast, Self::Default, Self::Thorough];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fast => "Fast",
+            Self::Default => "Default",
+            Self::Thorough => "Thorough",
+        }
+    }
+
+    #[must_use]
+    pub fn params(self) -> SearchParams {
+        match self {
+            Self::Fast => SearchParams {
+                aspiration_min_depth: 3,
+                aspiration_initial_delta: 15,
+                tm_default_moves_to_go: 40,
+                tm_increment_multiplier: 0.4,
+                ..Self::Default.params()
+            },
+            Self::Default => SearchParams {
+                aspiration_min_depth: 4,
+                aspiration_initial_delta: 25,
+                razor_max_depth: 2,
+                razor_margin: 300,
+                nmp_min_depth: 3,
+                nmp_base_reduction: 2,
+                nmp_depth_divisor: 4,
+                nmp_min_empties: 8,
+                fp_max_depth: 2,
+                fp_margin: 150,
+                probcut_min_depth: 5,
+                probcut_margin: 200,
+                probcut_reduction: 4,
+                probcut_min_flips: 3,
+                qs_min_flips: 2,
+                qs_max_depth: 4,
+                multi_pv_reduction_margin: 100,
+                multi_pv_max_reduction: 4,
+                tm_default_moves_to_go: 30,
+                tm_increment_multiplier: 0.5,
+                tm_hard_limit_scale: 3.0,
+                tm_max_time_fraction: 0.75,
+                tm_stability_scales: [2.0, 1.3, 1.0, 0.85, 0.7],
+                tm_node_fraction_base: 1.5,
+                tm_node_fraction_scale: 1.35,
+                tm_iteration_growth: 2.0,
+            },
+            Self::Thorough => SearchParams {
+                aspiration_min_depth: 5,
+                aspiration_initial_delta: 40,
+                tm_default_moves_to_go: 20,
+                tm_increment_multiplier: 0.75,
+                ..Self::Default.params()
+            },
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+// spsa only tunes integers, so fractional params are exposed scaled by 100
+#[cfg(feature = "tune")]
+pub struct Tunable {
+    pub name: &'static str,
+    pub min: i64,
+    pub max: i64,
+    pub step: f64,
+    pub get: fn(&SearchParams) -> i64,
+    pub set: fn(&mut SearchParams, i64),
+}
+
+// every field of SearchParams, with each stability scale exposed on its own
+#[cfg(feature = "tune")]
+pub const TUNABLES: [Tunable; 30] = [
+    Tunable {
+        name: "aspiration_min_depth",
+        min: 1,
+        max: 10,
+        step: 0.5,
+        get: |params| i64::from(params.aspiration_min_depth),
+        set: |params, value| params.aspiration_min_depth = value as i32,
+    },
+    Tunable {
+        name: "aspiration_initial_delta",
+        min: 5,
+        max: 100,
+        step: 5.0,
+        get: |params| i64::from(params.aspiration_initial_delta),
+        set: |params, value| params.aspiration_initial_delta = value as Score,
+    },
+    Tunable {
+        name: "razor_max_depth",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |params| i64::from(params.razor_max_depth),
+        set: |params, value| params.razor_max_depth = value as i32,
+    },
+    Tunable {
+        name: "razor_margin",
+        min: 100,
+        max: 800,
+        step: 25.0,
+        get: |params| i64::from(params.razor_margin),
+        set: |params, value| params.razor_margin = value as Score,
+    },
+    Tunable {
+        name: "nmp_min_depth",
+        min: 1,
+        max: 8,
+        step: 0.5,
+        get: |params| i64::from(params.nmp_min_depth),
+        set: |params, value| params.nmp_min_depth = value as i32,
+    },
+    Tunable {
+        name: "nmp_base_reduction",
+        min: 1,
+        max: 5,
+        step: 0.5,
+        get: |params| i64::from(params.nmp_base_reduction),
+        set: |params, value| params.nmp_base_reduction = value as i32,
+    },
+    Tunable {
+        name: "nmp_depth_divisor",
+        min: 2,
+        max: 8,
+        step: 0.5,
+        get: |params| i64::from(params.nmp_depth_divisor),
+        set: |params, value| params.nmp_depth_divisor = value as i32,
+    },
+    Tunable {
+        name: "nmp_min_empties",
+        min: 0,
+        max: 30,
+        step: 1.5,
+        get: |params| i64::from(params.nmp_min_empties),
+        set: |params, value| params.nmp_min_empties = value as u32,
+    },
+    Tunable {
+        name: "fp_max_depth",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |params| i64::from(params.fp_max_depth),
+        set: |params, value| params.fp_max_depth = value as i32,
+    },
+    Tunable {
+        name: "fp_margin",
+        min: 50,
+        max: 400,
+        step: 15.0,
+        get: |params| i64::from(params.fp_margin),
+        set: |params, value| params.fp_margin = value as Score,
+    },
+    Tunable {
+        name: "probcut_min_depth",
+        min: 3,
+        max: 10,
+        step: 0.5,
+        get: |params| i64::from(params.probcut_min_depth),
+        set: |params, value| params.probcut_min_depth = value as i32,
+    },
+    Tunable {
+        name: "probcut_margin",
+        min: 50,
+        max: 500,
+        step: 20.0,
+        get: |params| i64::from(params.probcut_margin),
+        set: |params, value| params.probcut_margin = value as Score,
+    },
+    Tunable {
+        name: "probcut_reduction",
+        min: 2,
+        max: 8,
+        step: 0.5,
+        get: |params| i64::from(params.probcut_reduction),
+        set: |params, value| params.probcut_reduction = value as i32,
+    },
+    Tunable {
+        name: "probcut_min_flips",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |params| i64::from(params.probcut_min_flips),
+        set: |params, value| params.probcut_min_flips = value as u32,
+    },
+    Tunable {
+        name: "qs_min_flips",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |params| i64::from(params.qs_min_flips),
+        set: |params, value| params.qs_min_flips = value as u32,
+    },
+    Tunable {
+        name: "qs_max_depth",
+        min: 1,
+        max: 10,
+        step: 0.5,
+        get: |params| i64::from(params.qs_max_depth),
+        set: |params, value| params.qs_max_depth = value as i32,
+    },
+    Tunable {
+        name: "multi_pv_reduction_margin",
+        min: 25,
+        max: 300,
+        step: 10.0,
+        get: |params| i64::from(params.multi_pv_reduction_margin),
+        set: |params, value| params.multi_pv_reduction_margin = value as Score,
+    },
+    Tunable {
+        name: "multi_pv_max_reduction",
+        min: 1,
+        max: 8,
+        step: 0.5,
+        get: |params| i64::from(params.multi_pv_max_reduction),
+        set: |params, value| params.multi_pv_max_reduction = value as i32,
+    },
+    Tunable {
+        name: "tm_default_moves_to_go",
+        min: 10,
+        max: 60,
+        step: 3.0,
+        get: |params| params.tm_default_moves_to_go as i64,
+        set: |params, value| params.tm_default_moves_to_go = value as u64,
+    },
+    Tunable {
+        name: "tm_increment_multiplier",
+        min: 10,
+        max: 100,
+        step: 5.0,
+        get: |params| (params.tm_increment_multiplier * 100.0).round() as i64,
+        set: |params, value| params.tm_increment_multiplier = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_hard_limit_scale",
+        min: 150,
+        max: 500,
+        step: 15.0,
+        get: |params| (params.tm_hard_limit_scale * 100.0).round() as i64,
+        set: |params, value| params.tm_hard_limit_scale = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_max_time_fraction",
+        min: 30,
+        max: 95,
+        step: 3.0,
+        get: |params| (params.tm_max_time_fraction * 100.0).round() as i64,
+        set: |params, value| params.tm_max_time_fraction = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_stability_scale_0",
+        min: 30,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_stability_scales[0] * 100.0).round() as i64,
+        set: |params, value| params.tm_stability_scales[0] = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_stability_scale_1",
+        min: 30,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_stability_scales[1] * 100.0).round() as i64,
+        set: |params, value| params.tm_stability_scales[1] = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_stability_scale_2",
+        min: 30,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_stability_scales[2] * 100.0).round() as i64,
+        set: |params, value| params.tm_stability_scales[2] = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_stability_scale_3",
+        min: 30,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_stability_scales[3] * 100.0).round() as i64,
+        set: |params, value| params.tm_stability_scales[3] = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_stability_scale_4",
+        min: 30,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_stability_scales[4] * 100.0).round() as i64,
+        set: |params, value| params.tm_stability_scales[4] = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_node_fraction_base",
+        min: 100,
+        max: 300,
+        step: 10.0,
+        get: |params| (params.tm_node_fraction_base * 100.0).round() as i64,
+        set: |params, value| params.tm_node_fraction_base = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_node_fraction_scale",
+        min: 100,
+        max: 200,
+        step: 5.0,
+        get: |params| (params.tm_node_fraction_scale * 100.0).round() as i64,
+        set: |params, value| params.tm_node_fraction_scale = value as f64 / 100.0,
+    },
+    Tunable {
+        name: "tm_iteration_growth",
+        min: 100,
+        max: 400,
+        step: 10.0,
+        get: |params| (params.tm_iteration_growth * 100.0).round() as i64,
+        set: |params, value| params.tm_iteration_growth = value as f64 / 100.0,
+    },
+];
+
+// name, type, default, min, max, step and learning rate, as openbench expects them
+#[cfg(feature = "tune")]
+pub fn print_spsa_inputs() {
+    let defaults = SearchParams::default();
+
+    for tunable in &TUNABLES {
+        println!(
+            "{}, int, {}, {}, {}, {}, 0.002",
+            tunable.name,
+            (tunable.get)(&defaults),
+            tunable.min,
+            tunable.max,
+            tunable.step
+        );
+    }
+}
+
+#[cfg(all(test, feature = "tune"))]
+mod tests {
+    use crate::params::{SearchParams, TUNABLES};
+
+    #[test]
+    fn tunable_defaults_in_range() {
+        let defaults = SearchParams::default();
+
+        for tunable in &TUNABLES {
+            let value = (tunable.get)(&defaults);
+            assert!(
+                (tunable.min..=tunable.max).contains(&value),
+                "{} default {} outside {}..={}",
+                tunable.name,
+                value,
+                tunable.min,
+                tunable.max
+            );
+
+            let mut params = defaults;
+            (tunable.set)(&mut params, value);
+            assert_eq!((tunable.get)(&params), value, "{}", tunable.name);
+        }
+    }
+}
@@ -16,12 +16,12 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::ataxx_move::AtaxxMove;
 use crate::movegen::{fill_move_list, MoveList};
 use crate::position::Position;
-use std::time::Instant;
 
 #[must_use]
-fn do_perft(pos: &mut Position, depth: i32) -> usize {
+fn do_perft(pos: &mut Position, depth: i32) -> u64 {
     if depth <= 0 {
         return 1;
     }
@@ -30,10 +30,10 @@ fn do_perft(pos: &mut Position, depth: i32) -> usize {
     fill_move_list(&mut moves, pos);
 
     if depth == 1 {
-        return moves.len();
+        return moves.len() as u64;
     }
 
-    let mut total = 0usize;
+    let mut total = 0u64;
 
     for mv in moves {
         pos.apply_move::<true, false>(mv, None);
@@ -44,45 +44,36 @@ fn do_perft(pos: &mut Position, depth: i32) -> usize {
     total
 }
 
-pub fn perft(pos: &mut Position, depth: i32) {
-    let total = do_perft(pos, depth);
-    println!("{}", total);
+#[must_use]
+pub fn perft(pos: &mut Position, depth: i32) -> u64 {
+    do_perft(pos, depth)
 }
 
-pub fn split_perft(pos: &mut Position, depth: i32) {
-    let start = Instant::now();
-
+// leaf counts below each legal move, in generation order
+#[must_use]
+pub fn split_perft(pos: &mut Position, depth: i32) -> Vec<(AtaxxMove, u64)> {
     let mut moves = MoveList::new();
     fill_move_list(&mut moves, pos);
 
-    let mut total = 0usize;
-
-    for mv in moves {
-        pos.apply_move::<true, false>(mv, None);
-
-        let value = do_perft(pos, depth - 1);
-
-        total += value;
-        println!("{}\t{}", mv, value);
+    moves
+        .iter()
+        .map(|&mv| {
+            pos.apply_move::<true, false>(mv, None);
+            let count = do_perft(pos, depth - 1);
+            pos.pop_move::<false>(None);
 
-        pos.pop_move::<false>(None);
-    }
-
-    let time = start.elapsed().as_secs_f64();
-    let nps = (total as f64 / time) as usize;
-
-    println!();
-    println!("total {}", total);
-    println!("{} nps", nps);
+            (mv, count)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::perft::do_perft;
+    use crate::perft::{perft, split_perft};
     use crate::position::Position;
 
     #[rustfmt::skip]
-    const PERFT4_POSITIONS: &[(&str, &[usize])] = &[
+    const PERFT4_POSITIONS: &[(&str, &[u64])] = &[
         ("7/7/7/7/7/7/7 x 0 1", &[1, 0, 0, 0, 0]),
         ("7/7/7/7/7/7/7 o 0 1", &[1, 0, 0, 0, 0]),
         ("7/7/7/7/ooooooo/ooooooo/xxxxxxx o 0 1", &[1, 75, 249, 14270, 452_980]),
@@ -92,7 +83,7 @@ mod tests {
     ];
 
     #[rustfmt::skip]
-    const PERFT5_POSITIONS: &[(&str,  &[usize])] = &[
+    const PERFT5_POSITIONS: &[(&str,  &[u64])] = &[
         ("x5o/7/7/7/7/7/o5x x 0 1", &[1, 16, 256, 6460, 155_888, 4_752_668]),
         ("x5o/7/7/7/7/7/o5x o 0 1", &[1, 16, 256, 6460, 155_888, 4_752_668]),
         ("x5o/7/2-1-2/7/2-1-2/7/o5x x 0 1", &[1, 14, 196, 4184, 86528, 2_266_352]),
@@ -108,22 +99,31 @@ mod tests {
     ];
 
     #[rustfmt::skip]
-    const PERFT6_POSITIONS: &[(&str, &[usize])] = &[
+    const PERFT6_POSITIONS: &[(&str, &[u64])] = &[
         ("7/7/7/7/-------/-------/x5o x 0 1", &[1, 2, 4, 13, 30, 73, 174]),
         ("7/7/7/7/-------/-------/x5o o 0 1", &[1, 2, 4, 13, 30, 73, 174]),
     ];
 
-    fn test_perft(positions: &[(&str, &[usize])]) {
+    fn test_perft(positions: &[(&str, &[u64])]) {
         let mut pos = Position::empty();
 
         for (fen, counts) in positions {
             pos.reset_from_fen(fen).unwrap();
             for (depth, &count) in counts.iter().enumerate() {
-                assert_eq!(do_perft(&mut pos, depth as i32), count);
+                assert_eq!(perft(&mut pos, depth as i32), count);
             }
         }
     }
 
+    #[test]
+    fn split_perft_sums_to_perft() {
+        let mut pos = Position::from_fen("x5o/7/2-1-2/7/2-1-2/7/o5x x 0 1").unwrap();
+
+        let split = split_perft(&mut pos, 3);
+        assert_eq!(split.len(), 14);
+        assert_eq!(split.iter().map(|&(_, count)| count).sum::<u64>(), 4184);
+    }
+
     #[test]
     fn perft4() {
         test_perft(PERFT4_POSITIONS);
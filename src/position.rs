@@ -20,7 +20,7 @@ use crate::ataxx_move::AtaxxMove;
 use crate::attacks::SINGLES;
 use crate::bitboard::Bitboard;
 use crate::core::{Color, Square};
-use crate::hash;
+use crate::hash::{self, Symmetry};
 use crate::nnue::NnueState;
 use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
@@ -66,7 +66,6 @@ impl BoardState {
 }
 
 impl Default for BoardState {
-    #[must_use]
     fn default() -> Self {
         BoardState {
             colors: [Bitboard::EMPTY, Bitboard::EMPTY],
@@ -114,7 +113,7 @@ impl Display for FenError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum GameResult {
     Win(Color),
     Draw,
@@ -247,6 +246,33 @@ impl Position {
         Ok(())
     }
 
+    // the inverse of taking a position apart into its bitboards, as dataset formats do
+    #[must_use]
+    pub fn from_boards(
+        red: Bitboard,
+        blue: Bitboard,
+        gaps: Bitboard,
+        stm: Color,
+        halfmove: u16,
+        fullmove: u32,
+    ) -> Self {
+        let mut pos = Self::empty();
+
+        pos.blue_to_move = stm == Color::BLUE;
+        pos.fullmove = fullmove;
+        pos.gaps = gaps;
+
+        pos.states.push(BoardState {
+            colors: [red, blue],
+            key: 0,
+            halfmove,
+        });
+
+        pos.regen_curr_key();
+
+        pos
+    }
+
     pub fn reset_from_fen(&mut self, fen: &str) -> Result<(), FenError> {
         let parts: Vec<&str> = fen.split_whitespace().collect();
         self.reset_from_fen_parts(parts.as_slice())
@@ -254,6 +280,7 @@ impl Position {
 
     fn regen_curr_key(&mut self) {
         let blue_to_move = self.blue_to_move;
+        let gaps = self.gaps;
         let state = self.curr_state_mut();
 
         state.key = 0;
@@ -266,6 +293,10 @@ impl Position {
             state.key ^= hash::color_square_key(Color::BLUE, blue_piece);
         }
 
+        for gap in gaps {
+            state.key ^= hash::gap_key(gap);
+        }
+
         if blue_to_move {
             state.key ^= hash::stm_key();
         }
@@ -295,6 +326,14 @@ impl Position {
     pub fn result(&self) -> GameResult {
         let state = self.curr_state();
 
+        // the fifty move rule draws regardless of material
+        if state.halfmove >= 100
+            && !state.red_occupancy().is_empty()
+            && !state.blue_occupancy().is_empty()
+        {
+            return GameResult::Draw;
+        }
+
         let red_count = state.red_occupancy().popcount();
         let blue_count = state.blue_occupancy().popcount();
 
@@ -402,6 +441,16 @@ impl Position {
         }
     }
 
+    // passing is only legal when no other move is, but search
+    // sometimes wants to know what would happen if it were anyway
+    pub fn apply_pretend_pass(&mut self) {
+        self.apply_move::<true, true>(AtaxxMove::Null, None);
+    }
+
+    pub fn pop_pretend_pass(&mut self) {
+        self.pop_move::<true>(None);
+    }
+
     pub fn pop_move<const UPDATE_KEY: bool>(&mut self, nnue: Option<&mut NnueState>) {
         self.states.pop().expect("pop_move with no state history?");
 
@@ -459,6 +508,16 @@ impl Position {
         self.curr_state().color_at(sq)
     }
 
+    // enemy pieces the move would capture
+    #[must_use]
+    pub fn flip_count(&self, mv: AtaxxMove) -> u32 {
+        let (AtaxxMove::Single(to) | AtaxxMove::Double(_, to)) = mv else {
+            return 0;
+        };
+
+        (SINGLES[to.bit_idx()] & self.color_occupancy(self.side_to_move().flip())).popcount()
+    }
+
     #[must_use]
     pub fn gaps(&self) -> Bitboard {
         self.gaps
@@ -469,11 +528,55 @@ impl Position {
         self.gaps.get(sq)
     }
 
+    // for editing the board, keeping the key and nnue features up to date without a full
+    // rebuild. a stone on a square that becomes a gap is removed. only the current state's key
+    // is updated, so earlier positions in the history no longer match the board
+    pub fn set_gap(&mut self, sq: Square, gap: bool, mut nnue: Option<&mut NnueState>) {
+        if self.gap_at(sq) == gap {
+            return;
+        }
+
+        let state = self.states.last_mut().unwrap();
+
+        if gap {
+            for color in [Color::RED, Color::BLUE] {
+                if state.colors[color.idx()].get(sq) {
+                    state.colors[color.idx()].clear(sq);
+                    state.key ^= hash::color_square_key(color, sq);
+
+                    if let Some(nnue) = nnue.as_deref_mut() {
+                        nnue.deactivate_feature(color, sq);
+                    }
+                }
+            }
+
+            if let Some(nnue) = nnue {
+                nnue.activate_gap(sq);
+            }
+        } else if let Some(nnue) = nnue {
+            nnue.deactivate_gap(sq);
+        }
+
+        self.gaps.set_to(sq, gap);
+        state.key ^= hash::gap_key(sq);
+    }
+
     #[must_use]
     pub fn key(&self) -> u64 {
         self.curr_state().key
     }
 
+    // shared by every mirror image of the position, rebuilt from scratch on each call
+    #[must_use]
+    pub fn canonical_key(&self) -> (u64, Symmetry) {
+        hash::canonical_key(
+            self.red_occupancy(),
+            self.blue_occupancy(),
+            self.gaps,
+            self.side_to_move(),
+        )
+    }
+
     #[must_use]
     pub fn halfmoves(&self) -> u16 {
         self.curr_state().halfmove
@@ -508,6 +611,7 @@ impl Position {
                             while file < 6
                                 && state.color_at(Square::from_coords(rank, file + 1))
                                     == Color::NONE
+                                && !self.gap_at(Square::from_coords(rank, file + 1))
                             {
                                 file += 1;
                                 empty_squares += 1;
@@ -577,8 +681,11 @@ impl Display for Position {
 #[cfg(test)]
 mod tests {
     use crate::ataxx_move::AtaxxMove;
+    use crate::bitboard::Bitboard;
     use crate::core::Square;
-    use crate::position::Position;
+    use crate::movegen::{fill_move_list, MoveList};
+    use crate::nnue::{evaluate_once, NnueState};
+    use crate::position::{GameResult, Position};
 
     #[test]
     fn noncapture_single_key() {
@@ -619,6 +726,68 @@ mod tests {
         assert_eq!(incr_key, regen_key);
     }
 
+    #[test]
+    fn set_gap_key_and_features() {
+        let mut pos = Position::startpos();
+
+        let mut nnue = Box::<NnueState>::default();
+        nnue.reset(&pos);
+
+        pos.set_gap(Square::D4, true, Some(&mut nnue));
+        pos.set_gap(Square::A7, true, Some(&mut nnue));
+
+        let gapped = Position::from_fen("-5o/7/7/3-3/7/7/o5x x 0 1").unwrap();
+        assert_eq!(pos.to_fen(), gapped.to_fen());
+        assert_eq!(pos.key(), gapped.key());
+        assert_eq!(nnue.evaluate(&pos), evaluate_once(&gapped));
+
+        pos.set_gap(Square::D4, false, Some(&mut nnue));
+
+        let ungapped = Position::from_fen("-5o/7/7/7/7/7/o5x x 0 1").unwrap();
+        assert_eq!(pos.key(), ungapped.key());
+        assert_eq!(nnue.evaluate(&pos), evaluate_once(&ungapped));
+    }
+
+    #[test]
+    fn lazy_nnue_updates() {
+        let mut pos = Position::startpos();
+
+        let mut nnue = Box::<NnueState>::default();
+        nnue.reset(&pos);
+
+        // some plies are never evaluated, so later ones have several to catch up on
+        for ply in 0..24 {
+            let mut moves = MoveList::new();
+            fill_move_list(&mut moves, &pos);
+
+            pos.apply_move::<true, true>(moves[(ply * 7) % moves.len()], Some(&mut nnue));
+
+            if ply % 3 == 2 {
+                assert_eq!(nnue.evaluate(&pos), evaluate_once(&pos));
+            }
+        }
+
+        for _ in 0..24 {
+            pos.pop_move::<true>(Some(&mut nnue));
+            assert_eq!(nnue.evaluate(&pos), evaluate_once(&pos));
+        }
+
+        // editing a ply that is still waiting on its updates, with more changes than it can
+        // hold, so they have to be applied early. this net's gap weights are all 0, so what
+        // this checks is the stones being removed
+        let mut pos =
+            Position::from_fen("xxxxxxx/ooooooo/xxxxxxx/ooooooo/xxxxxxx/7/7 x 0 1").unwrap();
+        nnue.reset(&pos);
+
+        pos.apply_move::<true, true>(AtaxxMove::Single(Square::A2), Some(&mut nnue));
+
+        for sq in Bitboard::ALL {
+            pos.set_gap(sq, true, Some(&mut nnue));
+        }
+
+        assert_eq!(nnue.evaluate(&pos), evaluate_once(&pos));
+    }
+
     #[test]
     fn capture_double_key() {
         let mut pos = Position::from_fen("x5o/2o4/7/7/7/7/o5x x 0 1").unwrap();
@@ -631,4 +800,26 @@ mod tests {
 
         assert_eq!(incr_key, regen_key);
     }
+
+    #[test]
+    fn fifty_move_rule_draws() {
+        let pos = Position::from_fen("x5o/7/7/7/7/7/o5x x 100 60").unwrap();
+        assert!(pos.game_over());
+        assert!(matches!(pos.result(), GameResult::Draw));
+
+        let pos = Position::from_fen("xx4o/7/7/7/7/7/o5x x 100 60").unwrap();
+        assert!(matches!(pos.result(), GameResult::Draw));
+
+        let pos = Position::from_fen("xx4o/7/7/7/7/7/o5x x 99 60").unwrap();
+        assert!(!pos.game_over());
+    }
+
+    #[test]
+    fn flip_count_counts_adjacent_enemies() {
+        let pos = Position::from_fen("ooo4/o1o4/ooo4/3x3/7/7/7 x 0 1").unwrap();
+
+        assert_eq!(pos.flip_count(AtaxxMove::Double(Square::D4, Square::B6)), 8);
+        assert_eq!(pos.flip_count(AtaxxMove::Single(Square::D5)), 2);
+        assert_eq!(pos.flip_count(AtaxxMove::Null), 0);
+    }
 }
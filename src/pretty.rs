@@ -0,0 +1,154 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::{Score, SCORE_WIN};
+use crate::search::format_score;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputStyle {
+    Uai,
+    Pretty { color: bool },
+}
+
+#[must_use]
+pub fn header() -> String {
+    format!(
+        "{:>3} {:>7} {:>12} {:>9} {:>9} {:>9}  pv",
+        "#", "depth", "score", "time", "nodes", "nps"
+    )
+}
+
+#[must_use]
+fn format_time(ms: usize) -> String {
+    let secs = ms / 1000;
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}.{:02}s", secs, ms % 1000 / 10)
+    }
+}
+
+#[must_use]
+fn format_count(count: usize) -> String {
+    if count >= 10_000_000_000 {
+        format!("{}G", count / 1_000_000_000)
+    } else if count >= 10_000_000 {
+        format!("{}M", count / 1_000_000)
+    } else if count >= 10_000 {
+        format!("{}k", count / 1000)
+    } else {
+        count.to_string()
+    }
+}
+
+#[must_use]
+fn format_pretty_score(score: Score) -> String {
+    if score.abs() > SCORE_WIN {
+        // "mate N"
+        format!("#{}", &format_score(score)[5..])
+    } else {
+        format!("{:+.2}", f64::from(score) / 100.0)
+    }
+}
+
+// one aligned table row per search update, colored only when writing to a terminal
+#[allow(clippy::too_many_arguments)]
+#[must_use]
+pub fn row(
+    multi_pv: Option<usize>,
+    depth: i32,
+    seldepth: u32,
+    score: Score,
+    time_ms: usize,
+    nodes: usize,
+    nps: usize,
+    pv: &str,
+    color: bool,
+) -> String {
+    let score_str = format!("{:>12}", format_pretty_score(score));
+
+    let score_str = if !color {
+        score_str
+    } else if score > 0 {
+        format!("{}{}{}", GREEN, score_str, RESET)
+    } else if score < 0 {
+        format!("{}{}{}", RED, score_str, RESET)
+    } else {
+        score_str
+    };
+
+    let depth_str = format!("{}/{}", depth, seldepth);
+    let stats = format!(
+        "{:>9} {:>9} {:>9}",
+        format_time(time_ms),
+        format_count(nodes),
+        format_count(nps)
+    );
+
+    let stats = if color {
+        format!("{}{}{}", DIM, stats, RESET)
+    } else {
+        stats
+    };
+
+    format!(
+        "{:>3} {:>7} {} {}  {}",
+        multi_pv.unwrap_or(1),
+        depth_str,
+        score_str,
+        stats,
+        pv
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::row;
+    use crate::core::SCORE_MATE;
+
+    #[test]
+    fn rows_are_aligned() {
+        let short = row(None, 1, 1, 5, 3, 16, 5000, "g1f3", false);
+        let long = row(
+            Some(2),
+            12,
+            20,
+            -1234,
+            61_500,
+            123_456_789,
+            9_876_543,
+            "a7c6",
+            false,
+        );
+
+        assert_eq!(short.find("g1f3"), long.find("a7c6"));
+        assert!(long.contains("1m01s"));
+        assert!(long.contains("123M"));
+    }
+
+    #[test]
+    fn mates_shown_in_moves() {
+        let line = row(None, 3, 3, SCORE_MATE - 3, 0, 0, 0, "b2", false);
+        assert!(line.contains("#2"));
+    }
+}
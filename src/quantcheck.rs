@@ -0,0 +1,68 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::bench::BENCH_FENS;
+use crate::error::Result;
+use crate::nnue::{evaluate_once, load_network, FloatNetwork};
+use crate::position::Position;
+
+// how far the quantised net's evals stray from the float net it was made from, over
+// the bench positions. the embedded net is checked unless another is given
+pub fn run_quantcheck(float_path: &str, quantised_path: Option<&str>) -> Result<()> {
+    if let Some(path) = quantised_path {
+        load_network(path)?;
+    }
+
+    let float = FloatNetwork::load(float_path)?;
+
+    let mut total_error = 0.0;
+    let mut total_abs_error = 0.0;
+    let mut max_error = (0.0, "");
+    let mut sign_flips = 0;
+
+    for fen in BENCH_FENS {
+        let pos = Position::from_fen(fen)?;
+
+        let expected = float.evaluate(&pos);
+        let quantised = f64::from(evaluate_once(&pos));
+
+        let error = quantised - expected;
+
+        total_error += error;
+        total_abs_error += error.abs();
+
+        if error.abs() > max_error.0 {
+            max_error = (error.abs(), fen);
+        }
+
+        // a quantised eval of exactly 0 is not counted as either side
+        if expected * quantised < 0.0 {
+            sign_flips += 1;
+        }
+    }
+
+    let count = BENCH_FENS.len() as f64;
+
+    println!("positions: {}", BENCH_FENS.len());
+    println!("mean error: {:.3}", total_error / count);
+    println!("mean absolute error: {:.3}", total_abs_error / count);
+    println!("max absolute error: {:.3} ({})", max_error.0, max_error.1);
+    println!("sign flips: {}", sign_flips);
+
+    Ok(())
+}
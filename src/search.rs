@@ -18,13 +18,134 @@
 
 use crate::ataxx_move::AtaxxMove;
 use crate::core::*;
-use crate::eval::static_eval;
-use crate::limit::SearchLimiter;
-use crate::movegen::{fill_scored_move_list, ScoredMoveList};
-use crate::nnue::NnueState;
+use crate::correction::CorrectionHistory;
+use crate::eval::{static_eval, static_eval_once};
+use crate::evalcache::EvalCache;
+use crate::hash::Symmetry;
+use crate::history::{history_bonus, HistoryTable};
+use crate::iolog::uai_println;
+use crate::limit::{SearchLimiter, SearchSignals};
+use crate::movegen::{
+    fill_move_list, fill_noisy_move_list, fill_scored_move_list, must_pass, MoveList,
+    ScoredMoveList,
+};
+use crate::movepick::MovePicker;
+use crate::nnue::{eval_key, network_size, NnueState, RefreshCache};
+use crate::numa;
+use crate::params::SearchParams;
 use crate::position::{GameResult, Position};
-use crate::ttable::{TTable, TtEntryFlag};
-use std::time::Instant;
+use crate::pretty::{self, OutputStyle};
+use crate::skill::{skill_seed, SkillLevel};
+use crate::stats::SearchStats;
+use crate::trace::{SearchTrace, TraceEvent};
+use crate::ttable::{TTable, TtEntry, TtEntryFlag};
+use crate::util::rng::Jsf64Rng;
+use crate::wdl::WdlModel;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// root moves are only announced once a search has run long enough for a gui to care
+const CURRMOVE_MIN_TIME: Duration = Duration::from_secs(3);
+
+// a time-limited search that completes fewer iterations than this checks
+// the unfinished iteration's best move before discarding it
+const ROOT_VERIFICATION_MAX_DEPTH: i32 = 5;
+const ROOT_VERIFICATION_DEPTH: i32 = 2;
+
+const ROOT_HISTORY_SIZE: usize = 16;
+// our move, the expected reply, and our move after that
+const ROOT_HISTORY_PV_PLIES: usize = 3;
+
+pub const MAX_RISK: Score = 100;
+
+// at full risk, every piece of imbalance up to the cap is worth this many cp to the root side
+const RISK_IMBALANCE_MAX_BONUS: Score = 10;
+const RISK_MAX_IMBALANCE: Score = 20;
+
+// evals shrink linearly as the halfmove clock runs towards the fifty move draw,
+// down to half at the limit, so progress is preferred over shuffling doubles
+const HALFMOVE_LIMIT: u16 = 100;
+const HALFMOVE_SCALE_BASE: Score = 2 * HALFMOVE_LIMIT as Score;
+
+// every finished thread votes for its best move, weighted by depth and by how far
+// its score is above the worst thread's. the base keeps the worst thread's vote alive
+const VOTE_SCORE_BASE: i64 = 20;
+
+// longer pvs are cut off, which only affects what gets printed
+const MAX_PV_LENGTH: usize = 64;
+
+type PvLine = arrayvec::ArrayVec<AtaxxMove, MAX_PV_LENGTH>;
+
+// per-ply state, indexed by ply
+#[derive(Debug, Clone)]
+struct StackEntry {
+    static_eval: Score,
+    // the move being searched below this ply, Null for a pretend or forced pass
+    mv: AtaxxMove,
+    // skipped by the move loop, so a node can be searched without one of its moves
+    excluded: AtaxxMove,
+    // quiet moves that caused cutoffs at this ply in sibling nodes
+    killers: [AtaxxMove; 2],
+    pv: PvLine,
+}
+
+impl Default for StackEntry {
+    fn default() -> Self {
+        Self {
+            static_eval: 0,
+            mv: AtaxxMove::None,
+            excluded: AtaxxMove::None,
+            killers: [AtaxxMove::None; 2],
+            pv: PvLine::new(),
+        }
+    }
+}
+
+// everything a search thread learns that is still useful for the next search,
+// kept by the searcher between searches until a new game
+#[derive(Debug, Clone)]
+struct ThreadState {
+    history: Box<HistoryTable>,
+    correction: CorrectionHistory,
+    eval_cache: EvalCache,
+    // one entry per ply, plus one for qsearch at max depth
+    stack: Vec<StackEntry>,
+    // lent to the search's nnue state. not cleared for a new game, as it only saves work
+    refresh_cache: RefreshCache,
+}
+
+impl ThreadState {
+    fn clear(&mut self) {
+        self.history.clear();
+        self.correction.clear();
+        self.eval_cache.clear();
+        self.stack.fill(StackEntry::default());
+    }
+
+    // the refresh cache is left to the nnue accumulators' share
+    #[must_use]
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<HistoryTable>()
+            + self.correction.memory_usage()
+            + self.eval_cache.memory_usage()
+            + self.stack.len() * std::mem::size_of::<StackEntry>()
+    }
+}
+
+impl Default for ThreadState {
+    fn default() -> Self {
+        Self {
+            history: Box::default(),
+            correction: CorrectionHistory::default(),
+            eval_cache: EvalCache::default(),
+            stack: vec![StackEntry::default(); MAX_DEPTH as usize + 1],
+            refresh_cache: RefreshCache::default(),
+        }
+    }
+}
 
 pub struct SearchContext<'a> {
     pub pos: &'a mut Position,
@@ -32,284 +153,2495 @@ pub struct SearchContext<'a> {
     pub nodes: usize,
     pub seldepth: u32,
     pub best_move: AtaxxMove,
+    // the root score that made best_move the best, which may only be a lower bound
+    best_move_score: Score,
+    pub excluded_root_moves: MoveList,
+    // if not empty, only these moves are searched at the root
+    pub search_moves: MoveList,
+    // nodes spent by all threads in each completed iteration, only tracked by the main thread
+    pub depth_nodes: Vec<usize>,
+    // best first, scores are relative to the side to move
+    pub root_lines: Vec<RootLine>,
+    // iterative deepening begins here rather than at depth 1, to skip
+    // shallow iterations when the tt already holds results from a previous search
+    pub start_depth: i32,
+    thread_id: usize,
+    reporting: bool,
+    start_time: Instant,
+    verifying_mate: bool,
+    // in an analysis burst, each thread publishes its best line after every iteration
+    burst: bool,
+    state: ThreadState,
+    // the pv of each root move that was best in a multipv pass
+    root_pvs: Vec<PvLine>,
+    // for the current iteration, and the last one that completed
+    root_moves: Vec<RootMoveStats>,
+    completed_root_moves: Vec<RootMoveStats>,
+    stats: SearchStats,
+    depth_completed: i32,
+    limiter: SearchLimiter,
 }
 
 impl<'a> SearchContext<'a> {
     pub fn new(pos: &'a mut Position) -> Self {
+        Self::with_state(pos, ThreadState::default())
+    }
+
+    #[must_use]
+    fn with_state(pos: &'a mut Position, mut state: ThreadState) -> Self {
+        let mut nnue_state = NnueState::default();
+        nnue_state.swap_refresh_cache(&mut state.refresh_cache);
+
         Self {
             pos,
-            nnue_state: NnueState::default(),
+            nnue_state,
             nodes: 0,
             seldepth: 0,
             best_move: AtaxxMove::None,
+            best_move_score: -SCORE_INF,
+            excluded_root_moves: MoveList::new(),
+            search_moves: MoveList::new(),
+            depth_nodes: Vec::new(),
+            root_lines: Vec::new(),
+            start_depth: 1,
+            thread_id: 0,
+            reporting: false,
+            start_time: Instant::now(),
+            verifying_mate: false,
+            burst: false,
+            state,
+            root_pvs: Vec::new(),
+            root_moves: Vec::new(),
+            completed_root_moves: Vec::new(),
+            stats: SearchStats::default(),
+            depth_completed: 0,
+            limiter: SearchLimiter::infinite(),
         }
     }
-}
-
-pub struct Searcher {
-    limiter: SearchLimiter,
-    ttable: TTable,
-}
 
-impl Searcher {
     #[must_use]
-    pub fn new() -> Self {
-        Self {
-            limiter: SearchLimiter::infinite(),
-            ttable: TTable::new(),
-        }
+    pub fn depth_completed(&self) -> i32 {
+        self.depth_completed
     }
 
-    pub fn new_game(&mut self) {
-        self.ttable.clear();
+    #[must_use]
+    fn is_main_thread(&self) -> bool {
+        self.thread_id == 0
     }
 
-    pub fn resize_tt(&mut self, mb: usize) {
-        self.ttable.resize(mb);
+    fn return_refresh_cache(&mut self) {
+        self.nnue_state
+            .swap_refresh_cache(&mut self.state.refresh_cache);
     }
 
-    pub fn start_search(&mut self, mut pos: Position, limiter: SearchLimiter, max_depth: i32) {
-        self.limiter = limiter;
+    // the network's eval, before correction
+    #[must_use]
+    fn raw_eval(&mut self) -> Score {
+        let key = eval_key(self.pos);
 
-        let mut ctx = SearchContext::new(&mut pos);
-        ctx.nnue_state.reset(ctx.pos);
+        if let Some(eval) = self.state.eval_cache.probe(key) {
+            return eval;
+        }
+
+        let eval = static_eval(self.pos, &mut self.nnue_state);
+        self.state.eval_cache.store(key, eval);
 
-        self.search_root(&mut ctx, max_depth, true);
+        eval
     }
 
-    pub fn run_datagen_search(
-        &mut self,
-        ctx: &mut SearchContext,
-        limiter: SearchLimiter,
-        max_depth: i32,
-    ) -> Score {
-        self.limiter = limiter;
+    // the pv at this ply is the move followed by the child's pv
+    fn update_pv(&mut self, ply: usize, mv: AtaxxMove) {
+        let (parent, child) = self.state.stack.split_at_mut(ply + 1);
+        let pv = &mut parent[ply].pv;
 
-        let score = self.search_root(ctx, max_depth, false);
+        pv.clear();
+        pv.push(mv);
+        pv.extend(child[0].pv.iter().copied().take(MAX_PV_LENGTH - 1));
+    }
 
-        if ctx.pos.side_to_move() == Color::BLUE {
-            -score
+    fn update_killers(&mut self, ply: usize, mv: AtaxxMove) {
+        let killers = &mut self.state.stack[ply].killers;
+
+        if killers[0] != mv {
+            killers[1] = killers[0];
+            killers[0] = mv;
+        }
+    }
+
+    // a multipv pass only leaves the root pv for the move it found best
+    fn record_root_pv(&mut self) {
+        let pv = self.state.stack[0].pv.clone();
+        let Some(&mv) = pv.first() else {
+            return;
+        };
+
+        if let Some(existing) = self
+            .root_pvs
+            .iter_mut()
+            .find(|line| line.first() == Some(&mv))
+        {
+            *existing = pv;
         } else {
-            score
+            self.root_pvs.push(pv);
         }
     }
 
     #[must_use]
-    pub fn bench(&mut self, pos: &mut Position, depth: i32) -> (usize, f64) {
-        self.limiter = SearchLimiter::infinite();
+    fn root_pv(&self, mv: AtaxxMove) -> Option<&PvLine> {
+        self.root_pvs.iter().find(|line| line.first() == Some(&mv))
+    }
 
-        let mut ctx = SearchContext::new(pos);
-        ctx.nnue_state.reset(ctx.pos);
+    // nodes add up over multipv lines and re-searches, the score is the latest one
+    fn record_root_move(&mut self, mv: AtaxxMove, nodes: usize, score: Score) {
+        if let Some(stats) = self.root_moves.iter_mut().find(|stats| stats.mv == mv) {
+            stats.nodes += nodes;
+            stats.score = score;
+        } else {
+            self.root_moves.push(RootMoveStats { mv, nodes, score });
+        }
+    }
 
-        let start = Instant::now();
+    // share of this iteration's root nodes spent below the given move
+    #[must_use]
+    fn root_move_nodes_fraction(&self, mv: AtaxxMove) -> f64 {
+        let total: usize = self.root_moves.iter().map(|stats| stats.nodes).sum();
+        if total == 0 {
+            return 0.0;
+        }
 
-        self.search_root(&mut ctx, depth, false);
+        let nodes = self
+            .root_moves
+            .iter()
+            .find(|stats| stats.mv == mv)
+            .map_or(0, |stats| stats.nodes);
 
-        let time = start.elapsed().as_secs_f64();
-        (ctx.nodes, time)
+        nodes as f64 / total as f64
     }
+}
 
-    fn search_root(&mut self, ctx: &mut SearchContext, max_depth: i32, report: bool) -> Score {
-        assert!(max_depth > 0);
+#[derive(Debug, Copy, Clone)]
+pub struct RootMoveStats {
+    pub mv: AtaxxMove,
+    pub nodes: usize,
+    // may only be a bound, if the move fell outside the window
+    pub score: Score,
+}
 
-        let max_depth = max_depth.min(MAX_DEPTH);
+// kept on its own cache line, as every thread writes its own counter constantly
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct NodeCounter(AtomicUsize);
 
-        let start = Instant::now();
+// the lines of a root search, kept so that
+// re-analysing the same position can pick up where it left off
+#[derive(Debug, Clone)]
+struct RootCache {
+    key: u64,
+    // best first, one per multipv line
+    lines: Vec<RootLine>,
+}
 
-        let mut score = -SCORE_INF;
-        let mut best_move = AtaxxMove::None;
+// root results of recent searches in this game, along with the positions further
+// down their pvs, which are likely to become the root a move or two later
+#[derive(Debug, Default)]
+struct RootHistory {
+    // most recent last
+    entries: Vec<RootCache>,
+}
 
-        let mut depth_completed = 0i32;
+impl RootHistory {
+    #[must_use]
+    fn probe(&self, key: u64) -> Option<&RootCache> {
+        self.entries.iter().rev().find(|entry| entry.key == key)
+    }
 
-        for depth in 1..=max_depth {
-            ctx.seldepth = 0;
+    fn insert(&mut self, entry: RootCache) {
+        self.entries.retain(|other| other.key != entry.key);
 
-            score = self.search(ctx, -SCORE_INF, SCORE_INF, depth, 0);
+        if self.entries.len() >= ROOT_HISTORY_SIZE {
+            self.entries.remove(0);
+        }
 
-            if self.limiter.stopped() {
-                break;
-            }
+        self.entries.push(entry);
+    }
 
-            depth_completed = depth;
-            best_move = ctx.best_move;
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
 
-            if report && depth < max_depth {
-                let time = start.elapsed().as_secs_f64();
-                Self::report(ctx, best_move, depth, time, score);
-            }
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RootLine {
+    pub mv: AtaxxMove,
+    pub score: Score,
+    // secondary multipv lines may be searched shallower than the iteration
+    pub depth: i32,
+}
 
-            if self.limiter.should_stop(ctx.nodes) {
-                break;
-            }
+// stands in for a thread that has not completed an iteration yet
+const NO_LINE: RootLine = RootLine {
+    mv: AtaxxMove::None,
+    score: -SCORE_INF,
+    depth: 0,
+};
+
+// raw scores are divided by this and multiplied by 100 when normalising,
+// bringing them closer to the scale other engines report in
+const NORMALIZED_PAWN: Score = 400;
+
+// how scores are written in info lines
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ScoreFormat {
+    pub mate_in_plies: bool,
+    pub normalize: bool,
+    pub show_wdl: bool,
+}
+
+impl ScoreFormat {
+    #[must_use]
+    pub fn format(self, score: Score) -> String {
+        if self.show_wdl {
+            let (win, draw, loss) = WdlModel::default().wdl(score);
+            format!("{} wdl {} {} {}", self.format_score(score), win, draw, loss)
+        } else {
+            self.format_score(score)
         }
+    }
 
-        if report {
-            let time = start.elapsed().as_secs_f64();
-            Self::report(ctx, best_move, depth_completed, time, score);
+    #[must_use]
+    fn format_score(self, score: Score) -> String {
+        if score.abs() > SCORE_WIN {
+            let plies = SCORE_MATE - score.abs();
+
+            let mate = if self.mate_in_plies {
+                plies
+            } else {
+                (plies + 1) / 2
+            };
 
-            println!("bestmove {}", best_move);
+            format!("mate {}", if score > 0 { mate } else { -mate })
+        } else if self.normalize {
+            format!("cp {}", score * 100 / NORMALIZED_PAWN)
+        } else {
+            format!("cp {}", score)
         }
+    }
+}
+
+// never takes a line below half the iteration's depth
+#[must_use]
+fn multi_pv_reduction(params: &SearchParams, gap: Score, depth: i32) -> i32 {
+    (gap.max(0) / params.multi_pv_reduction_margin)
+        .min(params.multi_pv_max_reduction)
+        .min(depth / 2)
+}
+
+#[must_use]
+fn scale_by_halfmoves(eval: Score, halfmoves: u16) -> Score {
+    let halfmoves = Score::from(halfmoves.min(HALFMOVE_LIMIT));
+    eval * (HALFMOVE_SCALE_BASE - halfmoves) / HALFMOVE_SCALE_BASE
+}
+
+// the main thread's line comes first. a proven win is always taken,
+// otherwise the move with the most votes wins, ties going to the better score
+#[must_use]
+fn vote_best_line(results: &[(RootLine, i32)]) -> RootLine {
+    let voters = results
+        .iter()
+        .filter(|(line, depth)| line.mv != AtaxxMove::None && *depth > 0)
+        .copied()
+        .collect::<Vec<_>>();
+
+    let Some(&(mut best, _)) = voters.first() else {
+        return results[0].0;
+    };
+
+    let min_score = voters
+        .iter()
+        .map(|(line, _)| line.score)
+        .min()
+        .unwrap_or(best.score);
+
+    let votes = |mv: AtaxxMove| -> i64 {
+        voters
+            .iter()
+            .filter(|(line, _)| line.mv == mv)
+            .map(|(line, depth)| {
+                (i64::from(line.score - min_score) + VOTE_SCORE_BASE) * i64::from(*depth)
+            })
+            .sum()
+    };
 
-        score
+    for &(line, _) in &voters[1..] {
+        let better = if best.score >= SCORE_WIN {
+            line.score > best.score
+        } else if line.score >= SCORE_WIN {
+            true
+        } else {
+            let (line_votes, best_votes) = (votes(line.mv), votes(best.mv));
+            line_votes > best_votes || (line_votes == best_votes && line.score > best.score)
+        };
+
+        if better {
+            best = line;
+        }
     }
 
+    best
+}
+
+// in uai form, with mate scores converted to moves
+#[must_use]
+pub fn format_score(score: Score) -> String {
+    ScoreFormat::default().format(score)
+}
+
+// which info fields are sent while searching
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Verbosity {
+    // depth, score and pv only
+    Minimal,
+    Normal,
+    // adds hashfull and per-root-move results
+    Debug,
+}
+
+impl Verbosity {
+    pub const ALL: [Self; 3] = [Self::Minimal, Self::Normal, Self::Debug];
+
     #[must_use]
-    fn search(
-        &mut self,
-        ctx: &mut SearchContext,
-        mut alpha: Score,
-        beta: Score,
-        depth: i32,
-        ply: i32,
-    ) -> Score {
-        if depth > 1 && self.limiter.should_stop(ctx.nodes) {
-            return beta;
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Minimal => "Minimal",
+            Self::Normal => "Normal",
+            Self::Debug => "Debug",
         }
+    }
+}
 
-        ctx.seldepth = ctx.seldepth.max(ply as u32);
+impl FromStr for Verbosity {
+    type Err = ();
 
-        if depth <= 0 || ply >= MAX_DEPTH {
-            return static_eval(ctx.pos, &ctx.nnue_state);
-        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|verbosity| verbosity.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
 
-        let is_root = ply == 0;
-        let is_pv = beta - alpha > 1;
+// search threads share the tt, and are stopped together through the signals
+pub struct Searcher {
+    signals: Arc<SearchSignals>,
+    ttable: TTable,
+    root_history: RootHistory,
+    // one per thread, each picked up again by the same thread in the next search
+    thread_states: Vec<ThreadState>,
+    multi_pv: usize,
+    node_counters: Vec<NodeCounter>,
+    trace_path: Option<String>,
+    trace: Option<SearchTrace>,
+    depth_nodes: Vec<usize>,
+    // from the last completed iteration of the last search's main thread
+    root_stats: (i32, Vec<RootMoveStats>),
+    params: SearchParams,
+    mate_search: bool,
+    debug: bool,
+    skill: SkillLevel,
+    skill_rng: Jsf64Rng,
+    verbosity: Verbosity,
+    risk: Score,
+    analysis_burst: bool,
+    // each thread's latest completed line and its depth during an analysis burst
+    burst_lines: Mutex<Vec<(RootLine, i32)>>,
+    output_style: OutputStyle,
+    score_format: ScoreFormat,
+    // if set, search threads are pinned to these cpus in order
+    thread_cpus: Option<Vec<usize>>,
+}
 
-        let tt_entry = self.ttable.probe(ctx.pos.key()).unwrap_or_default();
-        let tt_hit = tt_entry.flag != TtEntryFlag::None;
+impl Searcher {
+    pub const MAX_THREADS: usize = 512;
 
-        if !is_pv
-            && tt_hit
-            && i32::from(tt_entry.depth) >= depth
-            && match tt_entry.flag {
-                TtEntryFlag::Exact => true,
-                TtEntryFlag::Alpha => Score::from(tt_entry.score) <= alpha,
-                TtEntryFlag::Beta => Score::from(tt_entry.score) >= beta,
-                TtEntryFlag::None => unreachable!(),
-            }
-        {
-            return Score::from(tt_entry.score);
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            signals: Arc::new(SearchSignals::default()),
+            ttable: TTable::new(),
+            root_history: RootHistory::default(),
+            thread_states: vec![ThreadState::default()],
+            multi_pv: 1,
+            node_counters: vec![NodeCounter::default()],
+            trace_path: None,
+            trace: None,
+            depth_nodes: Vec::new(),
+            root_stats: (0, Vec::new()),
+            params: SearchParams::default(),
+            mate_search: false,
+            debug: false,
+            skill: SkillLevel::default(),
+            skill_rng: Jsf64Rng::new(skill_seed()),
+            verbosity: Verbosity::Normal,
+            risk: 0,
+            analysis_burst: false,
+            burst_lines: Mutex::new(Vec::new()),
+            output_style: OutputStyle::Uai,
+            score_format: ScoreFormat::default(),
+            thread_cpus: None,
         }
+    }
 
-        // if no tt hit, the entry's move is None
-        let tt_move = tt_entry.mv.unpack();
+    pub fn new_game(&mut self) {
+        self.ttable.clear(self.threads());
+        self.root_history.clear();
 
-        let mut moves = ScoredMoveList::new();
-        fill_scored_move_list(&mut moves, ctx.pos);
-        Self::order_moves(&mut moves, tt_move);
-
-        if moves.is_empty() {
-            return match ctx.pos.result() {
-                GameResult::Win(side) => {
-                    if side == ctx.pos.side_to_move() {
-                        SCORE_MATE - ply
-                    } else {
-                        -SCORE_MATE + ply
-                    }
-                }
-                GameResult::Draw => 0,
-            };
+        for state in &mut self.thread_states {
+            state.clear();
         }
+    }
 
-        let mut best_score: Score = -SCORE_INF;
-        let mut best_move = AtaxxMove::None;
+    // returns the size actually applied, which may be smaller than requested
+    pub fn resize_tt(&mut self, mb: usize) -> usize {
+        self.ttable.resize(mb)
+    }
 
-        let mut entry_flag = TtEntryFlag::Alpha;
+    // bytes used by each subsystem while searching
+    #[must_use]
+    pub fn memory_usage(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("tt", self.ttable.size_mb() * 1024 * 1024),
+            (
+                "nnue accumulators",
+                self.threads()
+                    * (std::mem::size_of::<NnueState>() + std::mem::size_of::<RefreshCache>()),
+            ),
+            (
+                "thread state",
+                self.thread_states
+                    .iter()
+                    .map(ThreadState::memory_usage)
+                    .sum(),
+            ),
+            ("network", network_size()),
+        ]
+    }
 
-        for (move_idx, &(mv, _)) in moves.iter().enumerate() {
-            ctx.nodes += 1;
+    // everything but the tt, which is what a memory limit has to leave room for
+    #[must_use]
+    pub fn fixed_memory_mb(&self) -> usize {
+        let bytes: usize = self
+            .memory_usage()
+            .iter()
+            .filter(|(name, _)| *name != "tt")
+            .map(|(_, bytes)| bytes)
+            .sum();
 
-            ctx.pos.apply_move::<true, true>(
-                mv,
-                if mv != AtaxxMove::Null {
-                    Some(&mut ctx.nnue_state)
-                } else {
-                    None
-                },
-            );
+        bytes.div_ceil(1024 * 1024)
+    }
 
-            let score = if is_pv && move_idx == 0 {
-                -self.search(ctx, -beta, -alpha, depth - 1, ply + 1)
-            } else {
-                let zw_score = -self.search(ctx, -alpha - 1, -alpha, depth - 1, ply + 1);
-                if zw_score > alpha && zw_score < beta {
-                    -self.search(ctx, -beta, -alpha, depth - 1, ply + 1)
-                } else {
-                    zw_score
-                }
-            };
+    pub fn set_threads(&mut self, threads: usize) {
+        let threads = threads.clamp(1, Self::MAX_THREADS);
+        self.node_counters
+            .resize_with(threads, NodeCounter::default);
+        self.thread_states
+            .resize_with(threads, ThreadState::default);
+    }
 
-            ctx.pos.pop_move::<true>(if mv != AtaxxMove::Null {
-                Some(&mut ctx.nnue_state)
-            } else {
-                None
-            });
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        self.node_counters.len()
+    }
 
-            if score > best_score {
-                best_score = score;
+    // searches started from uai write a trace of all shared state changes to this file
+    pub fn set_trace_path(&mut self, path: Option<String>) {
+        self.trace_path = path;
+    }
 
-                if score > alpha {
-                    best_move = mv;
+    pub fn set_params(&mut self, params: SearchParams) {
+        self.params = params;
+    }
 
-                    if is_root {
-                        ctx.best_move = mv;
-                    }
+    #[must_use]
+    pub fn params(&self) -> &SearchParams {
+        &self.params
+    }
 
-                    if score >= beta {
-                        entry_flag = TtEntryFlag::Beta;
-                        break;
-                    }
+    // searches full width without pruning, aspiration windows or mate scores
+    // from the tt, and re-proves every mate found before reporting it
+    pub fn set_mate_search(&mut self, mate_search: bool) {
+        self.mate_search = mate_search;
+    }
 
-                    alpha = score;
-                    entry_flag = TtEntryFlag::Exact;
-                }
-            }
-        }
+    // uai debug mode, printing diagnostics as info strings
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
 
-        if !self.limiter.stopped() {
-            self.ttable
-                .store(ctx.pos.key(), best_move, best_score, depth, entry_flag);
-        }
+    // plays for a win by making draws worse for the side to move at the
+    // root, and by preferring positions where the piece counts differ
+    pub fn set_risk(&mut self, risk: Score) {
+        self.risk = risk.clamp(0, MAX_RISK);
+    }
 
-        best_score
+    // in infinite analysis, helper threads each leave out a different number of the
+    // most promising root moves, so that their results form the next best lines
+    pub fn set_analysis_burst(&mut self, analysis_burst: bool) {
+        self.analysis_burst = analysis_burst;
     }
 
-    // very temporary solution
-    //TODO movepicker
-    fn order_moves(moves: &mut ScoredMoveList, tt_move: AtaxxMove) {
-        for (mv, score) in moves.iter_mut() {
-            if *mv == tt_move {
-                *score = 100;
-                break;
+    // mirror images of a position share tt entries
+    pub fn set_canonical_hash(&mut self, canonical_hash: bool) {
+        let threads = self.threads();
+        self.ttable.set_canonical_keys(canonical_hash, threads);
+    }
+
+    pub fn set_score_format(&mut self, score_format: ScoreFormat) {
+        self.score_format = score_format;
+    }
+
+    #[must_use]
+    pub fn score_format(&self) -> ScoreFormat {
+        self.score_format
+    }
+
+    // threads allocate their own nnue accumulators, so pinned
+    // threads also keep them in memory local to their node
+    pub fn set_thread_affinity(&mut self, thread_affinity: bool) {
+        self.thread_cpus =
+            Some(numa::thread_cpus()).filter(|cpus| thread_affinity && !cpus.is_empty());
+    }
+
+    pub fn set_numa_interleave(&mut self, interleave: bool) {
+        self.ttable.set_interleaved(interleave);
+    }
+
+    // a table for humans instead of info lines, colored if stdout is a terminal
+    pub fn set_pretty(&mut self, pretty: bool) {
+        self.output_style = if pretty {
+            OutputStyle::Pretty {
+                color: std::io::stdout().is_terminal(),
             }
-        }
+        } else {
+            OutputStyle::Uai
+        };
+    }
 
-        moves.sort_unstable_by(|(_, a_score), (_, b_score)| b_score.cmp(a_score));
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
     }
 
-    fn report(ctx: &SearchContext, mv: AtaxxMove, depth: i32, time: f64, score: Score) {
-        let nps = (ctx.nodes as f64 / time) as usize;
+    pub fn set_skill_level(&mut self, level: u32) {
+        self.skill = SkillLevel::new(level);
+    }
 
-        println!(
-            "info depth {} seldepth {} time {} nodes {} nps {} score {} pv {}",
-            depth,
-            ctx.seldepth,
-            (time * 1000.0) as usize,
-            ctx.nodes,
-            nps,
-            if score.abs() > SCORE_WIN {
-                format!(
-                    "mate {}",
-                    if score > 0 {
-                        (SCORE_MATE - score + 1) / 2
-                    } else {
-                        -(SCORE_MATE + score) / 2
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    #[must_use]
+    pub fn multi_pv(&self) -> usize {
+        self.multi_pv
+    }
+
+    #[must_use]
+    pub fn signals(&self) -> Arc<SearchSignals> {
+        Arc::clone(&self.signals)
+    }
+
+    pub fn start_search(
+        &mut self,
+        mut pos: Position,
+        limiter: SearchLimiter,
+        max_depth: i32,
+        search_moves: &MoveList,
+    ) {
+        self.ttable.new_search();
+
+        let best_move = self
+            .run_threads(&pos, limiter, max_depth, search_moves, true)
+            .mv;
+
+        // the watchdog gave up on this search and already answered
+        if !self.signals.claim_bestmove() {
+            return;
+        }
+
+        if let Some(ponder_move) = self.ponder_move(&mut pos, best_move) {
+            uai_println!("bestmove {} ponder {}", best_move, ponder_move);
+        } else {
+            uai_println!("bestmove {}", best_move);
+        }
+    }
+
+    pub fn run_datagen_search(
+        &mut self,
+        ctx: &mut SearchContext,
+        limiter: SearchLimiter,
+        max_depth: i32,
+    ) -> Score {
+        self.signals.reset(false);
+        self.ttable.new_search();
+        ctx.limiter = limiter;
+
+        std::mem::swap(&mut ctx.state, &mut self.thread_states[0]);
+        let result = self.search_root(ctx, max_depth, false);
+        std::mem::swap(&mut ctx.state, &mut self.thread_states[0]);
+        self.update_root_cache(ctx.pos, &ctx.root_lines);
+
+        if ctx.pos.side_to_move() == Color::BLUE {
+            -result.score
+        } else {
+            result.score
+        }
+    }
+
+    pub fn print_root_stats(&self) {
+        let (depth, stats) = &self.root_stats;
+        self.print_root_moves(*depth, stats);
+    }
+
+    // busiest first, with each move's share of the iteration's root nodes
+    fn print_root_moves(&self, depth: i32, stats: &[RootMoveStats]) {
+        let total = stats.iter().map(|stats| stats.nodes).sum::<usize>().max(1);
+
+        let mut stats = stats.to_vec();
+        stats.sort_by_key(|stats| std::cmp::Reverse(stats.nodes));
+
+        for stats in &stats {
+            uai_println!(
+                "info string depth {} rootmove {} nodes {} ({:.1}%) score {}",
+                depth,
+                stats.mv,
+                stats.nodes,
+                stats.nodes as f64 * 100.0 / total as f64,
+                self.score_format.format(stats.score)
+            );
+        }
+    }
+
+    // per-iteration node counts of the last search
+    #[must_use]
+    pub fn depth_nodes(&self) -> &[usize] {
+        &self.depth_nodes
+    }
+
+    #[must_use]
+    pub fn bench(&mut self, pos: &mut Position, depth: i32) -> (usize, f64) {
+        self.signals.reset(false);
+
+        let start = Instant::now();
+
+        self.run_threads(
+            pos,
+            SearchLimiter::infinite(),
+            depth,
+            &MoveList::new(),
+            false,
+        );
+
+        let time = start.elapsed().as_secs_f64();
+        (self.total_nodes(), time)
+    }
+
+    // the calling thread runs the main search, which is the only one
+    // that reports and checks limits. helpers only stop when it does
+    fn run_threads(
+        &mut self,
+        pos: &Position,
+        limiter: SearchLimiter,
+        max_depth: i32,
+        search_moves: &MoveList,
+        report: bool,
+    ) -> RootLine {
+        let limiter = match self.skill.node_cap() {
+            Some(cap) if report => limiter.with_nodes(cap),
+            _ => limiter,
+        };
+
+        for counter in &self.node_counters {
+            counter.0.store(0, Ordering::Relaxed);
+        }
+
+        if report && self.trace_path.is_some() {
+            self.trace = Some(SearchTrace::new(pos, self.threads(), self.ttable.size_mb()));
+        }
+
+        let burst_moves = if report
+            && self.analysis_burst
+            && self.threads() > 1
+            && limiter.is_infinite()
+            && search_moves.is_empty()
+        {
+            Self::rank_root_moves(pos)
+        } else {
+            Vec::new()
+        };
+
+        *self.burst_lines.get_mut().unwrap() = vec![(NO_LINE, 0); self.threads()];
+
+        let mut states = std::mem::take(&mut self.thread_states).into_iter();
+        let main_state = states.next().unwrap_or_default();
+
+        let searcher = &*self;
+
+        let result = std::thread::scope(|s| {
+            let helpers = states
+                .enumerate()
+                .map(|(idx, state)| {
+                    let thread_id = idx + 1;
+                    let mut pos = pos.clone();
+                    let burst_moves = &burst_moves;
+                    s.spawn(move || {
+                        searcher.pin_thread(thread_id);
+
+                        let mut ctx = SearchContext::with_state(&mut pos, state);
+                        ctx.thread_id = thread_id;
+                        ctx.burst = !burst_moves.is_empty();
+                        ctx.nnue_state.reset(ctx.pos);
+
+                        if burst_moves.len() > thread_id {
+                            ctx.search_moves
+                                .extend(burst_moves[thread_id..].iter().copied());
+                        } else {
+                            ctx.search_moves.clone_from(search_moves);
+                        }
+
+                        let result = searcher.search_root(&mut ctx, max_depth, false);
+                        ctx.return_refresh_cache();
+
+                        (result, ctx.depth_completed, ctx.state, ctx.stats)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            searcher.pin_thread(0);
+
+            let mut pos = pos.clone();
+
+            let mut ctx = SearchContext::with_state(&mut pos, main_state);
+            ctx.limiter = limiter;
+            ctx.search_moves.clone_from(search_moves);
+            ctx.burst = !burst_moves.is_empty();
+            ctx.nnue_state.reset(ctx.pos);
+
+            if report && searcher.output_style != OutputStyle::Uai {
+                uai_println!("{}", pretty::header());
+            }
+
+            if report {
+                searcher.debug_info(|| {
+                    format!(
+                        "nnue accumulators refreshed from the root on {} threads",
+                        searcher.threads()
+                    )
+                });
+
+                if let Some(budget) = ctx.limiter.time_budget_ms() {
+                    searcher.debug_info(|| format!("time manager allocated {:.0} ms", budget));
+                }
+            }
+
+            let result = searcher.search_root(&mut ctx, max_depth, report);
+
+            // bestmove must not be sent while pondering, even if the search is done
+            if report {
+                while searcher.signals.pondering() && !searcher.signals.stop_requested() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+
+            searcher.signals.stop();
+
+            let mut helper_results = Vec::with_capacity(helpers.len());
+            let mut helper_states = Vec::with_capacity(helpers.len());
+
+            for handle in helpers {
+                let (result, depth_completed, state, stats) =
+                    handle.join().expect("search thread panicked");
+
+                helper_results.push((result, depth_completed));
+                helper_states.push(state);
+                ctx.stats.merge(&stats);
+            }
+
+            if report && searcher.debug {
+                for line in ctx.stats.lines() {
+                    searcher.debug_info(|| line);
+                }
+            }
+
+            if !burst_moves.is_empty() {
+                let main_line = ctx.root_lines.first().copied().unwrap_or(NO_LINE);
+                searcher.report_burst(&ctx, main_line, ctx.depth_completed);
+            }
+
+            // burst helpers search other moves on purpose, and multipv lines come from the main thread
+            let result = if burst_moves.is_empty() && searcher.multi_pv == 1 {
+                let mut results = vec![(result, ctx.depth_completed)];
+                results.extend_from_slice(&helper_results);
+
+                let voted = vote_best_line(&results);
+
+                if report && voted.mv != result.mv {
+                    searcher.debug_info(|| {
+                        format!(
+                            "threads voted for {} over the main thread's {}",
+                            voted.mv, result.mv
+                        )
+                    });
+                }
+
+                voted
+            } else {
+                result
+            };
+
+            if report {
+                searcher.debug_info(|| {
+                    format!(
+                        "search finished after {} ms",
+                        ctx.start_time.elapsed().as_millis()
+                    )
+                });
+            }
+
+            ctx.return_refresh_cache();
+
+            (
+                result,
+                ctx.depth_nodes,
+                (ctx.depth_completed, ctx.completed_root_moves),
+                ctx.root_lines,
+                std::iter::once(ctx.state)
+                    .chain(helper_states)
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+        let (result, depth_nodes, root_stats, root_lines, thread_states) = result;
+        self.depth_nodes = depth_nodes;
+        self.root_stats = root_stats;
+        self.thread_states = thread_states;
+
+        if let (Some(trace), Some(path)) = (self.trace.take(), &self.trace_path) {
+            if let Err(err) = trace.write(path) {
+                eprintln!("Failed to write search trace to {}: {}", path, err);
+            }
+        }
+
+        // a restricted search's best move may not be the best in the position
+        if search_moves.is_empty() {
+            self.update_root_cache(pos, &root_lines);
+        }
+
+        if report {
+            if let Some(picked) = self.skill.pick(&root_lines, &mut self.skill_rng) {
+                return picked;
+            }
+        }
+
+        result
+    }
+
+    fn pin_thread(&self, thread_id: usize) {
+        if let Some(cpus) = &self.thread_cpus {
+            numa::pin_current_thread(cpus[thread_id % cpus.len()]);
+        }
+    }
+
+    // by static eval after the move, which is good enough to spread the helpers out
+    fn rank_root_moves(pos: &Position) -> Vec<AtaxxMove> {
+        let mut pos = pos.clone();
+
+        let mut moves = ScoredMoveList::new();
+        fill_scored_move_list(&mut moves, &pos);
+
+        for (mv, score) in &mut moves {
+            pos.apply_move::<true, true>(*mv, None);
+            *score = -static_eval_once(&pos);
+            pos.pop_move::<true>(None);
+        }
+
+        moves.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+        moves.iter().map(|&(mv, _)| mv).collect()
+    }
+
+    fn publish_burst_line(&self, thread_id: usize, line: RootLine, depth: i32) {
+        if let Some(slot) = self.burst_lines.lock().unwrap().get_mut(thread_id) {
+            *slot = (line, depth);
+        }
+    }
+
+    // the main thread's best line comes first, followed by the helpers' distinct best moves
+    fn report_burst(&self, ctx: &SearchContext, main_line: RootLine, main_depth: i32) {
+        let helper_lines = self.burst_lines.lock().unwrap()[1..].to_vec();
+
+        let mut lines = vec![(main_line, main_depth)];
+
+        for (line, depth) in helper_lines {
+            if line.mv != AtaxxMove::None && lines.iter().all(|(other, _)| other.mv != line.mv) {
+                lines.push((line, depth));
+            }
+        }
+
+        lines[1..].sort_by_key(|(line, _)| std::cmp::Reverse(line.score));
+
+        let time = ctx.start_time.elapsed().as_secs_f64();
+
+        for (idx, &(line, depth)) in lines.iter().enumerate() {
+            self.report(ctx, Some(idx + 1), line.mv, depth, time, line.score);
+        }
+    }
+
+    fn update_root_cache(&mut self, pos: &Position, lines: &[RootLine]) {
+        let Some(&best) = lines.first() else {
+            return;
+        };
+
+        if best.mv == AtaxxMove::None {
+            return;
+        }
+
+        let mut pos = pos.clone();
+        let pv = self.tt_pv(&mut pos, best.mv, ROOT_HISTORY_PV_PLIES);
+
+        // the root keeps all of its lines, positions further down the pv only have
+        // the one line through them, and scores flip with the side to move along it
+        for (ply, &mv) in pv.iter().enumerate() {
+            let lines = if ply == 0 {
+                lines.to_vec()
+            } else {
+                vec![RootLine {
+                    mv,
+                    score: if ply % 2 == 0 {
+                        best.score
+                    } else {
+                        -best.score
+                    },
+                    depth: best.depth - ply as i32,
+                }]
+            };
+
+            self.root_history.insert(RootCache {
+                key: pos.key(),
+                lines,
+            });
+
+            pos.apply_move::<true, true>(mv, None);
+        }
+
+        for _ in 0..pv.len() {
+            pos.pop_move::<true>(None);
+        }
+    }
+
+    #[must_use]
+    fn total_nodes(&self) -> usize {
+        self.node_counters
+            .iter()
+            .map(|counter| counter.0.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    fn search_root(&self, ctx: &mut SearchContext, max_depth: i32, report: bool) -> RootLine {
+        assert!(max_depth > 0);
+
+        let max_depth = max_depth.min(MAX_DEPTH);
+
+        let start = Instant::now();
+
+        ctx.reporting = report;
+        ctx.start_time = start;
+        ctx.root_pvs.clear();
+
+        let root_key = ctx.pos.key();
+        let cached = self
+            .root_history
+            .probe(root_key)
+            .map_or_else(Vec::new, |cache| cache.lines.clone());
+
+        let root_moves = if ctx.search_moves.is_empty() {
+            let mut root_moves = MoveList::new();
+            fill_move_list(&mut root_moves, ctx.pos);
+            root_moves
+        } else {
+            ctx.search_moves.clone()
+        };
+        let root_move_count = root_moves.len();
+
+        // played if not even the first iteration finishes
+        let fallback_move = root_moves.first().copied().unwrap_or(AtaxxMove::None);
+
+        ctx.best_move = fallback_move;
+        if report {
+            self.signals.set_best_move(fallback_move);
+        }
+
+        // helper threads only need to fill the tt
+        let multi_pv = if ctx.is_main_thread() {
+            self.skill
+                .multi_pv(self.multi_pv)
+                .min(root_move_count)
+                .max(1)
+        } else {
+            1
+        };
+
+        let mut lines: Vec<RootLine> = Vec::with_capacity(multi_pv);
+        let mut depth_completed = 0i32;
+        ctx.depth_completed = 0;
+
+        // the best move of an iteration that ran out of time, if it changed
+        // before the previous best move was searched again
+        let mut aborted_candidate = AtaxxMove::None;
+        // or the line it gave, if it beat the previous best move in the same iteration
+        let mut partial_line = None;
+
+        // iterations in a row that kept the same best move
+        let mut best_move_stability = 0u32;
+
+        let start_depth = ctx.start_depth.clamp(1, max_depth);
+
+        'iterations: for depth in start_depth..=max_depth {
+            ctx.seldepth = 0;
+            ctx.root_moves.clear();
+            ctx.excluded_root_moves.clear();
+
+            let mut new_lines = Vec::with_capacity(multi_pv);
+
+            for pv_idx in 0..multi_pv {
+                let prev_line = lines.get(pv_idx);
+                // until an iteration completes, the previous search's lines stand in
+                let cached_line = cached.get(pv_idx).filter(|_| lines.is_empty());
+
+                ctx.best_move = prev_line
+                    .or(cached_line)
+                    .map(|line| line.mv)
+                    .filter(|mv| !ctx.excluded_root_moves.contains(mv))
+                    .unwrap_or(AtaxxMove::None);
+
+                // lines far behind the best one get less of the budget
+                let line_depth = match prev_line {
+                    Some(line) if pv_idx > 0 && !self.mate_search => {
+                        depth - multi_pv_reduction(&self.params, lines[0].score - line.score, depth)
                     }
-                )
+                    _ => depth,
+                };
+
+                // when re-analysing a position, the previous result is a
+                // good enough centre for an aspiration window from depth 1
+                let aspiration_center = match prev_line {
+                    _ if self.mate_search => None,
+                    Some(line) if line_depth >= self.params.aspiration_min_depth => {
+                        Some(line.score)
+                    }
+                    None => cached_line.map(|line| line.score),
+                    _ => None,
+                };
+
+                let score = if let Some(center) = aspiration_center {
+                    self.search_aspiration(ctx, line_depth, center)
+                } else {
+                    self.search(ctx, -SCORE_INF, SCORE_INF, line_depth, 0)
+                };
+
+                if ctx.limiter.stopped() {
+                    match prev_line {
+                        Some(prev) if pv_idx == 0 && prev.mv != ctx.best_move => {
+                            if ctx.root_moves.iter().any(|stats| stats.mv == prev.mv) {
+                                partial_line = Some(RootLine {
+                                    mv: ctx.best_move,
+                                    score: ctx.best_move_score,
+                                    depth: prev.depth,
+                                });
+                            } else {
+                                aborted_candidate = ctx.best_move;
+                            }
+                        }
+                        _ => {}
+                    }
+                    break 'iterations;
+                }
+
+                ctx.record_root_pv();
+
+                new_lines.push(RootLine {
+                    mv: ctx.best_move,
+                    score,
+                    depth: line_depth,
+                });
+                ctx.excluded_root_moves.push(ctx.best_move);
+            }
+
+            if self.mate_search
+                && ctx.is_main_thread()
+                && !self.verify_mate(ctx, &mut new_lines[0], depth)
+            {
+                break 'iterations;
+            }
+
+            new_lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+
+            if lines.first().is_some_and(|line| line.mv == new_lines[0].mv) {
+                best_move_stability += 1;
+            } else {
+                best_move_stability = 0;
+            }
+
+            lines = new_lines;
+            depth_completed = depth;
+            ctx.depth_completed = depth;
+
+            if ctx.is_main_thread() {
+                let nodes = self.total_nodes();
+                let prev_nodes: usize = ctx.depth_nodes.iter().sum();
+                ctx.depth_nodes.push(nodes - prev_nodes);
+            }
+
+            ctx.completed_root_moves.clone_from(&ctx.root_moves);
+
+            if report && self.verbosity == Verbosity::Debug {
+                self.print_root_moves(depth, &ctx.completed_root_moves);
+            }
+
+            ctx.best_move = lines[0].mv;
+
+            if report {
+                self.signals.set_best_move(ctx.best_move);
+            }
+
+            self.record(TraceEvent::BestMove {
+                thread: ctx.thread_id,
+                depth,
+                mv: lines[0].mv,
+                score: lines[0].score,
+            });
+
+            if report {
+                self.debug_info(|| format!("depth {} {}", depth, ctx.stats.tt_summary()));
+            }
+
+            if ctx.burst {
+                self.publish_burst_line(ctx.thread_id, lines[0], depth);
+            }
+
+            if report && depth < max_depth {
+                if ctx.burst {
+                    self.report_burst(ctx, lines[0], depth);
+                } else {
+                    let time = start.elapsed().as_secs_f64();
+                    self.report_lines(ctx, &lines, depth, time);
+                }
+            }
+
+            if self.should_stop(ctx) {
+                break;
+            }
+
+            let best_move_nodes = ctx.root_move_nodes_fraction(lines[0].mv);
+            if self.should_stop_soft(ctx, best_move_stability, best_move_nodes) {
+                if report {
+                    self.debug_info(|| {
+                        format!(
+                            "stopping after depth {}, best move stable for {} iterations, {:.0}% of root nodes",
+                            depth,
+                            best_move_stability,
+                            best_move_nodes * 100.0
+                        )
+                    });
+                }
+                break;
+            }
+        }
+
+        ctx.excluded_root_moves.clear();
+
+        // a deep first iteration may not finish at all, and there has to be some move
+        if lines.is_empty() && start_depth > 1 {
+            let limiter = std::mem::replace(&mut ctx.limiter, SearchLimiter::infinite());
+            let score = self.search(ctx, -SCORE_INF, SCORE_INF, 1, 0);
+            ctx.limiter = limiter;
+
+            lines.push(RootLine {
+                mv: ctx.best_move,
+                score,
+                depth: 1,
+            });
+            depth_completed = 1;
+            ctx.depth_completed = 1;
+        }
+
+        // only completed root moves can become the best move, so this one was
+        // proven better than the previous best at a greater depth than it was
+        if let Some(line) = partial_line {
+            if report {
+                self.debug_info(|| {
+                    format!(
+                        "keeping {} from the unfinished iteration over {}",
+                        line.mv, lines[0].mv
+                    )
+                });
+            }
+
+            lines.retain(|other| other.mv != line.mv);
+            lines.insert(0, line);
+            lines.truncate(multi_pv);
+        }
+
+        if aborted_candidate != AtaxxMove::None
+            && depth_completed < ROOT_VERIFICATION_MAX_DEPTH
+            && ctx.limiter.time_budget_ms().is_some()
+            && !self.signals.stop_requested()
+        {
+            self.verify_root_candidate(ctx, &mut lines[0], aborted_candidate);
+        }
+
+        let result = lines.first().copied().unwrap_or(RootLine {
+            mv: fallback_move,
+            score: -SCORE_INF,
+            depth: 0,
+        });
+
+        ctx.best_move = result.mv;
+
+        // a burst's final lines are reported once the helpers have finished theirs
+        if report && !ctx.burst {
+            let time = start.elapsed().as_secs_f64();
+            self.report_lines(ctx, &lines, depth_completed, time);
+        }
+
+        ctx.root_lines = lines;
+
+        result
+    }
+
+    // when time runs out this early, the unfinished iteration may have found a better
+    // move that was never confirmed. a cheap null window search decides between them,
+    // which costs far less than whatever is left of the clock
+    fn verify_root_candidate(
+        &self,
+        ctx: &mut SearchContext,
+        best: &mut RootLine,
+        candidate: AtaxxMove,
+    ) {
+        let limiter = std::mem::replace(&mut ctx.limiter, SearchLimiter::infinite());
+
+        Self::make_move(ctx, candidate);
+        let score = -self.search(
+            ctx,
+            -best.score - 1,
+            -best.score,
+            ROOT_VERIFICATION_DEPTH - 1,
+            1,
+        );
+        Self::unmake_move(ctx, candidate);
+
+        ctx.limiter = limiter;
+
+        self.debug_info(|| {
+            format!(
+                "verified aborted candidate {} against {}: {}",
+                candidate,
+                best.mv,
+                if score > best.score {
+                    "better"
+                } else {
+                    "not better"
+                }
+            )
+        });
+
+        if score > best.score {
+            *best = RootLine {
+                mv: candidate,
+                score,
+                ..*best
+            };
+        }
+    }
+
+    // re-searches a mate score with no tt cutoffs at all, just deep enough to see the mate.
+    // a mate that doesn't hold up is replaced by the verified score.
+    // returns false if the search was stopped during verification
+    fn verify_mate(&self, ctx: &mut SearchContext, line: &mut RootLine, depth: i32) -> bool {
+        if line.score.abs() <= SCORE_WIN {
+            return true;
+        }
+
+        // game over is only detected at nodes with depth left
+        let verification_depth = (SCORE_MATE - line.score.abs() + 1).clamp(1, MAX_DEPTH);
+
+        let excluded_root_moves = std::mem::take(&mut ctx.excluded_root_moves);
+
+        ctx.verifying_mate = true;
+        let score = self.search(ctx, -SCORE_INF, SCORE_INF, verification_depth, 0);
+        ctx.verifying_mate = false;
+
+        ctx.excluded_root_moves = excluded_root_moves;
+
+        if ctx.limiter.stopped() {
+            return false;
+        }
+
+        if score != line.score {
+            uai_println!(
+                "info string depth {} mate score {} for {} not confirmed, verified score {}",
+                depth,
+                line.score,
+                line.mv,
+                score
+            );
+            line.score = score;
+        }
+
+        true
+    }
+
+    fn search_aspiration(&self, ctx: &mut SearchContext, depth: i32, center: Score) -> Score {
+        let mut delta = self.params.aspiration_initial_delta;
+
+        let mut alpha = (center - delta).max(-SCORE_INF);
+        let mut beta = (center + delta).min(SCORE_INF);
+
+        ctx.stats.aspiration_searches += 1;
+
+        loop {
+            let score = self.search(ctx, alpha, beta, depth, 0);
+
+            if ctx.limiter.stopped() {
+                return score;
+            }
+
+            if score <= alpha || score >= beta {
+                ctx.stats.aspiration_researches += 1;
+            }
+
+            if score <= alpha {
+                beta = i32::midpoint(alpha, beta);
+                alpha = (score - delta).max(-SCORE_INF);
+            } else if score >= beta {
+                beta = (score + delta).min(SCORE_INF);
+            } else {
+                return score;
+            }
+
+            if ctx.reporting {
+                self.debug_info(|| {
+                    format!(
+                        "depth {} aspiration fail at {}, window now [{}, {}]",
+                        depth, score, alpha, beta
+                    )
+                });
+            }
+
+            delta += delta / 2;
+        }
+    }
+
+    #[must_use]
+    fn search(
+        &self,
+        ctx: &mut SearchContext,
+        mut alpha: Score,
+        beta: Score,
+        depth: i32,
+        ply: i32,
+    ) -> Score {
+        if depth > 1 && self.should_stop(ctx) {
+            return beta;
+        }
+
+        ctx.seldepth = ctx.seldepth.max(ply as u32);
+
+        let ply_idx = ply as usize;
+
+        // qsearch leaves no pv behind
+        ctx.state.stack[ply_idx].pv.clear();
+
+        if depth <= 0 || ply >= MAX_DEPTH {
+            return self.qsearch(ctx, alpha, beta, self.params.qs_max_depth, ply);
+        }
+
+        // killers are only shared between siblings
+        ctx.state.stack[ply_idx + 1].killers = [AtaxxMove::None; 2];
+
+        let after_pass = ply > 0 && ctx.state.stack[ply_idx - 1].mv == AtaxxMove::Null;
+
+        let is_root = ply == 0;
+        let is_pv = beta - alpha > 1;
+
+        // the tt does not know the halfmove clock, so this has to come before it
+        if !is_root && ctx.pos.halfmoves() >= HALFMOVE_LIMIT {
+            return self.game_over_score(ctx.pos, ply);
+        }
+
+        let tt_key = self.ttable.key(ctx.pos);
+        let tt_entry = self.tt_probe(tt_key).unwrap_or_default();
+        let tt_hit = tt_entry.flag() != TtEntryFlag::None;
+
+        ctx.stats.tt_probes += 1;
+        if tt_hit {
+            ctx.stats.tt_hits += 1;
+        }
+
+        // mate scores from the tt are never trusted when looking for proven mates
+        let tt_usable = tt_hit
+            && !ctx.verifying_mate
+            && !(self.mate_search && Score::from(tt_entry.score).abs() > SCORE_WIN);
+
+        if !is_pv
+            && tt_usable
+            && i32::from(tt_entry.depth) >= depth
+            && match tt_entry.flag() {
+                TtEntryFlag::Exact => true,
+                TtEntryFlag::Alpha => Score::from(tt_entry.score) <= alpha,
+                TtEntryFlag::Beta => Score::from(tt_entry.score) >= beta,
+                TtEntryFlag::None => unreachable!(),
+            }
+        {
+            ctx.stats.tt_cutoffs += 1;
+            return Score::from(tt_entry.score);
+        }
+
+        // if no tt hit, the entry's move is None
+        let tt_move = tt_entry.mv.unpack();
+
+        // a previous search's best move is only a hint, so it goes behind the tt move.
+        // later multipv passes take the best cached line not already taken
+        let root_hint = if is_root {
+            self.root_history
+                .probe(ctx.pos.key())
+                .and_then(|cache| {
+                    cache
+                        .lines
+                        .iter()
+                        .find(|line| !ctx.excluded_root_moves.contains(&line.mv))
+                })
+                .map_or(AtaxxMove::None, |line| line.mv)
+        } else {
+            AtaxxMove::None
+        };
+
+        if ctx.pos.game_over() {
+            return self.game_over_score(ctx.pos, ply);
+        }
+
+        let forced_pass = must_pass(ctx.pos);
+
+        // searchmoves are already validated, and may include moves that movegen would prune
+        let mut picker = if is_root && !ctx.search_moves.is_empty() {
+            MovePicker::with_moves(&ctx.search_moves, tt_move, root_hint)
+        } else {
+            MovePicker::new(tt_move, root_hint, ctx.state.stack[ply_idx].killers)
+        };
+
+        let raw_eval = ctx.raw_eval();
+        let static_eval = scale_by_halfmoves(
+            ctx.state.correction.correct(ctx.pos, raw_eval),
+            ctx.pos.halfmoves(),
+        );
+
+        ctx.state.stack[ply_idx].static_eval = static_eval;
+
+        // if the eval went up since our last move, pruning on it is less safe
+        let improving = ply >= 2 && static_eval > ctx.state.stack[ply_idx - 2].static_eval;
+
+        // hopeless nodes near the leaves only get the captures a qsearch can find
+        if !is_pv
+            && !is_root
+            && !forced_pass
+            && !ctx.verifying_mate
+            && !self.mate_search
+            && depth <= self.params.razor_max_depth
+            && alpha.abs() < SCORE_WIN
+            && static_eval + self.params.razor_margin * depth < alpha
+        {
+            ctx.stats.razor_tries += 1;
+
+            let score = self.qsearch(ctx, alpha, alpha + 1, self.params.qs_max_depth, ply);
+
+            if ctx.limiter.stopped() {
+                return beta;
+            }
+
+            if score <= alpha {
+                ctx.stats.razor_cutoffs += 1;
+                return score;
+            }
+        }
+
+        // if passing still beats beta, a real move almost certainly would too
+        if !is_pv
+            && !is_root
+            && !forced_pass
+            && !after_pass
+            && !ctx.verifying_mate
+            && !self.mate_search
+            && depth >= self.params.nmp_min_depth
+            && beta.abs() < SCORE_WIN
+            && ctx.pos.empty_squares().popcount() >= self.params.nmp_min_empties
+            && static_eval >= beta
+        {
+            let reduction = self.params.nmp_base_reduction + depth / self.params.nmp_depth_divisor;
+
+            ctx.nodes += 1;
+            ctx.stats.nmp_tries += 1;
+
+            ctx.state.stack[ply_idx].mv = AtaxxMove::Null;
+            ctx.pos.apply_pretend_pass();
+            let score = -self.search(ctx, -beta, -beta + 1, depth - 1 - reduction, ply + 1);
+            ctx.pos.pop_pretend_pass();
+
+            if ctx.limiter.stopped() {
+                return beta;
+            }
+
+            // don't trust a mate found by passing
+            if score >= beta {
+                ctx.stats.nmp_cutoffs += 1;
+                return if score >= SCORE_WIN { beta } else { score };
+            }
+        }
+
+        let probcut_beta = beta + self.params.probcut_margin;
+        // tuned min depths can be below the reduction, which leaves a qsearch
+        let probcut_depth = (depth - 1 - self.params.probcut_reduction).max(0);
+
+        // big captures are tried at reduced depth against a raised beta first,
+        // unless the tt already knows a search this deep stays below it
+        if !is_pv
+            && !is_root
+            && !forced_pass
+            && !ctx.verifying_mate
+            && !self.mate_search
+            && depth >= self.params.probcut_min_depth
+            && probcut_beta.abs() < SCORE_WIN
+            && !(tt_usable
+                && i32::from(tt_entry.depth) > probcut_depth
+                && Score::from(tt_entry.score) < probcut_beta)
+        {
+            let mut moves = ScoredMoveList::new();
+            fill_noisy_move_list(&mut moves, ctx.pos, self.params.probcut_min_flips);
+            moves.sort_unstable_by(|(_, a_flips), (_, b_flips)| b_flips.cmp(a_flips));
+
+            for &(mv, _) in &moves {
+                ctx.nodes += 1;
+                ctx.stats.probcut_tries += 1;
+
+                ctx.state.stack[ply_idx].mv = mv;
+                Self::make_move(ctx, mv);
+
+                // most moves are already ruled out by the much cheaper qsearch
+                let mut score = -self.qsearch(
+                    ctx,
+                    -probcut_beta,
+                    -probcut_beta + 1,
+                    self.params.qs_max_depth,
+                    ply + 1,
+                );
+
+                if score >= probcut_beta {
+                    score = -self.search(
+                        ctx,
+                        -probcut_beta,
+                        -probcut_beta + 1,
+                        probcut_depth,
+                        ply + 1,
+                    );
+                }
+
+                Self::unmake_move(ctx, mv);
+
+                if ctx.limiter.stopped() {
+                    return beta;
+                }
+
+                if score >= probcut_beta {
+                    ctx.stats.probcut_cutoffs += 1;
+                    self.tt_store(ctx, tt_key, mv, score, probcut_depth + 1, TtEntryFlag::Beta);
+                    return score;
+                }
+            }
+        }
+
+        let mut best_score: Score = -SCORE_INF;
+        let mut best_move = AtaxxMove::None;
+
+        let mut entry_flag = TtEntryFlag::Alpha;
+
+        let mut move_idx = 0usize;
+
+        // moves that failed to cut off and their flip counts, for history maluses
+        let mut tried_moves = ScoredMoveList::new();
+
+        let futility_score = static_eval + self.params.fp_margin * (depth + i32::from(improving));
+        let futile = !is_pv
+            && !is_root
+            && !ctx.verifying_mate
+            && !self.mate_search
+            && depth <= self.params.fp_max_depth
+            && alpha.abs() < SCORE_WIN
+            && futility_score <= alpha;
+
+        while let Some(mv) = picker.next(ctx.pos, &ctx.state.history) {
+            if mv == ctx.state.stack[ply_idx].excluded
+                || (is_root && ctx.excluded_root_moves.contains(&mv))
+            {
+                continue;
+            }
+
+            // on top of the node cadence, so an iteration that is
+            // slow to get going cannot run far past the hard limit
+            if is_root && move_idx > 0 && self.deadline_passed(ctx) {
+                break;
+            }
+
+            let flips = ctx.pos.flip_count(mv);
+            let quiet = flips == 0;
+
+            // at least one move is always searched, so the node still gets a real score
+            if futile && move_idx > 0 && quiet {
+                // a skipped move is assumed to score up to the margin, so
+                // a fail low still returns a bound the move cannot beat
+                best_score = best_score.max(futility_score);
+                ctx.stats.futility_skips += 1;
+                continue;
+            }
+
+            let report_root_move = is_root && ctx.reporting && self.verbosity == Verbosity::Debug;
+
+            if is_root
+                && ctx.reporting
+                && (report_root_move
+                    || (self.verbosity == Verbosity::Normal
+                        && ctx.start_time.elapsed() >= CURRMOVE_MIN_TIME))
+            {
+                uai_println!(
+                    "info depth {} currmove {} currmovenumber {}",
+                    depth,
+                    mv,
+                    move_idx + 1
+                );
+            }
+
+            let nodes_before = ctx.nodes;
+
+            ctx.nodes += 1;
+            self.node_counters[ctx.thread_id]
+                .0
+                .store(ctx.nodes, Ordering::Relaxed);
+
+            ctx.state.stack[ply_idx].mv = mv;
+            Self::make_move(ctx, mv);
+
+            let score = if is_pv && move_idx == 0 {
+                -self.search(ctx, -beta, -alpha, depth - 1, ply + 1)
+            } else {
+                ctx.stats.zw_searches += 1;
+
+                let zw_score = -self.search(ctx, -alpha - 1, -alpha, depth - 1, ply + 1);
+                if zw_score > alpha && zw_score < beta {
+                    ctx.stats.pv_researches += 1;
+                    -self.search(ctx, -beta, -alpha, depth - 1, ply + 1)
+                } else {
+                    zw_score
+                }
+            };
+
+            Self::unmake_move(ctx, mv);
+
+            if is_root && !ctx.limiter.stopped() {
+                ctx.record_root_move(mv, ctx.nodes - nodes_before, score);
+            }
+
+            // scores outside the window are only bounds
+            if report_root_move && !ctx.limiter.stopped() {
+                uai_println!(
+                    "info string depth {} move {} nodes {} score {}{}",
+                    depth,
+                    mv,
+                    ctx.nodes - nodes_before,
+                    self.score_format.format(score),
+                    if score <= alpha {
+                        " upperbound"
+                    } else if score >= beta {
+                        " lowerbound"
+                    } else {
+                        ""
+                    }
+                );
+            }
+
+            move_idx += 1;
+
+            if score > best_score {
+                best_score = score;
+
+                if score > alpha {
+                    best_move = mv;
+
+                    if is_root {
+                        ctx.best_move = mv;
+                        ctx.best_move_score = score;
+                    }
+
+                    if is_pv {
+                        ctx.update_pv(ply_idx, mv);
+                    }
+
+                    if score >= beta {
+                        entry_flag = TtEntryFlag::Beta;
+
+                        if quiet {
+                            ctx.update_killers(ply_idx, mv);
+                        }
+
+                        ctx.stats.record_cutoff(move_idx - 1);
+
+                        let stm = ctx.pos.side_to_move();
+                        let bonus = history_bonus(depth);
+
+                        ctx.state.history.update(stm, mv, flips, bonus);
+                        for &(tried, tried_flips) in &tried_moves {
+                            ctx.state
+                                .history
+                                .update(stm, tried, tried_flips as u32, -bonus);
+                        }
+
+                        break;
+                    }
+
+                    alpha = score;
+                    entry_flag = TtEntryFlag::Exact;
+                }
+            }
+
+            tried_moves.push((mv, flips as i32));
+        }
+
+        // with root moves excluded, this is not the real result for the root position
+        let partial_root =
+            is_root && !(ctx.excluded_root_moves.is_empty() && ctx.search_moves.is_empty());
+
+        if !ctx.limiter.stopped() && !partial_root {
+            // a pass is only ever a valid tt move in a position where it is forced
+            let tt_store_move = if best_move == AtaxxMove::Null && !forced_pass {
+                AtaxxMove::None
+            } else {
+                best_move
+            };
+
+            self.tt_store(ctx, tt_key, tt_store_move, best_score, depth, entry_flag);
+
+            // bounds only say which way the eval was wrong if they point away from it
+            if !ctx.verifying_mate
+                && best_score.abs() < SCORE_WIN
+                && match entry_flag {
+                    TtEntryFlag::Exact => true,
+                    TtEntryFlag::Beta => best_score > static_eval,
+                    TtEntryFlag::Alpha => best_score < static_eval,
+                    TtEntryFlag::None => unreachable!(),
+                }
+            {
+                ctx.state
+                    .correction
+                    .update(ctx.pos, depth, raw_eval, best_score);
+            }
+        }
+
+        best_score
+    }
+
+    // only big captures are searched, on top of standing pat
+    fn qsearch(
+        &self,
+        ctx: &mut SearchContext,
+        mut alpha: Score,
+        beta: Score,
+        depth: i32,
+        ply: i32,
+    ) -> Score {
+        ctx.seldepth = ctx.seldepth.max(ply as u32);
+
+        if ctx.pos.game_over() {
+            return self.game_over_score(ctx.pos, ply);
+        }
+
+        let eval = ctx.raw_eval();
+        let stand_pat = scale_by_halfmoves(
+            ctx.state.correction.correct(ctx.pos, eval),
+            ctx.pos.halfmoves(),
+        ) + self.risk_bias(ctx.pos, ply);
+
+        if stand_pat >= beta || depth <= 0 || ply >= MAX_DEPTH {
+            return stand_pat;
+        }
+
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = ScoredMoveList::new();
+        fill_noisy_move_list(&mut moves, ctx.pos, self.params.qs_min_flips);
+        moves.sort_unstable_by(|(_, a_flips), (_, b_flips)| b_flips.cmp(a_flips));
+
+        let mut best_score = stand_pat;
+
+        for &(mv, _) in &moves {
+            ctx.nodes += 1;
+
+            Self::make_move(ctx, mv);
+            let score = -self.qsearch(ctx, -beta, -alpha, depth - 1, ply + 1);
+            Self::unmake_move(ctx, mv);
+
+            if ctx.limiter.stopped() {
+                return beta;
+            }
+
+            if score > best_score {
+                best_score = score;
+
+                if score >= beta {
+                    break;
+                }
+
+                alpha = alpha.max(score);
+            }
+        }
+
+        best_score
+    }
+
+    #[must_use]
+    fn game_over_score(&self, pos: &Position, ply: i32) -> Score {
+        match pos.result() {
+            GameResult::Win(side) => {
+                if side == pos.side_to_move() {
+                    SCORE_MATE - ply
+                } else {
+                    -SCORE_MATE + ply
+                }
+            }
+            GameResult::Draw => self.draw_score(ply),
+        }
+    }
+
+    // the root side is to move at even plies
+    #[must_use]
+    fn draw_score(&self, ply: i32) -> Score {
+        if ply % 2 == 0 {
+            -self.risk
+        } else {
+            self.risk
+        }
+    }
+
+    #[must_use]
+    fn risk_bias(&self, pos: &Position, ply: i32) -> Score {
+        if self.risk == 0 {
+            return 0;
+        }
+
+        let imbalance = (pos.red_occupancy().popcount() as Score
+            - pos.blue_occupancy().popcount() as Score)
+            .abs()
+            .min(RISK_MAX_IMBALANCE);
+
+        let bias = imbalance * RISK_IMBALANCE_MAX_BONUS * self.risk / MAX_RISK;
+
+        if ply % 2 == 0 {
+            bias
+        } else {
+            -bias
+        }
+    }
+
+    fn debug_info(&self, message: impl FnOnce() -> String) {
+        if self.debug {
+            uai_println!("info string {}", message());
+        }
+    }
+
+    fn record(&self, event: TraceEvent) {
+        if let Some(trace) = &self.trace {
+            trace.record(event);
+        }
+    }
+
+    #[must_use]
+    fn should_stop(&self, ctx: &mut SearchContext) -> bool {
+        if self.signals.stop_requested() {
+            ctx.limiter.stop();
+            return true;
+        }
+
+        // no limits apply until the opponent plays the expected move
+        if self.signals.pondering() {
+            return false;
+        }
+
+        ctx.limiter.should_stop(ctx.nodes)
+    }
+
+    #[must_use]
+    fn deadline_passed(&self, ctx: &mut SearchContext) -> bool {
+        !self.signals.pondering() && ctx.limiter.check_clock(ctx.nodes)
+    }
+
+    // only between iterations, where the time manager may end the search before its
+    // allocation runs out, or let it carry on past it
+    fn should_stop_soft(
+        &self,
+        ctx: &mut SearchContext,
+        best_move_stability: u32,
+        best_move_nodes: f64,
+    ) -> bool {
+        !self.signals.pondering()
+            && ctx
+                .limiter
+                .should_stop_soft(ctx.nodes, best_move_stability, best_move_nodes)
+    }
+
+    // the expected reply is whatever the tt has stored for the position after our move
+    fn ponder_move(&self, pos: &mut Position, best_move: AtaxxMove) -> Option<AtaxxMove> {
+        self.tt_pv(pos, best_move, 2).get(1).copied()
+    }
+
+    // from the main thread of the last search
+    #[must_use]
+    pub fn history_score(&self, pos: &Position, mv: AtaxxMove) -> i32 {
+        self.thread_states[0]
+            .history
+            .get(pos.side_to_move(), mv, pos.flip_count(mv))
+    }
+
+    // with canonical hashing, entries keep their moves in the frame of the canonical key
+    #[must_use]
+    fn tt_probe(&self, (key, sym): (u64, Symmetry)) -> Option<TtEntry> {
+        self.ttable.probe(key).map(|mut entry| {
+            entry.mv = sym.invert_move(entry.mv.unpack()).pack();
+            entry
+        })
+    }
+
+    fn tt_store(
+        &self,
+        ctx: &SearchContext,
+        (key, sym): (u64, Symmetry),
+        mv: AtaxxMove,
+        score: Score,
+        depth: i32,
+        flag: TtEntryFlag,
+    ) {
+        let mv = sym.apply_move(mv);
+
+        self.ttable.store(key, mv, score, depth, flag);
+
+        self.record(TraceEvent::TtStore {
+            thread: ctx.thread_id,
+            key,
+            mv,
+            score,
+            depth,
+            flag,
+        });
+    }
+
+    // whatever an earlier search left in the tt for this position
+    #[must_use]
+    pub fn tt_move(&self, pos: &Position) -> AtaxxMove {
+        self.tt_probe(self.ttable.key(pos))
+            .map_or(AtaxxMove::None, |entry| entry.mv.unpack())
+    }
+
+    // follows tt moves from the root, stopping at the first missing or illegal one
+    #[must_use]
+    pub fn tt_pv(
+        &self,
+        pos: &mut Position,
+        best_move: AtaxxMove,
+        max_len: usize,
+    ) -> Vec<AtaxxMove> {
+        let mut pv = Vec::new();
+
+        if best_move == AtaxxMove::None || max_len == 0 {
+            return pv;
+        }
+
+        pv.push(best_move);
+        pos.apply_move::<true, true>(best_move, None);
+
+        while pv.len() < max_len && !pos.game_over() {
+            let mut moves = ScoredMoveList::new();
+            fill_scored_move_list(&mut moves, pos);
+
+            let Some(mv) = self
+                .tt_probe(self.ttable.key(pos))
+                .map(|entry| entry.mv.unpack())
+                .filter(|&mv| moves.iter().any(|&(legal, _)| legal == mv))
+            else {
+                break;
+            };
+
+            pv.push(mv);
+            pos.apply_move::<true, true>(mv, None);
+        }
+
+        for _ in 0..pv.len() {
+            pos.pop_move::<true>(None);
+        }
+
+        pv
+    }
+
+    // a pass leaves the board untouched, so the
+    // accumulators are neither pushed nor popped for it
+    fn make_move(ctx: &mut SearchContext, mv: AtaxxMove) {
+        if mv == AtaxxMove::Null {
+            ctx.pos.apply_move::<true, true>(mv, None);
+        } else {
+            ctx.pos
+                .apply_move::<true, true>(mv, Some(&mut ctx.nnue_state));
+        }
+    }
+
+    fn unmake_move(ctx: &mut SearchContext, mv: AtaxxMove) {
+        if mv == AtaxxMove::Null {
+            ctx.pos.pop_move::<true>(None);
+        } else {
+            ctx.pos.pop_move::<true>(Some(&mut ctx.nnue_state));
+        }
+    }
+
+    fn report_lines(&self, ctx: &SearchContext, lines: &[RootLine], depth: i32, time: f64) {
+        if lines.is_empty() {
+            self.report(ctx, None, AtaxxMove::None, depth, time, -SCORE_INF);
+            return;
+        }
+
+        for (pv_idx, line) in lines.iter().enumerate() {
+            let multi_pv = if self.skill.multi_pv(self.multi_pv) > 1 {
+                Some(pv_idx + 1)
             } else {
-                format!("cp {}", score)
+                None
+            };
+
+            self.report(ctx, multi_pv, line.mv, line.depth, time, line.score);
+        }
+    }
+
+    fn report(
+        &self,
+        ctx: &SearchContext,
+        multi_pv: Option<usize>,
+        mv: AtaxxMove,
+        depth: i32,
+        time: f64,
+        score: Score,
+    ) {
+        let nodes = self.total_nodes();
+        let nps = (nodes as f64 / time) as usize;
+
+        if let OutputStyle::Pretty { color } = self.output_style {
+            uai_println!(
+                "{}",
+                pretty::row(
+                    multi_pv,
+                    depth,
+                    ctx.seldepth,
+                    score,
+                    (time * 1000.0) as usize,
+                    nodes,
+                    nps,
+                    &mv.to_string(),
+                    color
+                )
+            );
+            return;
+        }
+
+        let multi_pv = multi_pv.map_or(String::new(), |idx| format!("multipv {} ", idx));
+
+        let score = self.score_format.format(score);
+
+        // lines without a recorded pv, such as an unfinished iteration's, only show their move
+        let pv = ctx.root_pv(mv).map_or_else(
+            || mv.to_string(),
+            |pv| {
+                pv.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
             },
-            mv
         );
+
+        if self.verbosity == Verbosity::Minimal {
+            uai_println!("info {}depth {} score {} pv {}", multi_pv, depth, score, pv);
+            return;
+        }
+
+        let hashfull = if self.verbosity == Verbosity::Debug {
+            format!("hashfull {} ", self.ttable.full_permille())
+        } else {
+            String::new()
+        };
+
+        uai_println!(
+            "info {}depth {} seldepth {} time {} nodes {} nps {} {}score {} pv {}",
+            multi_pv,
+            depth,
+            ctx.seldepth,
+            (time * 1000.0) as usize,
+            nodes,
+            nps,
+            hashfull,
+            score,
+            pv
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::{Square, SCORE_INF, SCORE_MATE};
+    use crate::limit::SearchLimiter;
+    use crate::movegen::is_legal;
+    use crate::nnue::{halfmove_network_bytes, use_thread_network};
+    use crate::params::SearchParams;
+    use crate::position::Position;
+    use crate::search::{
+        multi_pv_reduction, scale_by_halfmoves, vote_best_line, RootLine, ScoreFormat,
+        SearchContext, Searcher,
+    };
+
+    // red's only stone is walled in by gaps, so red has to pass every turn
+    const RED_TRAPPED_FEN: &str = "x--4/---4/---4/7/7/7/6o x 0 1";
+
+    fn search_best_move(pos: &mut Position, depth: i32) -> AtaxxMove {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+
+        let mut ctx = SearchContext::new(pos);
+        ctx.nnue_state.reset(ctx.pos);
+
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), depth);
+        ctx.best_move
+    }
+
+    #[test]
+    fn eval_cache_separates_halfmove_buckets() {
+        use_thread_network(&halfmove_network_bytes());
+
+        let mut pos = Position::from_fen("x5o/7/7/7/7/7/o5x x 0 1").unwrap();
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+        let early = ctx.raw_eval();
+
+        // the same board ten halfmoves later, in the next bucket
+        *ctx.pos = Position::from_fen("x5o/7/7/7/7/7/o5x x 10 1").unwrap();
+        ctx.nnue_state.reset(ctx.pos);
+        assert_ne!(ctx.raw_eval(), early);
+    }
+
+    #[test]
+    fn canonical_hash_shares_mirrored_entries() {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+        searcher.set_canonical_hash(true);
+
+        let mut pos = Position::from_fen("x5o/7/2-4/7/1o5/7/o5x x 0 1").unwrap();
+        let mirrored = Position::from_fen("o5x/7/4-2/7/5o1/7/x5o x 0 1").unwrap();
+
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 4);
+
+        let best_move = searcher.tt_move(&pos);
+        let mirror = |sq: Square| sq.flip_horizontal();
+        let expected = match best_move {
+            AtaxxMove::Single(to) => AtaxxMove::Single(mirror(to)),
+            AtaxxMove::Double(from, to) => AtaxxMove::Double(mirror(from), mirror(to)),
+            other => panic!("unexpected tt move {}", other),
+        };
+
+        assert_eq!(searcher.tt_move(&mirrored), expected);
+    }
+
+    #[test]
+    fn root_pv_is_playable() {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+
+        let mut pos = Position::startpos();
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 5);
+
+        let pv = ctx.root_pv(ctx.best_move).unwrap().clone();
+        assert!(pv.len() > 1);
+
+        let mut pos = Position::startpos();
+        for mv in pv {
+            assert!(is_legal(&pos, mv));
+            pos.apply_move::<true, true>(mv, None);
+        }
+    }
+
+    #[test]
+    fn root_cache_keeps_multi_pv_lines() {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+        searcher.set_multi_pv(3);
+
+        let mut pos = Position::startpos();
+        let key = pos.key();
+
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 4);
+
+        let cache = searcher.root_history.probe(key).unwrap();
+        assert_eq!(cache.lines, ctx.root_lines);
+        assert_eq!(cache.lines.len(), 3);
+
+        searcher.new_game();
+        assert!(searcher.root_history.probe(key).is_none());
+    }
+
+    #[test]
+    fn thread_state_memory_scales_with_threads() {
+        let thread_state = |searcher: &Searcher| {
+            searcher
+                .memory_usage()
+                .into_iter()
+                .find(|(name, _)| *name == "thread state")
+                .unwrap()
+                .1
+        };
+
+        let mut searcher = Searcher::new();
+        let single = thread_state(&searcher);
+        assert!(single > 0);
+
+        searcher.set_threads(3);
+        assert_eq!(thread_state(&searcher), single * 3);
+    }
+
+    #[test]
+    fn thread_state_kept_until_new_game() {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+
+        let mut pos = Position::startpos();
+        let key = pos.key();
+
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 4);
+
+        let state = &searcher.thread_states[0];
+        assert!(state.eval_cache.probe(key).is_some());
+        assert!(state
+            .stack
+            .iter()
+            .any(|entry| entry.killers[0] != AtaxxMove::None));
+
+        searcher.new_game();
+
+        let state = &searcher.thread_states[0];
+        assert!(state.eval_cache.probe(key).is_none());
+        assert!(state
+            .stack
+            .iter()
+            .all(|entry| entry.killers[0] == AtaxxMove::None));
+    }
+
+    #[test]
+    fn scores_fail_soft() {
+        let searcher = Searcher::new();
+
+        let mut pos = Position::startpos();
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+
+        let exact = searcher.search(&mut ctx, -SCORE_INF, SCORE_INF, 4, 0);
+
+        // with windows far from the real score, the result lands beyond them
+        let high = searcher.search(&mut ctx, exact - 1001, exact - 1000, 4, 0);
+        assert!(high > exact - 1000);
+
+        let low = searcher.search(&mut ctx, exact + 1000, exact + 1001, 4, 0);
+        assert!(low < exact + 1000);
+    }
+
+    #[test]
+    fn forced_pass_is_best_move() {
+        let mut pos = Position::from_fen(RED_TRAPPED_FEN).unwrap();
+        assert_eq!(search_best_move(&mut pos, 5), AtaxxMove::Null);
+    }
+
+    #[test]
+    fn repeated_passes_restore_position() {
+        let mut pos = Position::from_fen(RED_TRAPPED_FEN).unwrap();
+        pos.apply_move::<true, true>(AtaxxMove::Null, None);
+
+        let fen = pos.to_fen();
+        let key = pos.key();
+
+        let mv = search_best_move(&mut pos, 6);
+        assert_ne!(mv, AtaxxMove::None);
+        assert_ne!(mv, AtaxxMove::Null);
+
+        assert_eq!(pos.to_fen(), fen);
+        assert_eq!(pos.key(), key);
+    }
+
+    #[test]
+    fn pass_only_stored_when_forced() {
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+
+        let mut pos = Position::from_fen(RED_TRAPPED_FEN).unwrap();
+        pos.apply_move::<true, true>(AtaxxMove::Null, None);
+
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+
+        let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 4);
+
+        let blue_entry = searcher.ttable.probe(ctx.pos.key()).unwrap();
+        assert_ne!(blue_entry.mv.unpack(), AtaxxMove::Null);
+
+        ctx.pos
+            .apply_move::<true, true>(AtaxxMove::Single(Square::F1), None);
+
+        let red_entry = searcher.ttable.probe(ctx.pos.key()).unwrap();
+        assert_eq!(red_entry.mv.unpack(), AtaxxMove::Null);
+    }
+
+    #[test]
+    fn mate_search_proves_mate_in_one() {
+        // e3f1 captures blue's last stone, with gaps on the other squares next to it
+        let mut pos = Position::from_fen("7/7/7/7/4x2/5--/6o x 0 1").unwrap();
+
+        let mut searcher = Searcher::new();
+        searcher.resize_tt(1);
+        searcher.set_mate_search(true);
+
+        let mut ctx = SearchContext::new(&mut pos);
+        ctx.nnue_state.reset(ctx.pos);
+
+        let score = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 4);
+
+        assert_eq!(ctx.best_move, AtaxxMove::Double(Square::E3, Square::F1));
+        assert_eq!(score, SCORE_MATE - 1);
+    }
+
+    #[test]
+    fn mate_search_skips_pruning() {
+        let pruned = |mate_search: bool| {
+            let mut searcher = Searcher::new();
+            searcher.resize_tt(1);
+            searcher.set_mate_search(mate_search);
+
+            // probcut would otherwise need a deeper search to get going
+            searcher.set_params(SearchParams {
+                probcut_min_depth: 3,
+                probcut_min_flips: 1,
+                ..SearchParams::default()
+            });
+
+            let mut pos = Position::from_fen("7/7/2xo3/2ox3/7/7/7 x 0 1").unwrap();
+            let mut ctx = SearchContext::new(&mut pos);
+            ctx.nnue_state.reset(ctx.pos);
+
+            let _ = searcher.run_datagen_search(&mut ctx, SearchLimiter::infinite(), 5);
+
+            let stats = &ctx.stats;
+            [
+                stats.razor_tries,
+                stats.nmp_tries,
+                stats.probcut_tries,
+                stats.futility_skips,
+            ]
+        };
+
+        assert!(pruned(false).iter().all(|&count| count > 0));
+        assert_eq!(pruned(true), [0; 4]);
+    }
+
+    #[test]
+    fn multi_pv_reduction_scales_with_gap() {
+        let params = SearchParams::default();
+
+        assert_eq!(multi_pv_reduction(&params, 0, 10), 0);
+        assert_eq!(multi_pv_reduction(&params, -50, 10), 0);
+        assert_eq!(multi_pv_reduction(&params, 250, 10), 2);
+        assert_eq!(
+            multi_pv_reduction(&params, SCORE_INF, 10),
+            params.multi_pv_max_reduction
+        );
+        assert_eq!(multi_pv_reduction(&params, SCORE_INF, 3), 1);
+    }
+
+    #[test]
+    fn evals_shrink_towards_fifty_move_draw() {
+        assert_eq!(scale_by_halfmoves(400, 0), 400);
+        assert_eq!(scale_by_halfmoves(400, 50), 300);
+        assert_eq!(scale_by_halfmoves(-400, 100), -200);
+        assert_eq!(scale_by_halfmoves(400, 120), 200);
+    }
+
+    #[test]
+    fn threads_vote_for_best_move() {
+        let line = |mv, score| RootLine {
+            mv,
+            score,
+            depth: 0,
+        };
+
+        let main = AtaxxMove::Single(Square::B6);
+        let other = AtaxxMove::Double(Square::A7, Square::C5);
+
+        // two helpers agreeing outvote the main thread
+        let voted = vote_best_line(&[
+            (line(main, 30), 10),
+            (line(other, 25), 10),
+            (line(other, 25), 10),
+        ]);
+        assert_eq!(voted.mv, other);
+
+        // unfinished helpers get no say
+        let voted = vote_best_line(&[
+            (line(main, 30), 10),
+            (line(other, 25), 0),
+            (line(AtaxxMove::None, -SCORE_INF), 10),
+        ]);
+        assert_eq!(voted.mv, main);
+
+        // a proven win beats any number of votes
+        let voted = vote_best_line(&[
+            (line(main, 30), 12),
+            (line(main, 30), 12),
+            (line(other, SCORE_MATE - 5), 6),
+        ]);
+        assert_eq!(voted.mv, other);
+    }
+
+    #[test]
+    fn score_formats() {
+        let moves = ScoreFormat::default();
+        let plies = ScoreFormat {
+            mate_in_plies: true,
+            normalize: true,
+            show_wdl: false,
+        };
+
+        assert_eq!(moves.format(SCORE_MATE - 3), "mate 2");
+        assert_eq!(moves.format(-SCORE_MATE + 2), "mate -1");
+        assert_eq!(plies.format(SCORE_MATE - 3), "mate 3");
+        assert_eq!(plies.format(-SCORE_MATE + 2), "mate -2");
+
+        assert_eq!(moves.format(800), "cp 800");
+        assert_eq!(plies.format(800), "cp 200");
     }
 }
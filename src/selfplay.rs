@@ -0,0 +1,117 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::core::{Color, MAX_DEPTH};
+use crate::error::Result;
+use crate::genfens::random_fen;
+use crate::iolog::uai_println;
+use crate::limit::SearchLimiter;
+use crate::position::{GameResult, Position};
+use crate::search::{format_score, SearchContext, Searcher};
+use crate::skill::skill_seed;
+use crate::solver::solve;
+use crate::util::rng::Jsf64Rng;
+
+pub const DEFAULT_SELFPLAY_DEPTH: i32 = 6;
+
+#[derive(Debug, Copy, Clone)]
+pub enum SelfplayLimit {
+    Depth(i32),
+    Nodes(usize),
+}
+
+impl Default for SelfplayLimit {
+    fn default() -> Self {
+        Self::Depth(DEFAULT_SELFPLAY_DEPTH)
+    }
+}
+
+// evals are from red's point of view, so the swings are easy to follow
+pub fn run_selfplay(
+    searcher: &mut Searcher,
+    start: &Position,
+    limit: SelfplayLimit,
+    random_opening: bool,
+) -> Result<()> {
+    let mut pos = if random_opening {
+        let mut rng = Jsf64Rng::new(skill_seed());
+        loop {
+            if let Some(fen) = random_fen(start, &mut rng) {
+                break Position::from_fen(&fen)?;
+            }
+        }
+    } else {
+        start.clone()
+    };
+
+    uai_println!("selfplay from {}", pos.to_fen());
+
+    searcher.new_game();
+
+    let (limiter, depth) = match limit {
+        SelfplayLimit::Depth(depth) => (SearchLimiter::infinite(), depth.clamp(1, MAX_DEPTH)),
+        SelfplayLimit::Nodes(nodes) => (SearchLimiter::fixed_nodes(nodes), MAX_DEPTH),
+    };
+
+    let mut ctx = SearchContext::new(&mut pos);
+
+    let mut solved = None;
+
+    while !ctx.pos.game_over() {
+        // no need to play out a game that is already decided
+        solved = solve(ctx.pos);
+        if solved.is_some() {
+            break;
+        }
+
+        ctx.nnue_state.reset(ctx.pos);
+
+        let score = searcher.run_datagen_search(&mut ctx, limiter.clone(), depth);
+        let mv = ctx.best_move;
+
+        if mv == AtaxxMove::None {
+            break;
+        }
+
+        uai_println!(
+            "{:>4}. {} {:<5} {}",
+            ctx.pos.fullmoves(),
+            ctx.pos.side_to_move().to_char(),
+            mv.to_string(),
+            format_score(score)
+        );
+
+        ctx.pos.apply_move::<true, true>(mv, None);
+    }
+
+    let result = match solved.unwrap_or_else(|| ctx.pos.result()) {
+        GameResult::Win(Color::RED) => "1-0",
+        GameResult::Win(_) => "0-1",
+        GameResult::Draw => "1/2-1/2",
+    };
+
+    if solved.is_some() {
+        uai_println!("adjudicated by solver");
+    }
+
+    uai_println!("result {} after {} moves", result, ctx.pos.fullmoves());
+    uai_println!("final position {}", ctx.pos.to_fen());
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::core::{Color, MAX_DEPTH};
+use crate::error::{Error, Result};
+use crate::limit::SearchLimiter;
+use crate::movegen::is_legal;
+use crate::position::Position;
+use crate::search::{SearchContext, Searcher};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::time::Instant;
+
+// clocks below this fraction of the starting time count as a close call
+const FLAG_RISK_FRACTION: f64 = 0.05;
+
+pub struct TimeControl {
+    pub time_ms: u64,
+    pub inc_ms: u64,
+    pub moves_to_go: u64,
+    pub move_overhead_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct SimulationStats {
+    moves: usize,
+    total_used_ms: f64,
+    max_used_ms: f64,
+    min_remaining_ms: f64,
+    close_calls: usize,
+    flags: usize,
+}
+
+// games are stored one per line, in the same form as the arguments to uai's position command
+fn parse_game(line: &str) -> Option<(Position, Vec<AtaxxMove>)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    let (pos, next) = match parts.first() {
+        Some(&"startpos") => (Position::startpos(), 1usize),
+        Some(&"fen") if parts.len() >= 5 => {
+            (Position::from_fen(&parts[1..5].join(" ")).ok()?, 5usize)
+        }
+        _ => return None,
+    };
+
+    let moves = match parts.get(next) {
+        None => Vec::new(),
+        Some(&"moves") => parts[next + 1..]
+            .iter()
+            .map(|mv| AtaxxMove::from_str(mv).ok())
+            .collect::<Option<Vec<_>>>()?,
+        Some(_) => return None,
+    };
+
+    Some((pos, moves))
+}
+
+// replays recorded games, running the real time manager for every move against a
+// synthetic clock for each side. the recorded move is always played, whatever the search finds
+pub fn run_tc_simulation(searcher: &mut Searcher, path: &str, tc: &TimeControl) -> Result<()> {
+    let file = File::open(path)?;
+
+    let base_time = tc.time_ms as f64;
+
+    let mut stats = SimulationStats {
+        min_remaining_ms: base_time,
+        ..SimulationStats::default()
+    };
+
+    for (game_idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let Some((mut pos, moves)) = parse_game(&line) else {
+            return Err(Error::invalid(
+                &format!("game on line {}", game_idx + 1),
+                &line,
+            ));
+        };
+
+        // checked before searching, so a bad game doesn't cost a search per ply first
+        let mut replay = pos.clone();
+        for (ply, &mv) in moves.iter().enumerate() {
+            if !is_legal(&replay, mv) {
+                return Err(Error::invalid(
+                    &format!(
+                        "move {} at ply {} of game on line {}",
+                        mv,
+                        ply + 1,
+                        game_idx + 1
+                    ),
+                    &line,
+                ));
+            }
+            replay.apply_move::<true, true>(mv, None);
+        }
+
+        searcher.new_game();
+
+        let mut clocks = [base_time; 2];
+        let mut moves_made = [0u64; 2];
+        let mut ctx = SearchContext::new(&mut pos);
+
+        let params = *searcher.params();
+
+        for (ply, &mv) in moves.iter().enumerate() {
+            let stm = ctx.pos.side_to_move();
+            let clock = &mut clocks[stm.idx()];
+            let moves_made = &mut moves_made[stm.idx()];
+
+            let moves_to_go = if tc.moves_to_go > 0 {
+                tc.moves_to_go - *moves_made % tc.moves_to_go
+            } else {
+                0
+            };
+
+            // a flagged side keeps playing on an empty clock, so that the rest of the game is still measured
+            let limiter = SearchLimiter::tournament(
+                clock.max(0.0) as u64,
+                tc.inc_ms,
+                moves_to_go,
+                tc.move_overhead_ms,
+                &params,
+            );
+
+            ctx.nnue_state.reset(ctx.pos);
+
+            let start = Instant::now();
+            let _ = searcher.run_datagen_search(&mut ctx, limiter, MAX_DEPTH);
+            // the overhead stands in for the lag a real game would add to every move
+            let used = start.elapsed().as_secs_f64() * 1000.0 + tc.move_overhead_ms as f64;
+
+            let before = *clock;
+            *clock -= used;
+
+            let flagged = before >= 0.0 && *clock < 0.0;
+
+            println!(
+                "game {} ply {} {} clock {:.0} used {:.0} remaining {:.0}{}",
+                game_idx + 1,
+                ply + 1,
+                if stm == Color::RED { "red" } else { "blue" },
+                before,
+                used,
+                *clock,
+                if flagged { " FLAG" } else { "" }
+            );
+
+            stats.moves += 1;
+            stats.total_used_ms += used;
+            stats.max_used_ms = stats.max_used_ms.max(used);
+            stats.min_remaining_ms = stats.min_remaining_ms.min(*clock);
+
+            if flagged {
+                stats.flags += 1;
+            } else if *clock < base_time * FLAG_RISK_FRACTION {
+                stats.close_calls += 1;
+            }
+
+            *clock += tc.inc_ms as f64;
+            *moves_made += 1;
+
+            // repeating controls get the base time again at the end of each period
+            if moves_to_go == 1 {
+                *clock += base_time;
+            }
+
+            ctx.pos.apply_move::<true, true>(mv, None);
+        }
+    }
+
+    if stats.moves == 0 {
+        println!("no moves simulated");
+        return Ok(());
+    }
+
+    println!("moves {}", stats.moves);
+    println!(
+        "average time used {:.1} ms",
+        stats.total_used_ms / stats.moves as f64
+    );
+    println!("max time used {:.1} ms", stats.max_used_ms);
+    println!("min remaining clock {:.1} ms", stats.min_remaining_ms);
+    println!(
+        "close calls (below {:.0}% of base time) {}",
+        FLAG_RISK_FRACTION * 100.0,
+        stats.close_calls
+    );
+    println!("flags {}", stats.flags);
+
+    Ok(())
+}
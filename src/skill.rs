@@ -0,0 +1,165 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::Score;
+use crate::search::RootLine;
+use crate::util::rng::Jsf64Rng;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MAX_SKILL_LEVEL: u32 = 20;
+
+// below full strength, the search always looks at enough lines to pick a weaker one from
+const SKILL_MULTI_PV: usize = 4;
+
+// the node cap doubles every two levels, from 256 nodes at level 0
+const SKILL_MIN_NODES_LOG2: u32 = 8;
+
+const SKILL_MAX_DELTA: Score = 100;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SkillLevel {
+    level: u32,
+}
+
+impl SkillLevel {
+    #[must_use]
+    pub fn new(level: u32) -> Self {
+        Self {
+            level: level.min(MAX_SKILL_LEVEL),
+        }
+    }
+
+    #[must_use]
+    pub fn full_strength(self) -> bool {
+        self.level >= MAX_SKILL_LEVEL
+    }
+
+    #[must_use]
+    pub fn node_cap(self) -> Option<usize> {
+        if self.full_strength() {
+            return None;
+        }
+
+        let log2 = f64::from(SKILL_MIN_NODES_LOG2) + f64::from(self.level) / 2.0;
+        Some(log2.exp2() as usize)
+    }
+
+    #[must_use]
+    pub fn multi_pv(self, multi_pv: usize) -> usize {
+        if self.full_strength() {
+            multi_pv
+        } else {
+            multi_pv.max(SKILL_MULTI_PV)
+        }
+    }
+
+    // every line gets a random bonus, and worse lines get a bigger one the weaker the level.
+    // lines must be sorted best first
+    #[must_use]
+    pub fn pick(self, lines: &[RootLine], rng: &mut Jsf64Rng) -> Option<RootLine> {
+        let best = *lines.first()?;
+
+        if self.full_strength() {
+            return Some(best);
+        }
+
+        let worst = lines.last().map_or(best.score, |line| line.score);
+
+        let weakness = 120 - 2 * self.level as Score;
+        let delta = (best.score - worst).min(SKILL_MAX_DELTA);
+
+        let mut picked = best;
+        let mut max_score = Score::MIN;
+
+        for line in lines {
+            let noise = rng.next_u32_bounded(weakness as u32) as Score;
+            let push = (weakness * (best.score - line.score) + delta * noise) / 128;
+
+            if line.score + push >= max_score {
+                max_score = line.score + push;
+                picked = *line;
+            }
+        }
+
+        Some(picked)
+    }
+}
+
+// weakened play should differ between sessions
+#[must_use]
+pub fn skill_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+impl Default for SkillLevel {
+    fn default() -> Self {
+        Self::new(MAX_SKILL_LEVEL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SkillLevel, MAX_SKILL_LEVEL};
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::Square;
+    use crate::search::RootLine;
+    use crate::util::rng::Jsf64Rng;
+
+    fn line(sq: Square, score: i32) -> RootLine {
+        RootLine {
+            mv: AtaxxMove::Single(sq),
+            score,
+            depth: 1,
+        }
+    }
+
+    #[test]
+    fn full_strength_plays_best_line() {
+        let skill = SkillLevel::new(MAX_SKILL_LEVEL);
+        let lines = [line(Square::A1, 50), line(Square::B1, 40)];
+
+        let mut rng = Jsf64Rng::new(1);
+        for _ in 0..100 {
+            assert_eq!(skill.pick(&lines, &mut rng), Some(lines[0]));
+        }
+
+        assert_eq!(skill.node_cap(), None);
+    }
+
+    #[test]
+    fn weaker_levels_search_less_and_deviate() {
+        assert!(SkillLevel::new(0).node_cap() < SkillLevel::new(10).node_cap());
+
+        let skill = SkillLevel::new(0);
+        let lines = [
+            line(Square::A1, 50),
+            line(Square::B1, 40),
+            line(Square::C1, 30),
+        ];
+
+        let mut rng = Jsf64Rng::new(1);
+        let deviations = (0..100)
+            .filter_map(|_| skill.pick(&lines, &mut rng))
+            .filter(|picked| picked.mv != lines[0].mv)
+            .count();
+
+        assert!(deviations > 0);
+    }
+}
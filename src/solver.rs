@@ -0,0 +1,120 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::movegen::{fill_move_list, MoveList};
+use crate::position::{GameResult, Position};
+
+// positions with more empty squares than this are not worth trying to solve
+pub const SOLVER_MAX_EMPTIES: u32 = 4;
+
+const SOLVER_MAX_PLIES: i32 = 8;
+const SOLVER_NODE_LIMIT: usize = 200_000;
+
+// proves forced wins within a few plies by searching to the end of the game, with no evaluation
+// at all. anything it returns is exact, but it gives up rather than guess when no proof is found
+struct Solver {
+    nodes: usize,
+}
+
+impl Solver {
+    fn out_of_nodes(&mut self) -> bool {
+        self.nodes += 1;
+        self.nodes > SOLVER_NODE_LIMIT
+    }
+
+    // whether the side to move can force a win in at most `plies` plies
+    fn proves_win(&mut self, pos: &mut Position, plies: i32) -> bool {
+        if pos.game_over() {
+            return pos.result() == GameResult::Win(pos.side_to_move());
+        } else if plies == 0 || self.out_of_nodes() {
+            return false;
+        }
+
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, pos);
+
+        moves.iter().any(|&mv| {
+            pos.apply_move::<true, true>(mv, None);
+            let won = self.proves_loss(pos, plies - 1);
+            pos.pop_move::<true>(None);
+            won
+        })
+    }
+
+    // whether every move of the side to move loses within `plies` plies
+    fn proves_loss(&mut self, pos: &mut Position, plies: i32) -> bool {
+        if pos.game_over() {
+            return pos.result() == GameResult::Win(pos.side_to_move().flip());
+        } else if plies == 0 || self.out_of_nodes() {
+            return false;
+        }
+
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, pos);
+
+        moves.iter().all(|&mv| {
+            pos.apply_move::<true, true>(mv, None);
+            let lost = self.proves_win(pos, plies - 1);
+            pos.pop_move::<true>(None);
+            lost
+        })
+    }
+}
+
+// the game's result if it is already decided, with best play from both sides
+#[must_use]
+pub fn solve(pos: &Position) -> Option<GameResult> {
+    if pos.game_over() {
+        return Some(pos.result());
+    } else if pos.empty_squares().popcount() > SOLVER_MAX_EMPTIES {
+        return None;
+    }
+
+    let mut pos = pos.clone();
+    let mut solver = Solver { nodes: 0 };
+
+    for plies in 1..=SOLVER_MAX_PLIES {
+        if solver.proves_win(&mut pos, plies) {
+            return Some(GameResult::Win(pos.side_to_move()));
+        } else if solver.proves_loss(&mut pos, plies) {
+            return Some(GameResult::Win(pos.side_to_move().flip()));
+        } else if solver.nodes > SOLVER_NODE_LIMIT {
+            break;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::solve;
+    use crate::core::Color;
+    use crate::position::{GameResult, Position};
+
+    #[test]
+    fn solves_decided_endgames() {
+        // red fills the last square and wins on material
+        let pos =
+            Position::from_fen("xxxxxxx/xxxxxxx/xxxxxxx/xxxxxxx/ooooooo/oooooox/oooooo1 x 0 1")
+                .unwrap();
+        assert_eq!(solve(&pos), Some(GameResult::Win(Color::RED)));
+
+        // far from the end, nothing can be proven
+        assert_eq!(solve(&Position::startpos()), None);
+    }
+}
@@ -0,0 +1,150 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// cutoffs after this many moves all land in the last bucket
+const CUTOFF_BUCKETS: usize = 8;
+
+#[must_use]
+fn percent(count: usize, total: usize) -> f64 {
+    count as f64 * 100.0 / total.max(1) as f64
+}
+
+// counters for judging move ordering and pruning, summed over all threads
+// and reported at the end of a search in debug mode
+#[derive(Debug, Clone, Default)]
+pub struct SearchStats {
+    pub tt_probes: usize,
+    pub tt_hits: usize,
+    pub tt_cutoffs: usize,
+    // beta cutoffs by the index of the move that caused them
+    cutoffs: [usize; CUTOFF_BUCKETS],
+    // zero window searches of later moves, and how many had to be searched again
+    pub zw_searches: usize,
+    pub pv_researches: usize,
+    pub aspiration_searches: usize,
+    pub aspiration_researches: usize,
+    pub razor_tries: usize,
+    pub razor_cutoffs: usize,
+    pub nmp_tries: usize,
+    pub nmp_cutoffs: usize,
+    pub probcut_tries: usize,
+    pub probcut_cutoffs: usize,
+    pub futility_skips: usize,
+}
+
+impl SearchStats {
+    pub fn record_cutoff(&mut self, move_idx: usize) {
+        self.cutoffs[move_idx.min(CUTOFF_BUCKETS - 1)] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+
+        for (ours, theirs) in self.cutoffs.iter_mut().zip(other.cutoffs) {
+            *ours += theirs;
+        }
+
+        self.zw_searches += other.zw_searches;
+        self.pv_researches += other.pv_researches;
+        self.aspiration_searches += other.aspiration_searches;
+        self.aspiration_researches += other.aspiration_researches;
+        self.razor_tries += other.razor_tries;
+        self.razor_cutoffs += other.razor_cutoffs;
+        self.nmp_tries += other.nmp_tries;
+        self.nmp_cutoffs += other.nmp_cutoffs;
+        self.probcut_tries += other.probcut_tries;
+        self.probcut_cutoffs += other.probcut_cutoffs;
+        self.futility_skips += other.futility_skips;
+    }
+
+    #[must_use]
+    pub fn tt_summary(&self) -> String {
+        format!(
+            "tt probes {} hits {} ({:.1}%) cutoffs {} ({:.1}%)",
+            self.tt_probes,
+            self.tt_hits,
+            percent(self.tt_hits, self.tt_probes),
+            self.tt_cutoffs,
+            percent(self.tt_cutoffs, self.tt_probes)
+        )
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> Vec<String> {
+        let total_cutoffs = self.cutoffs.iter().sum::<usize>();
+
+        let cutoffs = self
+            .cutoffs
+            .iter()
+            .enumerate()
+            .map(|(idx, &count)| {
+                let plus = if idx == CUTOFF_BUCKETS - 1 { "+" } else { "" };
+                format!("{}{} {:.1}%", idx + 1, plus, percent(count, total_cutoffs))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let tried = |name: &str, cutoffs: usize, tries: usize| {
+            format!(
+                "{} {} of {} ({:.1}%)",
+                name,
+                cutoffs,
+                tries,
+                percent(cutoffs, tries)
+            )
+        };
+
+        vec![
+            self.tt_summary(),
+            format!("beta cutoffs {} by move {}", total_cutoffs, cutoffs),
+            tried("pvs re-searches", self.pv_researches, self.zw_searches),
+            // a window can fail more than once
+            format!(
+                "aspiration windows {} re-searches {}",
+                self.aspiration_searches, self.aspiration_researches
+            ),
+            tried("razoring cutoffs", self.razor_cutoffs, self.razor_tries),
+            tried("nmp cutoffs", self.nmp_cutoffs, self.nmp_tries),
+            tried("probcut cutoffs", self.probcut_cutoffs, self.probcut_tries),
+            format!("futility skips {}", self.futility_skips),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchStats, CUTOFF_BUCKETS};
+
+    #[test]
+    fn late_cutoffs_share_a_bucket() {
+        let mut stats = SearchStats::default();
+        stats.record_cutoff(0);
+        stats.record_cutoff(CUTOFF_BUCKETS - 1);
+        stats.record_cutoff(100);
+
+        let mut total = SearchStats::default();
+        total.merge(&stats);
+        total.merge(&stats);
+
+        assert_eq!(total.cutoffs[0], 2);
+        assert_eq!(total.cutoffs[CUTOFF_BUCKETS - 1], 4);
+        assert_eq!(total.lines()[1].split(' ').nth(2), Some("6"));
+    }
+}
@@ -0,0 +1,280 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::core::Score;
+use crate::error::{Error, Result};
+use crate::movegen::{fill_move_list, MoveList};
+use crate::position::Position;
+use crate::ttable::{TTable, TtEntryFlag};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// everything in an smp search that other threads can observe, in the order it happened.
+// recording is slow, and only meant for tracking down races
+#[derive(Debug, Copy, Clone)]
+pub enum TraceEvent {
+    TtStore {
+        thread: usize,
+        key: u64,
+        mv: AtaxxMove,
+        score: Score,
+        depth: i32,
+        flag: TtEntryFlag,
+    },
+    BestMove {
+        thread: usize,
+        depth: i32,
+        mv: AtaxxMove,
+        score: Score,
+    },
+}
+
+pub struct SearchTrace {
+    fen: String,
+    threads: usize,
+    hash_mb: usize,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl SearchTrace {
+    #[must_use]
+    pub fn new(pos: &Position, threads: usize, hash_mb: usize) -> Self {
+        Self {
+            fen: pos.to_fen(),
+            threads,
+            hash_mb,
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, event: TraceEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "fen {}", self.fen)?;
+        writeln!(out, "threads {}", self.threads)?;
+        writeln!(out, "hash {}", self.hash_mb)?;
+
+        for event in self.events.lock().unwrap().iter() {
+            match *event {
+                TraceEvent::TtStore {
+                    thread,
+                    key,
+                    mv,
+                    score,
+                    depth,
+                    flag,
+                } => writeln!(
+                    out,
+                    "store {} {:016x} {} {} {} {}",
+                    thread,
+                    key,
+                    mv,
+                    score,
+                    depth,
+                    flag_to_str(flag)
+                )?,
+                TraceEvent::BestMove {
+                    thread,
+                    depth,
+                    mv,
+                    score,
+                } => writeln!(out, "best {} {} {} {}", thread, depth, mv, score)?,
+            }
+        }
+
+        out.flush()
+    }
+}
+
+#[must_use]
+fn flag_to_str(flag: TtEntryFlag) -> &'static str {
+    match flag {
+        TtEntryFlag::None => "none",
+        TtEntryFlag::Exact => "exact",
+        TtEntryFlag::Alpha => "alpha",
+        TtEntryFlag::Beta => "beta",
+    }
+}
+
+#[must_use]
+fn parse_flag(s: &str) -> Option<TtEntryFlag> {
+    match s {
+        "none" => Some(TtEntryFlag::None),
+        "exact" => Some(TtEntryFlag::Exact),
+        "alpha" => Some(TtEntryFlag::Alpha),
+        "beta" => Some(TtEntryFlag::Beta),
+        _ => None,
+    }
+}
+
+#[must_use]
+fn parse_move(s: &str) -> Option<AtaxxMove> {
+    if s == "<none>" {
+        Some(AtaxxMove::None)
+    } else {
+        AtaxxMove::from_str(s).ok()
+    }
+}
+
+#[must_use]
+fn parse_event(parts: &[&str]) -> Option<TraceEvent> {
+    match parts {
+        ["store", thread, key, mv, score, depth, flag] => Some(TraceEvent::TtStore {
+            thread: thread.parse().ok()?,
+            key: u64::from_str_radix(key, 16).ok()?,
+            mv: parse_move(mv)?,
+            score: score.parse().ok()?,
+            depth: depth.parse().ok()?,
+            flag: parse_flag(flag)?,
+        }),
+        ["best", thread, depth, mv, score] => Some(TraceEvent::BestMove {
+            thread: thread.parse().ok()?,
+            depth: depth.parse().ok()?,
+            mv: parse_move(mv)?,
+            score: score.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+// replays the recorded tt writes in order into a fresh table, on one thread,
+// so the exact table state at any best move change can be reproduced
+pub fn replay_trace(path: &str) -> Result<()> {
+    let file = File::open(path)?;
+
+    let mut pos = Position::empty();
+    let mut threads = 1usize;
+    let mut hash_mb = TTable::DEFAULT_SIZE_MB;
+
+    let mut events = Vec::new();
+
+    for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        let valid = match parts.first() {
+            None => true,
+            Some(&"fen") => pos.reset_from_fen_parts(&parts[1..]).is_ok(),
+            Some(&"threads") => parts
+                .get(1)
+                .and_then(|s| s.parse().ok())
+                .map(|n| threads = n)
+                .is_some(),
+            Some(&"hash") => parts
+                .get(1)
+                .and_then(|s| s.parse().ok())
+                .map(|n| hash_mb = n)
+                .is_some(),
+            Some(_) => parse_event(&parts)
+                .map(|event| events.push(event))
+                .is_some(),
+        };
+
+        if !valid {
+            return Err(Error::invalid(
+                &format!("trace line {}", line_idx + 1),
+                &line,
+            ));
+        }
+    }
+
+    let root_key = pos.key();
+
+    let mut legal_moves = MoveList::new();
+    fill_move_list(&mut legal_moves, &pos);
+
+    let mut ttable = TTable::new();
+    ttable.resize(hash_mb);
+
+    let mut stores = vec![0usize; threads];
+    let mut illegal = 0usize;
+
+    println!("replaying {} events from {} threads", events.len(), threads);
+
+    for (idx, event) in events.iter().enumerate() {
+        match *event {
+            TraceEvent::TtStore {
+                thread,
+                key,
+                mv,
+                score,
+                depth,
+                flag,
+            } => {
+                if let Some(count) = stores.get_mut(thread) {
+                    *count += 1;
+                }
+
+                ttable.store(key, mv, score, depth, flag);
+
+                if key == root_key && mv != AtaxxMove::None && !legal_moves.contains(&mv) {
+                    println!(
+                        "event {}: thread {} stored illegal root move {}",
+                        idx, thread, mv
+                    );
+                    illegal += 1;
+                }
+            }
+            TraceEvent::BestMove {
+                thread,
+                depth,
+                mv,
+                score,
+            } => {
+                let tt_move = ttable
+                    .probe(root_key)
+                    .map_or(AtaxxMove::None, |entry| entry.mv.unpack());
+
+                println!(
+                    "event {}: thread {} depth {} best {} score {} tt move {}{}",
+                    idx,
+                    thread,
+                    depth,
+                    mv,
+                    score,
+                    tt_move,
+                    if tt_move == mv { "" } else { " (diverged)" }
+                );
+
+                if !legal_moves.contains(&mv) {
+                    println!(
+                        "event {}: thread {} chose illegal root move {}",
+                        idx, thread, mv
+                    );
+                    illegal += 1;
+                }
+            }
+        }
+    }
+
+    for (thread, count) in stores.iter().enumerate() {
+        println!("thread {}: {} tt stores", thread, count);
+    }
+
+    println!("{} illegal root moves", illegal);
+
+    Ok(())
+}
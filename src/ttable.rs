@@ -18,8 +18,13 @@
 
 use crate::ataxx_move::{AtaxxMove, PackedMove};
 use crate::core::{Score, MAX_DEPTH, SCORE_INF};
+use crate::hash::Symmetry;
+use crate::numa;
+use crate::position::Position;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
 pub enum TtEntryFlag {
     None,
     Exact,
@@ -27,6 +32,28 @@ pub enum TtEntryFlag {
     Beta,
 }
 
+impl TtEntryFlag {
+    const MASK: u8 = 0b11;
+
+    #[must_use]
+    fn from_bits(bits: u8) -> Self {
+        match bits & Self::MASK {
+            0 => Self::None,
+            1 => Self::Exact,
+            2 => Self::Alpha,
+            _ => Self::Beta,
+        }
+    }
+}
+
+// generations wrap around, so only the distance to the current one means anything
+const GENERATION_SHIFT: u32 = 2;
+const GENERATION_CYCLE: u8 = 1 << (8 - GENERATION_SHIFT);
+
+// when picking which entry of a full cluster to replace, every search
+// an entry has sat unused counts for this many plies of depth
+const REPLACE_AGE_WEIGHT: i32 = 4;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct TtEntry {
@@ -34,26 +61,88 @@ pub struct TtEntry {
     pub mv: PackedMove,
     pub score: i16,
     pub depth: u8,
-    pub flag: TtEntryFlag,
+    // flag in the low bits, generation above it
+    flag_generation: u8,
 }
 
 const _: () = assert!(std::mem::size_of::<TtEntry>() == 8);
 
-impl Default for TtEntry {
+impl TtEntry {
+    #[must_use]
+    pub fn flag(self) -> TtEntryFlag {
+        TtEntryFlag::from_bits(self.flag_generation)
+    }
+
     #[must_use]
+    fn generation(self) -> u8 {
+        self.flag_generation >> GENERATION_SHIFT
+    }
+
+    #[must_use]
+    fn to_raw(self) -> u64 {
+        // SAFETY: TtEntry is plain old data of exactly 8 bytes
+        unsafe { std::mem::transmute(self) }
+    }
+
+    #[must_use]
+    fn from_raw(raw: u64) -> Self {
+        // SAFETY: every bit pattern is a valid entry, as the flag is decoded on access
+        unsafe { std::mem::transmute(raw) }
+    }
+}
+
+impl Default for TtEntry {
     fn default() -> Self {
         Self {
             key: 0,
             score: 0,
             mv: PackedMove::NONE,
             depth: 0,
-            flag: TtEntryFlag::None,
+            flag_generation: TtEntryFlag::None as u8,
+        }
+    }
+}
+
+const CLUSTER_SIZE: usize = 4;
+
+// half a cache line, so probing a cluster never touches more than one
+#[repr(C, align(32))]
+struct TtCluster {
+    entries: [AtomicU64; CLUSTER_SIZE],
+}
+
+const _: () = assert!(std::mem::size_of::<TtCluster>() == 32);
+
+impl TtCluster {
+    #[must_use]
+    fn empty() -> Self {
+        Self {
+            entries: std::array::from_fn(|_| AtomicU64::new(TtEntry::default().to_raw())),
         }
     }
 }
 
+// only known on linux, elsewhere only failed allocations are caught
+fn available_memory_mb() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kb| kb.trim().parse::<usize>().ok())
+        .map(|kb| kb / 1024)
+}
+
+// entries are stored as atomics so that the table can be shared between search threads.
+// racing writes may lose entries, but never produce a half-written one
 pub struct TTable {
-    table: Vec<TtEntry>,
+    table: Vec<TtCluster>,
+    // spread over all numa nodes rather than wherever it was first touched
+    interleaved: bool,
+    // keyed the same for every mirror image of a position
+    canonical_keys: bool,
+    generation: u8,
 }
 
 impl TTable {
@@ -62,51 +151,195 @@ impl TTable {
     pub const MIN_SIZE_MB: usize = 1;
     pub const MAX_SIZE_MB: usize = 131_072;
 
+    const FULL_SAMPLE_SIZE: usize = 1000;
+
+    // not worth spawning a thread for less than a megabyte
+    const MIN_CLEAR_CHUNK_SIZE: usize = 1024 * 1024 / std::mem::size_of::<TtCluster>();
+
     #[must_use]
     pub fn new() -> Self {
-        let mut result = Self { table: Vec::new() };
+        let mut result = Self {
+            table: Vec::new(),
+            interleaved: false,
+            canonical_keys: false,
+            generation: 0,
+        };
 
         result.resize(Self::DEFAULT_SIZE_MB);
 
         result
     }
 
-    pub fn resize(&mut self, capacity: usize) {
-        let bytes = capacity * 1024 * 1024;
-        let new_size = bytes / std::mem::size_of::<TtEntry>();
+    // sizes beyond the memory actually available are clamped, and failed allocations
+    // are retried at half the size. returns the size that was applied, in mb
+    pub fn resize(&mut self, capacity: usize) -> usize {
+        let mut capacity = capacity.clamp(Self::MIN_SIZE_MB, Self::MAX_SIZE_MB);
 
-        self.table.clear();
-        self.table.shrink_to_fit();
+        self.table = Vec::new();
 
-        self.table.resize_with(new_size, TtEntry::default);
+        capacity = capacity.min(Self::max_affordable_size_mb());
+
+        loop {
+            let new_size = capacity * 1024 * 1024 / std::mem::size_of::<TtCluster>();
+
+            if self.table.try_reserve_exact(new_size).is_ok() {
+                if self.interleaved {
+                    numa::interleave(self.table.spare_capacity_mut());
+                }
+
+                self.table.resize_with(new_size, TtCluster::empty);
+                return capacity;
+            }
+
+            // without even the minimum size there is nothing sensible left to do
+            assert!(
+                capacity > Self::MIN_SIZE_MB,
+                "failed to allocate {} MB for the tt",
+                capacity
+            );
+
+            capacity = (capacity / 2).max(Self::MIN_SIZE_MB);
+        }
     }
 
-    pub fn clear(&mut self) {
-        self.table.fill(TtEntry::default());
+    // reallocates the table
+    pub fn set_interleaved(&mut self, interleaved: bool) {
+        if interleaved != self.interleaved {
+            self.interleaved = interleaved;
+            self.resize(self.size_mb());
+        }
+    }
+
+    // entries stored under the other kind of key are useless, so the table is cleared
+    pub fn set_canonical_keys(&mut self, canonical_keys: bool, threads: usize) {
+        if canonical_keys != self.canonical_keys {
+            self.canonical_keys = canonical_keys;
+            self.clear(threads);
+        }
     }
 
+    // the key to store the position under, and the symmetry mapping its moves into that key's frame
     #[must_use]
-    pub fn probe(&self, key: u64) -> Option<TtEntry> {
-        let entry = self.table[self.index(key)];
-        if entry.flag == TtEntryFlag::None || entry.key != Self::pack_key(key) {
-            None
+    pub fn key(&self, pos: &Position) -> (u64, Symmetry) {
+        if self.canonical_keys {
+            pos.canonical_key()
         } else {
-            Some(entry)
+            (pos.key(), Symmetry::IDENTITY)
         }
     }
 
-    pub fn store(&mut self, key: u64, mv: AtaxxMove, score: Score, depth: i32, flag: TtEntryFlag) {
+    // leaves some headroom for the rest of the engine and the system
+    #[must_use]
+    pub fn max_affordable_size_mb() -> usize {
+        available_memory_mb().map_or(Self::MAX_SIZE_MB, |available| {
+            (available / 4 * 3).clamp(Self::MIN_SIZE_MB, Self::MAX_SIZE_MB)
+        })
+    }
+
+    #[must_use]
+    pub fn size_mb(&self) -> usize {
+        self.table.len() * std::mem::size_of::<TtCluster>() / (1024 * 1024)
+    }
+
+    // split across threads, as touching every entry of a large table
+    // one by one takes long enough to stall a gui waiting on isready
+    pub fn clear(&mut self, threads: usize) {
+        let empty = TtEntry::default().to_raw();
+
+        let chunk_size = self
+            .table
+            .len()
+            .div_ceil(threads.max(1))
+            .max(Self::MIN_CLEAR_CHUNK_SIZE);
+
+        std::thread::scope(|s| {
+            for chunk in self.table.chunks_mut(chunk_size) {
+                s.spawn(move || {
+                    for entry in chunk.iter_mut().flat_map(|cluster| &mut cluster.entries) {
+                        *entry.get_mut() = empty;
+                    }
+                });
+            }
+        });
+    }
+
+    // called once per search, ageing every entry already in the table
+    pub fn new_search(&mut self) {
+        self.generation = (self.generation + 1) % GENERATION_CYCLE;
+    }
+
+    // estimated from the first thousand clusters, which is plenty as indices are uniform.
+    // entries left over from earlier searches are not counted
+    #[must_use]
+    pub fn full_permille(&self) -> usize {
+        let sample = self.table.len().min(Self::FULL_SAMPLE_SIZE);
+
+        let filled = self.table[..sample]
+            .iter()
+            .flat_map(|cluster| &cluster.entries)
+            .filter(|entry| {
+                let entry = TtEntry::from_raw(entry.load(Ordering::Relaxed));
+                entry.flag() != TtEntryFlag::None && entry.generation() == self.generation
+            })
+            .count();
+
+        filled * 1000 / (sample * CLUSTER_SIZE)
+    }
+
+    #[must_use]
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let packed_key = Self::pack_key(key);
+
+        self.table[self.index(key)]
+            .entries
+            .iter()
+            .map(|entry| TtEntry::from_raw(entry.load(Ordering::Relaxed)))
+            .find(|entry| entry.flag() != TtEntryFlag::None && entry.key == packed_key)
+    }
+
+    pub fn store(&self, key: u64, mv: AtaxxMove, score: Score, depth: i32, flag: TtEntryFlag) {
         debug_assert!(score.abs() < SCORE_INF);
         debug_assert!((0..=MAX_DEPTH).contains(&depth));
 
-        let idx = self.index(key);
-        self.table[idx] = TtEntry {
-            key: Self::pack_key(key),
+        let packed_key = Self::pack_key(key);
+        let slot = self.replacement_slot(&self.table[self.index(key)], packed_key);
+
+        let entry = TtEntry {
+            key: packed_key,
             mv: mv.pack(),
             score: score as i16,
             depth: depth as u8,
-            flag,
+            flag_generation: flag as u8 | (self.generation << GENERATION_SHIFT),
         };
+
+        slot.store(entry.to_raw(), Ordering::Relaxed);
+    }
+
+    // the position's own entry or an empty one if there is one,
+    // otherwise whichever is shallowest once its age is accounted for
+    #[must_use]
+    fn replacement_slot<'a>(&self, cluster: &'a TtCluster, packed_key: u16) -> &'a AtomicU64 {
+        let mut worst = &cluster.entries[0];
+        let mut worst_value = i32::MAX;
+
+        for slot in &cluster.entries {
+            let entry = TtEntry::from_raw(slot.load(Ordering::Relaxed));
+
+            if entry.flag() == TtEntryFlag::None || entry.key == packed_key {
+                return slot;
+            }
+
+            let age =
+                i32::from(self.generation.wrapping_sub(entry.generation()) % GENERATION_CYCLE);
+            let value = i32::from(entry.depth) - age * REPLACE_AGE_WEIGHT;
+
+            if value < worst_value {
+                worst = slot;
+                worst_value = value;
+            }
+        }
+
+        worst
     }
 
     #[must_use]
@@ -119,3 +352,54 @@ impl TTable {
         (key & 0xFFFF) as u16
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_empties_every_chunk() {
+        let mut ttable = TTable::new();
+        let keys = [0u64, u64::MAX / 3, u64::MAX / 3 * 2, u64::MAX];
+
+        for key in keys {
+            ttable.store(key, AtaxxMove::Null, 100, 5, TtEntryFlag::Exact);
+            assert!(ttable.probe(key).is_some());
+        }
+
+        ttable.clear(4);
+
+        for key in keys {
+            assert!(ttable.probe(key).is_none());
+        }
+    }
+
+    #[test]
+    fn cluster_replaces_shallowest_then_stale() {
+        let mut ttable = TTable::new();
+
+        // all in the first cluster, with distinct stored keys
+        let keys = [0x10u64, 0x20, 0x30, 0x40];
+        for (idx, &key) in keys.iter().enumerate() {
+            ttable.store(key, AtaxxMove::Null, 0, 10 + idx as i32, TtEntryFlag::Beta);
+        }
+
+        assert!(keys.iter().all(|&key| ttable.probe(key).is_some()));
+        assert_eq!(ttable.full_permille(), 1);
+
+        ttable.store(0x50, AtaxxMove::Null, 0, 2, TtEntryFlag::Alpha);
+        assert!(ttable.probe(keys[0]).is_none());
+        assert!(ttable.probe(0x50).is_some());
+
+        ttable.new_search();
+        assert_eq!(ttable.full_permille(), 0);
+
+        ttable.store(0x60, AtaxxMove::Null, 0, 8, TtEntryFlag::Alpha);
+        assert!(ttable.probe(0x50).is_none());
+
+        // a deeper entry from the last search goes before this search's shallower one
+        ttable.store(0x70, AtaxxMove::Null, 0, 8, TtEntryFlag::Alpha);
+        assert!(ttable.probe(keys[1]).is_none());
+        assert!(ttable.probe(0x60).is_some());
+    }
+}
@@ -16,88 +16,400 @@
  * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::ataxx_move::{AtaxxMove, MoveStrError};
-use crate::bench::{run_bench, DEFAULT_BENCH_DEPTH};
-use crate::core::{Color, MAX_DEPTH};
+use crate::ataxx_move::AtaxxMove;
+use crate::bench::{
+    run_autoconfig, run_bench, run_bench_smp, DEFAULT_AUTOCONFIG_PATH, DEFAULT_BENCH_DEPTH,
+    DEFAULT_SMP_BENCH_MAX_THREADS,
+};
+use crate::core::{Color, Score, Square, MAX_DEPTH};
+use crate::error::{Error, Result};
 use crate::eval::static_eval_once;
-use crate::limit::SearchLimiter;
+use crate::evalcheck::run_evalcheck;
+use crate::evaltrace::run_evaltrace;
+use crate::explain::{run_explain, DEFAULT_EXPLAIN_DEPTH};
+use crate::fuzz::{run_fuzz_fens, DEFAULT_FUZZ_COUNT};
+use crate::genfens::run_genfens;
+use crate::iolog::{log_received, set_log_file, uai_println};
+use crate::limit::{SearchLimiter, SearchSignals, TimeManager};
+use crate::movegen::{fill_move_list, MoveList};
+use crate::nnue::{load_network, network_hash, use_embedded_network};
+use crate::params::Preset;
+#[cfg(feature = "tune")]
+use crate::params::{print_spsa_inputs, SearchParams, TUNABLES};
 use crate::perft::{perft, split_perft};
-use crate::position::Position;
-use crate::search::Searcher;
+use crate::position::{GameResult, Position};
+use crate::search::{Searcher, Verbosity, MAX_RISK};
+use crate::selfplay::{run_selfplay, SelfplayLimit};
+use crate::skill::MAX_SKILL_LEVEL;
+use crate::trace::replay_trace;
 use crate::ttable::TTable;
+use crate::watchdog::{spawn_watchdog, watchdog_deadline};
+use crate::wdl::run_wdl_fit;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Instant;
 
 const NAME: &str = "Sanctaphraxx";
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 
+const MAX_MULTI_PV: usize = 256;
+const MAX_NODES_TIME: u64 = 100_000;
+
 struct UaiHandler {
-    searcher: Searcher,
+    searcher: Arc<Mutex<Searcher>>,
+    signals: Arc<SearchSignals>,
+    search_thread: Option<JoinHandle<()>>,
+    watchdog_thread: Option<JoinHandle<bool>>,
     pos: Position,
+    move_overhead: u64,
+    // if not 0, times in go are node budgets at this many nodes per ms
+    nodes_time: u64,
+    eval_file: Option<String>,
+    hash_mb: usize,
+    // the size last asked of the tt once limited, which it may not have managed to allocate
+    applied_hash_mb: usize,
+    // 0 for no limit
+    max_memory_mb: usize,
+    debug: bool,
+    // last value set for every option, in the order they should be reapplied
+    option_values: Vec<(String, String)>,
 }
 
 #[allow(clippy::unused_self)]
 impl UaiHandler {
     #[must_use]
     fn new() -> Self {
+        let searcher = Self::create_searcher();
+        let signals = searcher.signals();
+
         Self {
-            searcher: Searcher::new(),
+            searcher: Arc::new(Mutex::new(searcher)),
+            signals,
+            search_thread: None,
+            watchdog_thread: None,
             pos: Position::startpos(),
+            move_overhead: TimeManager::DEFAULT_MOVE_OVERHEAD_MS,
+            nodes_time: 0,
+            eval_file: None,
+            hash_mb: TTable::DEFAULT_SIZE_MB,
+            applied_hash_mb: TTable::DEFAULT_SIZE_MB,
+            max_memory_mb: 0,
+            debug: false,
+            option_values: Self::default_option_values(),
         }
     }
 
-    fn run(&mut self) {
-        let mut line = String::with_capacity(256);
-        while let Ok(bytes) = std::io::stdin().read_line(&mut line) {
-            if bytes == 0 {
-                break;
+    fn default_option_values() -> Vec<(String, String)> {
+        #[allow(unused_mut)]
+        let mut values = vec![
+            ("Hash", TTable::DEFAULT_SIZE_MB.to_string()),
+            ("Threads", 1.to_string()),
+            ("MaxMemory", 0.to_string()),
+            (
+                "Move Overhead",
+                TimeManager::DEFAULT_MOVE_OVERHEAD_MS.to_string(),
+            ),
+            ("nodestime", 0.to_string()),
+            ("Ponder", false.to_string()),
+            ("MultiPV", 1.to_string()),
+            ("Preset", Preset::Default.name().to_string()),
+            ("Skill Level", MAX_SKILL_LEVEL.to_string()),
+            ("Verbosity", Verbosity::Normal.name().to_string()),
+            ("EvalFile", "<embedded>".to_string()),
+            ("Pretty", std::io::stdout().is_terminal().to_string()),
+            ("MateSearch", false.to_string()),
+            ("Mate In Plies", false.to_string()),
+            ("Normalize Scores", false.to_string()),
+            ("UAI_ShowWDL", false.to_string()),
+            ("Risk", 0.to_string()),
+            ("Canonical Hash", false.to_string()),
+            ("ThreadAffinity", false.to_string()),
+            ("NUMA Interleave", false.to_string()),
+            ("Analysis Burst", false.to_string()),
+            ("Search Trace", "<empty>".to_string()),
+            ("Log File", "<empty>".to_string()),
+        ];
+
+        // after Preset, so loading a saved file does not reset them
+        #[cfg(feature = "tune")]
+        {
+            let params = SearchParams::default();
+            for tunable in &TUNABLES {
+                values.push((tunable.name, (tunable.get)(&params).to_string()));
             }
+        }
 
-            let cmd: Vec<&str> = line.split_whitespace().collect();
-            if cmd.is_empty() {
-                line.clear();
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    #[must_use]
+    fn create_searcher() -> Searcher {
+        let mut searcher = Searcher::new();
+        searcher.set_pretty(std::io::stdout().is_terminal());
+        searcher
+    }
+
+    fn wait_for_search(&mut self) {
+        // exits as soon as it sees the search's bestmove, or once it has answered for a stuck one
+        let watchdog_fired = self
+            .watchdog_thread
+            .take()
+            .is_some_and(|handle| handle.join().expect("watchdog thread panicked"));
+
+        if watchdog_fired {
+            // a stuck search never lets go of its searcher, so both are left behind
+            self.search_thread = None;
+            self.replace_searcher();
+        } else if let Some(handle) = self.search_thread.take() {
+            handle.join().expect("search thread panicked");
+        }
+    }
+
+    // a fresh searcher with every option set as it was. the network and log
+    // file belong to the whole process, so they are still in place
+    fn replace_searcher(&mut self) {
+        let mut searcher = Self::create_searcher();
+        searcher.set_debug(self.debug);
+
+        self.signals = searcher.signals();
+        self.searcher = Arc::new(Mutex::new(searcher));
+        self.applied_hash_mb = TTable::DEFAULT_SIZE_MB;
+
+        for (name, value) in self.option_values.clone() {
+            if name == "EvalFile" || name == "Log File" {
                 continue;
             }
 
-            match cmd[0] {
-                "uai" => self.handle_uai(),
-                "uainewgame" => self.handle_uainewgame(),
-                "setoption" => self.handle_setoption(&cmd[1..]),
-                "isready" => self.handle_isready(),
-                "position" => self.handle_position(&cmd[1..]),
-                "go" => self.handle_go(&cmd[1..]),
-                "d" => self.handle_d(),
-                "perft" => self.handle_perft(&cmd[1..]),
-                "splitperft" => self.handle_splitperft(&cmd[1..]),
-                "bench" => self.handle_bench(&cmd[1..]),
-                "quit" => break,
-                unknown => eprintln!("Unknown command '{}'", unknown),
+            if let Err(err) = self.apply_option(&name, value) {
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    // blocks until any running search has finished
+    fn searcher(&mut self) -> MutexGuard<'_, Searcher> {
+        self.wait_for_search();
+        self.searcher.lock().unwrap()
+    }
+
+    // commands passed on the command line run first, which is how openbench drives genfens
+    fn run(&mut self, commands: &[String]) {
+        if commands.iter().all(|command| self.handle_line(command)) {
+            let mut line = String::with_capacity(256);
+            while let Ok(bytes) = std::io::stdin().read_line(&mut line) {
+                if bytes == 0 || !self.handle_line(&line) {
+                    break;
+                }
+
+                line.clear();
+            }
+        }
+
+        self.handle_stop();
+    }
+
+    // returns false on quit
+    fn handle_line(&mut self, line: &str) -> bool {
+        log_received(line.trim_end());
+
+        let cmd: Vec<&str> = line.split_whitespace().collect();
+        if cmd.is_empty() {
+            return true;
+        }
+
+        let result = match cmd[0] {
+            // ugi is a superset of uai for our purposes, the same handler serves both
+            "uai" => {
+                self.handle_uai("uai");
+                Ok(())
+            }
+            "ugi" => {
+                self.handle_uai("ugi");
+                Ok(())
+            }
+            "uainewgame" | "uginewgame" => {
+                self.handle_uainewgame();
+                Ok(())
+            }
+            "setoption" => self.handle_setoption(&cmd[1..]),
+            "isready" => {
+                self.handle_isready();
+                Ok(())
+            }
+            "position" => self.handle_position(&cmd[1..]),
+            "go" => self.handle_go(&cmd[1..]),
+            "stop" => {
+                self.handle_stop();
+                Ok(())
+            }
+            "ponderhit" => {
+                self.handle_ponderhit();
+                Ok(())
+            }
+            "d" => {
+                self.handle_d();
+                Ok(())
+            }
+            "setgap" => self.handle_setgap(&cmd[1..]),
+            "perft" => self.handle_perft(&cmd[1..]),
+            "splitperft" => self.handle_splitperft(&cmd[1..]),
+            "moves" => {
+                self.handle_moves();
+                Ok(())
+            }
+            "bench" => self.handle_bench(&cmd[1..]),
+            "replaytrace" => self.handle_replaytrace(&cmd[1..]),
+            "evalcheck" => Self::handle_evalcheck(&cmd[1..]),
+            "evaltrace" => {
+                run_evaltrace(&self.pos);
+                Ok(())
+            }
+            "wdlfit" => {
+                if cmd.len() < 2 {
+                    Err(Error::missing("dataset file"))
+                } else {
+                    run_wdl_fit(&cmd[1..].join(" "))
+                }
+            }
+            "genfens" => Self::handle_genfens(&cmd[1..]),
+            "selfplay" => self.handle_selfplay(&cmd[1..]),
+            "explain" => self.handle_explain(&cmd[1..]),
+            "fuzz" => Self::handle_fuzz(&cmd[1..]),
+            "autoconfig" => self.handle_autoconfig(&cmd[1..]),
+            "query" => self.handle_query(&cmd[1..]),
+            "reloadnet" => self.load_eval_file(),
+            #[cfg(feature = "tune")]
+            "spsa" => {
+                print_spsa_inputs();
+                Ok(())
+            }
+            "debug" => self.handle_debug(&cmd[1..]),
+            "memory" => {
+                self.handle_memory();
+                Ok(())
             }
+            "rootstats" => {
+                self.searcher().print_root_stats();
+                Ok(())
+            }
+            "options" => self.handle_options(&cmd[1..]),
+            "quit" => return false,
+            unknown => Err(Error::unknown("command", unknown)),
+        };
 
-            line.clear();
+        if let Err(err) = result {
+            eprintln!("{}", err);
         }
+
+        true
     }
 
-    fn handle_uai(&self) {
-        println!("id name {} {}", NAME, VERSION);
-        println!("id author {}", AUTHORS.replace(':', ", "));
-        println!(
+    fn handle_uai(&self, protocol: &str) {
+        uai_println!("id name {} {}", NAME, VERSION);
+        uai_println!("id author {}", AUTHORS.replace(':', ", "));
+        uai_println!(
             "option name Hash type spin default {} min {} max {}",
             TTable::DEFAULT_SIZE_MB,
             TTable::MIN_SIZE_MB,
             TTable::MAX_SIZE_MB
         );
-        println!("uaiok");
+        uai_println!(
+            "option name Threads type spin default 1 min 1 max {}",
+            Searcher::MAX_THREADS
+        );
+        uai_println!(
+            "option name MaxMemory type spin default 0 min 0 max {}",
+            TTable::MAX_SIZE_MB
+        );
+        uai_println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            TimeManager::DEFAULT_MOVE_OVERHEAD_MS,
+            TimeManager::MAX_MOVE_OVERHEAD_MS
+        );
+        uai_println!(
+            "option name nodestime type spin default 0 min 0 max {}",
+            MAX_NODES_TIME
+        );
+        uai_println!("option name Ponder type check default false");
+        uai_println!(
+            "option name MultiPV type spin default 1 min 1 max {}",
+            MAX_MULTI_PV
+        );
+        uai_println!(
+            "option name Preset type combo default {} {}",
+            Preset::Default.name(),
+            Preset::ALL
+                .iter()
+                .map(|preset| format!("var {}", preset.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        uai_println!(
+            "option name Skill Level type spin default {} min 0 max {}",
+            MAX_SKILL_LEVEL,
+            MAX_SKILL_LEVEL
+        );
+        uai_println!(
+            "option name Verbosity type combo default {} {}",
+            Verbosity::Normal.name(),
+            Verbosity::ALL
+                .iter()
+                .map(|verbosity| format!("var {}", verbosity.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        uai_println!("option name EvalFile type string default <embedded>");
+        // pretty output is on by default when talking to a terminal rather than a gui
+        uai_println!(
+            "option name Pretty type check default {}",
+            std::io::stdout().is_terminal()
+        );
+        uai_println!("option name MateSearch type check default false");
+        uai_println!("option name Mate In Plies type check default false");
+        uai_println!("option name Normalize Scores type check default false");
+        uai_println!("option name UAI_ShowWDL type check default false");
+        uai_println!(
+            "option name Risk type spin default 0 min 0 max {}",
+            MAX_RISK
+        );
+        uai_println!("option name Canonical Hash type check default false");
+        uai_println!("option name ThreadAffinity type check default false");
+        uai_println!("option name NUMA Interleave type check default false");
+        uai_println!("option name Analysis Burst type check default false");
+        uai_println!("option name Search Trace type string default <empty>");
+        uai_println!("option name Log File type string default <empty>");
+
+        #[cfg(feature = "tune")]
+        {
+            let params = SearchParams::default();
+            for tunable in &TUNABLES {
+                uai_println!(
+                    "option name {} type spin default {} min {} max {}",
+                    tunable.name,
+                    (tunable.get)(&params),
+                    tunable.min,
+                    tunable.max
+                );
+            }
+        }
+
+        uai_println!("{}ok", protocol);
     }
 
     fn handle_uainewgame(&mut self) {
-        self.searcher.new_game();
+        self.searcher().new_game();
     }
 
-    fn handle_setoption(&mut self, args: &[&str]) {
+    fn handle_setoption(&mut self, args: &[&str]) -> Result<()> {
         if args.len() < 2 || args[0] != "name" {
-            eprintln!("Missing name");
-            return;
+            return Err(Error::missing("name"));
         }
 
         let mut idx = 1usize;
@@ -106,33 +418,417 @@ impl UaiHandler {
         }
 
         if idx > args.len() - 2 || args[idx] != "value" {
-            eprintln!("Missing value");
-            return;
+            return Err(Error::missing("value"));
         }
 
         let name = args[1usize..idx].join(" ");
         let value = args[(idx + 1)..].join(" ");
 
-        #[allow(clippy::single_match)]
-        match name.as_str() {
+        if self.apply_option(&name, value.clone())? {
+            if let Some(entry) = self.option_values.iter_mut().find(|(n, _)| *n == name) {
+                entry.1 = value;
+            } else {
+                self.option_values.push((name, value));
+            }
+        }
+
+        Ok(())
+    }
+
+    // returns false for options we do not have
+    fn apply_option(&mut self, name: &str, value: String) -> Result<bool> {
+        match name {
             "Hash" => {
                 if let Ok(new_size) = value.parse::<usize>() {
-                    self.searcher.resize_tt(new_size);
+                    self.hash_mb = new_size.clamp(TTable::MIN_SIZE_MB, TTable::MAX_SIZE_MB);
+                    self.apply_memory_limit();
+                } else {
+                    return Err(Error::invalid("hash size", &value));
+                }
+            }
+            "Threads" => {
+                if let Ok(threads) = value.parse::<usize>() {
+                    self.searcher().set_threads(threads);
+                    self.apply_memory_limit();
+                } else {
+                    return Err(Error::invalid("thread count", &value));
+                }
+            }
+            "MaxMemory" => {
+                if let Ok(max_memory) = value.parse::<usize>() {
+                    self.max_memory_mb = max_memory;
+                    self.apply_memory_limit();
+                } else {
+                    return Err(Error::invalid("memory limit", &value));
+                }
+            }
+            "Move Overhead" => {
+                if let Ok(move_overhead) = value.parse::<u64>() {
+                    self.move_overhead = move_overhead.min(TimeManager::MAX_MOVE_OVERHEAD_MS);
+                } else {
+                    return Err(Error::invalid("move overhead", &value));
+                }
+            }
+            "nodestime" => {
+                if let Ok(nodes_time) = value.parse::<u64>() {
+                    self.nodes_time = nodes_time.min(MAX_NODES_TIME);
+                } else {
+                    return Err(Error::invalid("nodestime value", &value));
+                }
+            }
+            "MultiPV" => {
+                if let Ok(multi_pv) = value.parse::<usize>() {
+                    self.searcher()
+                        .set_multi_pv(multi_pv.clamp(1, MAX_MULTI_PV));
+                } else {
+                    return Err(Error::invalid("MultiPV value", &value));
+                }
+            }
+            "Preset" => {
+                if let Ok(preset) = Preset::from_str(&value) {
+                    self.searcher().set_params(preset.params());
+                } else {
+                    return Err(Error::unknown("preset", &value));
+                }
+            }
+            "EvalFile" => {
+                self.eval_file = if value.is_empty() || value == "<embedded>" {
+                    None
                 } else {
-                    eprintln!("Invalid hash size");
+                    Some(value)
+                };
+
+                self.load_eval_file()?;
+            }
+            "Pretty" => {
+                if let Ok(pretty) = value.parse::<bool>() {
+                    self.searcher().set_pretty(pretty);
+                } else {
+                    return Err(Error::invalid("Pretty value", &value));
+                }
+            }
+            "ThreadAffinity" => {
+                if let Ok(thread_affinity) = value.parse::<bool>() {
+                    self.searcher().set_thread_affinity(thread_affinity);
+                } else {
+                    return Err(Error::invalid("ThreadAffinity value", &value));
+                }
+            }
+            "NUMA Interleave" => {
+                if let Ok(interleave) = value.parse::<bool>() {
+                    self.searcher().set_numa_interleave(interleave);
+                } else {
+                    return Err(Error::invalid("NUMA Interleave value", &value));
+                }
+            }
+            "Analysis Burst" => {
+                if let Ok(analysis_burst) = value.parse::<bool>() {
+                    self.searcher().set_analysis_burst(analysis_burst);
+                } else {
+                    return Err(Error::invalid("Analysis Burst value", &value));
+                }
+            }
+            "Canonical Hash" => {
+                if let Ok(canonical_hash) = value.parse::<bool>() {
+                    self.searcher().set_canonical_hash(canonical_hash);
+                } else {
+                    return Err(Error::invalid("Canonical Hash value", &value));
+                }
+            }
+            "Mate In Plies" => {
+                if let Ok(mate_in_plies) = value.parse::<bool>() {
+                    let mut score_format = self.searcher().score_format();
+                    score_format.mate_in_plies = mate_in_plies;
+                    self.searcher().set_score_format(score_format);
+                } else {
+                    return Err(Error::invalid("Mate In Plies value", &value));
+                }
+            }
+            "Normalize Scores" => {
+                if let Ok(normalize) = value.parse::<bool>() {
+                    let mut score_format = self.searcher().score_format();
+                    score_format.normalize = normalize;
+                    self.searcher().set_score_format(score_format);
+                } else {
+                    return Err(Error::invalid("Normalize Scores value", &value));
+                }
+            }
+            "UAI_ShowWDL" => {
+                if let Ok(show_wdl) = value.parse::<bool>() {
+                    let mut score_format = self.searcher().score_format();
+                    score_format.show_wdl = show_wdl;
+                    self.searcher().set_score_format(score_format);
+                } else {
+                    return Err(Error::invalid("UAI_ShowWDL value", &value));
+                }
+            }
+            "Risk" => {
+                if let Ok(risk) = value.parse::<Score>() {
+                    self.searcher().set_risk(risk);
+                } else {
+                    return Err(Error::invalid("Risk value", &value));
+                }
+            }
+            "Verbosity" => {
+                if let Ok(verbosity) = Verbosity::from_str(&value) {
+                    self.searcher().set_verbosity(verbosity);
+                } else {
+                    return Err(Error::unknown("verbosity", &value));
+                }
+            }
+            "Skill Level" => {
+                if let Ok(level) = value.parse::<u32>() {
+                    self.searcher().set_skill_level(level.min(MAX_SKILL_LEVEL));
+                } else {
+                    return Err(Error::invalid("Skill Level value", &value));
+                }
+            }
+            "MateSearch" => {
+                if let Ok(mate_search) = value.parse::<bool>() {
+                    self.searcher().set_mate_search(mate_search);
+                } else {
+                    return Err(Error::invalid("MateSearch value", &value));
+                }
+            }
+            "Search Trace" => {
+                let path = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    Some(value)
+                };
+                self.searcher().set_trace_path(path);
+            }
+            "Log File" => {
+                let path = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    Some(value.as_str())
+                };
+                set_log_file(path)?;
+            }
+            // pondering is driven entirely by the gui, so the value only needs validating
+            "Ponder" => {
+                if value.parse::<bool>().is_err() {
+                    return Err(Error::invalid("ponder value", &value));
+                }
+            }
+            #[cfg(feature = "tune")]
+            name => {
+                if let Some(tunable) = TUNABLES.iter().find(|tunable| tunable.name == name) {
+                    let Ok(value) = value.parse::<i64>() else {
+                        return Err(Error::invalid(&format!("{} value", name), &value));
+                    };
+
+                    let mut params = *self.searcher().params();
+                    (tunable.set)(&mut params, value.clamp(tunable.min, tunable.max));
+                    self.searcher().set_params(params);
+                } else {
+                    return Ok(false);
                 }
             }
-            _ => {}
+            #[cfg(not(feature = "tune"))]
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    // saved files are plain setoption commands, so autoconfig output loads too
+    fn handle_options(&mut self, args: &[&str]) -> Result<()> {
+        let Some(&subcommand) = args.first() else {
+            return Err(Error::missing("options subcommand"));
+        };
+
+        if args.len() < 2 {
+            return Err(Error::missing("options file"));
+        }
+
+        let path = args[1..].join(" ");
+
+        match subcommand {
+            "save" => {
+                let mut out = BufWriter::new(File::create(&path)?);
+
+                for (name, value) in &self.option_values {
+                    writeln!(out, "setoption name {} value {}", name, value)?;
+                }
+
+                out.flush()?;
+
+                uai_println!(
+                    "info string saved {} options to {}",
+                    self.option_values.len(),
+                    path
+                );
+            }
+            "load" => {
+                let mut count = 0usize;
+
+                for line in BufReader::new(File::open(&path)?).lines() {
+                    let line = line?;
+                    let args: Vec<&str> = line.split_whitespace().collect();
+
+                    match args.first() {
+                        None => {}
+                        Some(&"setoption") => {
+                            self.handle_setoption(&args[1..])?;
+                            count += 1;
+                        }
+                        Some(_) => return Err(Error::invalid("options line", &line)),
+                    }
+                }
+
+                uai_println!("info string loaded {} options from {}", count, path);
+            }
+            unknown => return Err(Error::unknown("options subcommand", unknown)),
+        }
+
+        Ok(())
+    }
+
+    // also used to reload a net that has been overwritten on disk
+    fn load_eval_file(&mut self) -> Result<()> {
+        let eval_file = self.eval_file.clone();
+
+        // nothing may evaluate while the network is swapped out, and a search
+        // left running would never finish, as its stop could not be read
+        self.handle_stop();
+        let mut searcher = self.searcher();
+
+        if let Some(path) = &eval_file {
+            load_network(path)?;
+        } else {
+            use_embedded_network();
         }
+
+        // anything in the tt was evaluated by the previous network,
+        // which a failed load leaves in place along with its tt
+        searcher.new_game();
+        drop(searcher);
+
+        uai_println!(
+            "info string loaded network {} hash {:016x}",
+            self.eval_file.as_deref().unwrap_or("<embedded>"),
+            network_hash()
+        );
+
+        Ok(())
+    }
+
+    // benchmarks with a searcher of its own, which the engine's search would compete
+    // with for the cores and its tt for memory. the search is stopped and the tt
+    // shrunk to nothing for the duration, and whatever MaxMemory leaves is all it gets
+    fn handle_autoconfig(&mut self, args: &[&str]) -> Result<()> {
+        let path = if args.is_empty() {
+            DEFAULT_AUTOCONFIG_PATH.to_string()
+        } else {
+            args.join(" ")
+        };
+
+        self.handle_stop();
+
+        let max_memory_mb = self.max_memory_mb;
+        let mut searcher = self.searcher();
+
+        searcher.resize_tt(TTable::MIN_SIZE_MB);
+
+        let memory_budget_mb = (max_memory_mb > 0).then(|| {
+            max_memory_mb.saturating_sub(searcher.fixed_memory_mb() + TTable::MIN_SIZE_MB)
+        });
+
+        drop(searcher);
+        self.applied_hash_mb = TTable::MIN_SIZE_MB;
+
+        let result = run_autoconfig(&path, memory_budget_mb);
+
+        // puts the hash back
+        self.apply_memory_limit();
+
+        result
+    }
+
+    fn handle_debug(&mut self, args: &[&str]) -> Result<()> {
+        match args.first() {
+            Some(&"on") => self.debug = true,
+            Some(&"off") => self.debug = false,
+            Some(value) => return Err(Error::invalid("debug mode", value)),
+            None => return Err(Error::missing("debug mode")),
+        }
+
+        let debug = self.debug;
+        self.searcher().set_debug(debug);
+
+        Ok(())
+    }
+
+    // everything but the tt has a fixed size, so the tt gets whatever is left of the budget
+    fn apply_memory_limit(&mut self) {
+        let max_memory_mb = self.max_memory_mb;
+        let mut hash_mb = self.hash_mb;
+        let applied_hash_mb = self.applied_hash_mb;
+
+        let mut searcher = self.searcher();
+        let mut limited = false;
+
+        if max_memory_mb > 0 {
+            let limit = max_memory_mb
+                .saturating_sub(searcher.fixed_memory_mb())
+                .max(TTable::MIN_SIZE_MB);
+
+            if hash_mb > limit {
+                hash_mb = limit;
+                limited = true;
+            }
+        }
+
+        // resizing clears the tt, so avoid it when nothing changed. the tt's own size
+        // is no use for this, as it falls short of the request when allocation fails
+        if hash_mb == applied_hash_mb {
+            return;
+        }
+
+        if limited {
+            uai_println!("info string hash limited to {} MB by MaxMemory", hash_mb);
+        }
+
+        let applied = searcher.resize_tt(hash_mb);
+        if applied != hash_mb {
+            uai_println!(
+                "info string could not allocate {} MB of hash, using {} MB",
+                hash_mb,
+                applied
+            );
+        }
+
+        drop(searcher);
+        self.applied_hash_mb = hash_mb;
+    }
+
+    fn handle_memory(&mut self) {
+        let usage = self.searcher().memory_usage();
+
+        for (name, bytes) in &usage {
+            uai_println!(
+                "info string memory {} {:.2} MB",
+                name,
+                *bytes as f64 / (1024.0 * 1024.0)
+            );
+        }
+
+        let total: usize = usage.iter().map(|(_, bytes)| bytes).sum();
+        uai_println!(
+            "info string memory total {:.2} MB",
+            total as f64 / (1024.0 * 1024.0)
+        );
     }
 
     fn handle_isready(&self) {
-        println!("readyok");
+        uai_println!("readyok");
     }
 
-    fn handle_position(&mut self, args: &[&str]) {
+    fn handle_position(&mut self, args: &[&str]) -> Result<()> {
         if args.is_empty() {
-            return;
+            return Ok(());
         }
 
         let next = match args[0] {
@@ -141,43 +837,33 @@ impl UaiHandler {
                 1usize
             }
             "fen" => {
-                if let Err(err) = self.pos.reset_from_fen_parts(&args[1..]) {
-                    eprintln!("{}", err);
-                    return;
-                }
+                self.pos.reset_from_fen_parts(&args[1..])?;
                 5usize
             }
-            _ => return,
+            unknown => return Err(Error::unknown("token", unknown)),
         };
 
         if args.len() <= next {
-            return;
+            return Ok(());
         } else if args[next] != "moves" {
-            eprintln!("Unknown token '{}'", args[next]);
-            return;
+            return Err(Error::unknown("token", args[next]));
         }
 
         for move_str in &args[next + 1..] {
-            match AtaxxMove::from_str(move_str) {
-                Ok(m) => self.pos.apply_move::<false, true>(m, None),
-                Err(err) => eprintln!(
-                    "Invalid move '{}': {}",
-                    move_str,
-                    match err {
-                        MoveStrError::InvalidFrom => "invalid from-square",
-                        MoveStrError::InvalidTo => "invalid to-square",
-                        MoveStrError::WrongSize => "wrong size",
-                    }
-                ),
-            }
+            let mv = AtaxxMove::from_str(move_str)
+                .map_err(|err| Error::InvalidMove(move_str.to_string(), err))?;
+            self.pos.apply_move::<false, true>(mv, None);
         }
+
+        Ok(())
     }
 
-    fn handle_go(&mut self, args: &[&str]) {
-        let mut limiter: Option<SearchLimiter> = None;
+    fn handle_go(&mut self, args: &[&str]) -> Result<()> {
+        let mut limiter = SearchLimiter::infinite();
         let mut depth = MAX_DEPTH;
 
         let mut tournament_time = false;
+        let mut ponder = false;
 
         let mut red_time = 0u64;
         let mut blue_time = 0u64;
@@ -186,102 +872,102 @@ impl UaiHandler {
 
         let mut moves_to_go = 0u64;
 
+        let mut search_moves = MoveList::new();
+
         let mut i = 0usize;
         while i < args.len() {
             match args[i] {
-                "infinite" => {
-                    if tournament_time || limiter.is_some() {
-                        eprintln!("Multiple non-depth search limits not supported");
-                        return;
+                "ponder" => ponder = true,
+                "searchmoves" => {
+                    let mut legal_moves = MoveList::new();
+                    fill_move_list(&mut legal_moves, &self.pos);
+
+                    // moves continue until the next token that is not a move
+                    while let Some(Ok(mv)) = args.get(i + 1).map(|s| AtaxxMove::from_str(s)) {
+                        i += 1;
+
+                        if !legal_moves.contains(&mv) {
+                            return Err(Error::IllegalMove(mv));
+                        }
+
+                        if !search_moves.contains(&mv) {
+                            search_moves.push(mv);
+                        }
                     }
 
-                    limiter = Some(SearchLimiter::infinite());
+                    if search_moves.is_empty() {
+                        return Err(Error::missing("searchmoves"));
+                    }
                 }
+                // no limits at all is already infinite
+                "infinite" => {}
                 "depth" => {
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("Missing depth");
-                        return;
+                        return Err(Error::missing("depth"));
                     }
 
                     depth = if let Ok(depth) = args[i].parse::<i32>() {
                         depth
                     } else {
-                        eprintln!("Invalid depth '{}'", args[i]);
-                        return;
+                        return Err(Error::invalid("depth", args[i]));
                     }
                 }
                 "nodes" => {
-                    if tournament_time || limiter.is_some() {
-                        eprintln!("Multiple non-depth search limits not supported");
-                        return;
-                    }
-
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("Missing node count");
-                        return;
+                        return Err(Error::missing("node count"));
                     }
 
                     if let Ok(node_limit) = args[i].parse::<usize>() {
-                        limiter = Some(SearchLimiter::fixed_nodes(node_limit));
+                        limiter = limiter.with_nodes(node_limit);
                     } else {
-                        eprintln!("Invalid node limit '{}'", args[i]);
-                        return;
+                        return Err(Error::invalid("node limit", args[i]));
                     }
                 }
                 "movetime" => {
-                    if tournament_time || limiter.is_some() {
-                        eprintln!("Multiple non-depth search limits not supported");
-                        return;
-                    }
-
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("Missing move time");
-                        return;
+                        return Err(Error::missing("move time"));
                     }
 
                     if let Ok(time_limit) = args[i].parse::<u64>() {
-                        limiter = Some(SearchLimiter::move_time(time_limit));
+                        limiter = if self.nodes_time > 0 {
+                            limiter.with_nodes((time_limit * self.nodes_time) as usize)
+                        } else {
+                            limiter.with_move_time(time_limit)
+                        };
                     } else {
-                        eprintln!("Invalid move time '{}'", args[i]);
-                        return;
+                        return Err(Error::invalid("move time", args[i]));
                     }
                 }
-                "wtime" | "btime" | "winc" | "binc" | "movestogo" => {
-                    if limiter.is_some() {
-                        eprintln!("Multiple non-depth search limits not supported");
-                        return;
-                    }
-
+                "wtime" | "btime" | "winc" | "binc" | "p1time" | "p2time" | "p1inc" | "p2inc"
+                | "movestogo" => {
                     tournament_time = true;
 
                     let token = args[i];
 
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("Missing {}", token);
-                        return;
+                        return Err(Error::missing(token));
                     }
 
                     let Ok(value) = args[i].parse::<u64>() else {
-                        eprintln!("Invalid {} '{}'", token, args[i]);
-                        return;
+                        return Err(Error::invalid(token, args[i]));
                     };
 
                     match token {
-                        "wtime" => blue_time = value,
-                        "btime" => red_time = value,
-                        "winc" => blue_inc = value,
-                        "binc" => red_inc = value,
+                        // ugi's player 1 is x, the side to move first
+                        "wtime" | "p2time" => blue_time = value,
+                        "btime" | "p1time" => red_time = value,
+                        "winc" | "p2inc" => blue_inc = value,
+                        "binc" | "p1inc" => red_inc = value,
                         "movestogo" => moves_to_go = value,
                         _ => unreachable!(),
                     }
                 }
                 unknown => {
-                    eprintln!("Unknown search limit '{}'", unknown);
-                    return;
+                    return Err(Error::unknown("search limit", unknown));
                 }
             }
 
@@ -289,72 +975,451 @@ impl UaiHandler {
         }
 
         if tournament_time {
-            assert!(limiter.is_none());
-
             let (our_time, our_inc) = match self.pos.side_to_move() {
                 Color::RED => (red_time, red_inc),
                 Color::BLUE => (blue_time, blue_inc),
                 _ => unreachable!(),
             };
 
-            limiter = Some(SearchLimiter::tournament(our_time, our_inc, moves_to_go));
-        } else if limiter.is_none() {
-            limiter = Some(SearchLimiter::infinite());
+            let params = *self.searcher().params();
+
+            let time_manager =
+                TimeManager::new(our_time, our_inc, moves_to_go, self.move_overhead, &params);
+
+            limiter = limiter.with_time_manager(if self.nodes_time > 0 {
+                time_manager.with_nodes_time(self.nodes_time)
+            } else {
+                time_manager
+            });
+        }
+
+        self.wait_for_search();
+        self.signals.reset(ponder);
+
+        let searcher = Arc::clone(&self.searcher);
+        let pos = self.pos.clone();
+
+        self.watchdog_thread = limiter.time_limit_ms().map(|limit| {
+            spawn_watchdog(
+                Arc::clone(&self.signals),
+                pos.clone(),
+                watchdog_deadline(limit),
+            )
+        });
+
+        self.search_thread = Some(std::thread::spawn(move || {
+            searcher
+                .lock()
+                .unwrap()
+                .start_search(pos, limiter, depth, &search_moves);
+        }));
+
+        Ok(())
+    }
+
+    fn handle_stop(&mut self) {
+        self.signals.stop();
+        self.wait_for_search();
+    }
+
+    fn handle_ponderhit(&self) {
+        self.signals.ponderhit();
+    }
+
+    fn handle_query(&self, args: &[&str]) -> Result<()> {
+        if args.is_empty() {
+            return Err(Error::missing("query"));
         }
 
-        self.searcher
-            .start_search(self.pos.clone(), limiter.unwrap(), depth);
+        match args[0] {
+            "gameover" => uai_println!("response {}", self.pos.game_over()),
+            "p1turn" => uai_println!("response {}", self.pos.side_to_move() == Color::RED),
+            "result" => {
+                let result = if self.pos.game_over() {
+                    match self.pos.result() {
+                        GameResult::Win(Color::RED) => "p1win",
+                        GameResult::Win(_) => "p2win",
+                        GameResult::Draw => "draw",
+                    }
+                } else {
+                    "none"
+                };
+                uai_println!("response {}", result);
+            }
+            unknown => return Err(Error::unknown("query", unknown)),
+        }
+
+        Ok(())
+    }
+
+    // setgap <square> <true|false>
+    fn handle_setgap(&mut self, args: &[&str]) -> Result<()> {
+        if args.len() < 2 {
+            return Err(Error::missing("square or gap value"));
+        }
+
+        let Ok(sq) = Square::from_str(args[0]) else {
+            return Err(Error::invalid("square", args[0]));
+        };
+
+        let Ok(gap) = args[1].parse::<bool>() else {
+            return Err(Error::invalid("gap value", args[1]));
+        };
+
+        self.pos.set_gap(sq, gap, None);
+
+        Ok(())
     }
 
     fn handle_d(&self) {
-        println!("{}", self.pos);
-        println!();
-        println!("Fen: {}", self.pos.to_fen());
-        println!("Key: {:16x}", self.pos.key());
-        println!("Static eval: {}", static_eval_once(&self.pos));
+        uai_println!("{}", self.pos);
+        uai_println!();
+        uai_println!("Fen: {}", self.pos.to_fen());
+        uai_println!("Key: {:16x}", self.pos.key());
+        uai_println!("Static eval: {}", static_eval_once(&self.pos));
     }
 
-    fn handle_perft(&mut self, args: &[&str]) {
+    fn handle_perft(&mut self, args: &[&str]) -> Result<()> {
         if args.is_empty() {
-            eprintln!("Missing depth");
-            return;
+            return Err(Error::missing("depth"));
         }
 
         if let Ok(depth) = args[0].parse::<i32>() {
-            perft(&mut self.pos, depth);
+            uai_println!("{}", perft(&mut self.pos, depth));
         } else {
-            eprintln!("Invalid depth");
+            return Err(Error::invalid("depth", args[0]));
         }
+
+        Ok(())
     }
 
-    fn handle_splitperft(&mut self, args: &[&str]) {
+    // eval is the static eval after the move, from the mover's point of view
+    fn handle_moves(&mut self) {
+        let mut moves = MoveList::new();
+        fill_move_list(&mut moves, &self.pos);
+
+        self.wait_for_search();
+
+        let mut pos = self.pos.clone();
+        let searcher = self.searcher();
+
+        let mut scored = moves
+            .iter()
+            .map(|&mv| {
+                let flips = pos.flip_count(mv);
+                let history = searcher.history_score(&pos, mv);
+
+                pos.apply_move::<true, true>(mv, None);
+                let eval = -static_eval_once(&pos);
+                pos.pop_move::<true>(None);
+
+                (mv, flips, eval, history)
+            })
+            .collect::<Vec<_>>();
+
+        scored.sort_by_key(|&(_, _, eval, _)| std::cmp::Reverse(eval));
+
+        uai_println!("move  flips   eval history");
+        for (mv, flips, eval, history) in scored {
+            uai_println!(
+                "{:<5} {:>5} {:>6} {:>7}",
+                mv.to_string(),
+                flips,
+                eval,
+                history
+            );
+        }
+        uai_println!("{} legal moves", moves.len());
+    }
+
+    fn handle_splitperft(&mut self, args: &[&str]) -> Result<()> {
         if args.is_empty() {
-            eprintln!("Missing depth");
-            return;
+            return Err(Error::missing("depth"));
         }
 
-        if let Ok(depth) = args[0].parse::<i32>() {
-            split_perft(&mut self.pos, depth);
-        } else {
-            eprintln!("Invalid depth");
+        let Ok(depth) = args[0].parse::<i32>() else {
+            return Err(Error::invalid("depth", args[0]));
+        };
+
+        let start = Instant::now();
+
+        let counts = split_perft(&mut self.pos, depth);
+        for (mv, count) in &counts {
+            uai_println!("{}\t{}", mv, count);
         }
+
+        let total = counts.iter().map(|&(_, count)| count).sum::<u64>();
+
+        let time = start.elapsed().as_secs_f64();
+        let nps = (total as f64 / time) as u64;
+
+        uai_println!();
+        uai_println!("total {}", total);
+        uai_println!("{} nps", nps);
+
+        Ok(())
+    }
+
+    fn handle_replaytrace(&self, args: &[&str]) -> Result<()> {
+        if args.is_empty() {
+            return Err(Error::missing("trace file"));
+        }
+
+        replay_trace(&args.join(" "))
+    }
+
+    // selfplay [depth <depth> | nodes <nodes>] [random]
+    fn handle_selfplay(&mut self, args: &[&str]) -> Result<()> {
+        let mut limit = SelfplayLimit::default();
+        let mut random_opening = false;
+
+        let mut idx = 0usize;
+        while idx < args.len() {
+            match args[idx] {
+                "random" => random_opening = true,
+                "depth" | "nodes" => {
+                    let Some(&value) = args.get(idx + 1) else {
+                        return Err(Error::missing(args[idx]));
+                    };
+
+                    limit = if args[idx] == "depth" {
+                        let Ok(depth) = value.parse::<i32>() else {
+                            return Err(Error::invalid("depth", value));
+                        };
+                        SelfplayLimit::Depth(depth)
+                    } else {
+                        let Ok(nodes) = value.parse::<usize>() else {
+                            return Err(Error::invalid("node count", value));
+                        };
+                        SelfplayLimit::Nodes(nodes)
+                    };
+
+                    idx += 1;
+                }
+                unknown => return Err(Error::unknown("selfplay argument", unknown)),
+            }
+
+            idx += 1;
+        }
+
+        let pos = self.pos.clone();
+        run_selfplay(&mut self.searcher(), &pos, limit, random_opening)
+    }
+
+    // fuzz fens [count]
+    fn handle_fuzz(args: &[&str]) -> Result<()> {
+        match args.first() {
+            Some(&"fens") => {
+                let count = match args.get(1) {
+                    Some(count) => count
+                        .parse::<usize>()
+                        .map_err(|_| Error::invalid("position count", count))?,
+                    None => DEFAULT_FUZZ_COUNT,
+                };
+
+                run_fuzz_fens(count);
+                Ok(())
+            }
+            Some(unknown) => Err(Error::unknown("fuzz target", unknown)),
+            None => Err(Error::missing("fuzz target")),
+        }
+    }
+
+    // explain [depth]
+    fn handle_explain(&mut self, args: &[&str]) -> Result<()> {
+        let depth = match args.first() {
+            Some(depth) => depth
+                .parse::<i32>()
+                .map_err(|_| Error::invalid("depth", depth))?,
+            None => DEFAULT_EXPLAIN_DEPTH,
+        };
+
+        self.wait_for_search();
+
+        let pos = self.pos.clone();
+        run_explain(&mut self.searcher(), &pos, depth);
+
+        Ok(())
     }
 
-    fn handle_bench(&mut self, args: &[&str]) {
+    // genfens <count> seed <seed> book <path|None>
+    fn handle_genfens(args: &[&str]) -> Result<()> {
+        let Some(count) = args.first() else {
+            return Err(Error::missing("fen count"));
+        };
+
+        let Ok(count) = count.parse::<usize>() else {
+            return Err(Error::invalid("fen count", count));
+        };
+
+        let mut seed = 0u64;
+        let mut book = None;
+
+        let mut idx = 1usize;
+        while idx < args.len() {
+            let Some(&value) = args.get(idx + 1) else {
+                return Err(Error::missing(&format!("{} value", args[idx])));
+            };
+
+            match args[idx] {
+                "seed" => {
+                    let Ok(value) = value.parse::<u64>() else {
+                        return Err(Error::invalid("seed", value));
+                    };
+                    seed = value;
+                }
+                "book" => {
+                    book = if value.eq_ignore_ascii_case("none") {
+                        None
+                    } else {
+                        Some(value)
+                    };
+                }
+                unknown => return Err(Error::unknown("genfens argument", unknown)),
+            }
+
+            idx += 2;
+        }
+
+        run_genfens(count, seed, book)
+    }
+
+    fn handle_evalcheck(args: &[&str]) -> Result<()> {
+        if args.is_empty() {
+            return Err(Error::missing("dataset file"));
+        }
+
+        run_evalcheck(&args.join(" "))
+    }
+
+    fn handle_bench(&mut self, args: &[&str]) -> Result<()> {
+        if args.first() == Some(&"smp") {
+            return self.handle_bench_smp(&args[1..]);
+        }
+
         let depth = if args.is_empty() {
             DEFAULT_BENCH_DEPTH
         } else if let Ok(depth) = args[0].parse::<i32>() {
             depth
         } else {
-            eprintln!("Invalid depth");
-            return;
+            return Err(Error::invalid("depth", args[0]));
         };
 
-        run_bench(&mut self.searcher, depth);
+        run_bench(&mut self.searcher(), depth)
+    }
+
+    fn handle_bench_smp(&mut self, args: &[&str]) -> Result<()> {
+        let depth = if args.is_empty() {
+            DEFAULT_BENCH_DEPTH
+        } else if let Ok(depth) = args[0].parse::<i32>() {
+            depth
+        } else {
+            return Err(Error::invalid("depth", args[0]));
+        };
+
+        let max_threads = if args.len() < 2 {
+            DEFAULT_SMP_BENCH_MAX_THREADS
+        } else if let Ok(max_threads) = args[1].parse::<usize>() {
+            max_threads
+        } else {
+            return Err(Error::invalid("thread count", args[1]));
+        };
+
+        run_bench_smp(&mut self.searcher(), depth, max_threads)
     }
 }
 
-pub fn run() {
+pub fn run(commands: &[String]) {
     let mut handler = UaiHandler::new();
-    handler.run();
+    handler.run(commands);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::uai::UaiHandler;
+    use crate::watchdog::spawn_watchdog;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn go_after_watchdog_timeout() {
+        let mut handler = UaiHandler::new();
+        handler
+            .handle_setoption(&["name", "MultiPV", "value", "2"])
+            .unwrap();
+
+        let (locked_tx, locked_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+
+        // stands in for a search that never returns, holding on to the searcher
+        let searcher = Arc::clone(&handler.searcher);
+        let stuck = std::thread::spawn(move || {
+            let _searcher = searcher.lock().unwrap();
+            locked_tx.send(()).unwrap();
+            let _ = release_rx.recv();
+        });
+        locked_rx.recv().unwrap();
+
+        let stuck_signals = Arc::clone(&handler.signals);
+        stuck_signals.reset(false);
+        handler.watchdog_thread = Some(spawn_watchdog(
+            Arc::clone(&stuck_signals),
+            handler.pos.clone(),
+            Duration::from_millis(10),
+        ));
+
+        // reads the search params before starting, which needs the searcher
+        handler
+            .handle_go(&["p1time", "100", "p2time", "100"])
+            .unwrap();
+        handler.wait_for_search();
+
+        assert!(stuck_signals.bestmove_sent());
+        assert!(handler.signals.bestmove_sent());
+        assert!(!Arc::ptr_eq(&stuck_signals, &handler.signals));
+        assert_eq!(handler.searcher().multi_pv(), 2);
+
+        release_tx.send(()).unwrap();
+        stuck.join().unwrap();
+    }
+
+    #[test]
+    fn option_values_recorded() {
+        let mut handler = UaiHandler::new();
+        let value = |handler: &UaiHandler, name: &str| {
+            handler
+                .option_values
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+
+        assert!(value(&handler, "Pretty").is_some());
+
+        handler
+            .handle_setoption(&["name", "Ponder", "value", "true"])
+            .unwrap();
+        assert_eq!(value(&handler, "Ponder").as_deref(), Some("true"));
+
+        assert!(handler
+            .handle_setoption(&["name", "Ponder", "value", "maybe"])
+            .is_err());
+        assert_eq!(value(&handler, "Ponder").as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn reloadnet_during_infinite_search() {
+        let mut handler = UaiHandler::new();
+        handler.handle_go(&["infinite"]).unwrap();
+
+        assert!(handler.handle_line("reloadnet"));
+        assert!(handler.search_thread.is_none());
+        assert!(handler.signals.bestmove_sent());
+
+        handler.handle_go(&["infinite"]).unwrap();
+        assert!(handler.handle_line("setoption name EvalFile value <embedded>"));
+        assert!(handler.search_thread.is_none());
+        assert!(handler.signals.bestmove_sent());
+    }
 }
@@ -19,3 +19,4 @@
 pub mod misc;
 pub mod rng;
 pub mod simd;
+pub mod squares;
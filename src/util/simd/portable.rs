@@ -0,0 +1,108 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// plain arrays standing in for registers, in safe code the compiler can vectorise
+// for whatever it targets. arithmetic wraps and mul_add_adj_i16 sums adjacent
+// pairs, exactly as the x86 instructions do, so every backend evaluates the same
+
+pub const CHUNK_SIZE_I16: usize = 16;
+
+pub type Register16 = [i16; CHUNK_SIZE_I16];
+pub type Register32 = [i32; CHUNK_SIZE_I16 / 2];
+
+pub const BACKEND: &str = "portable";
+
+#[inline(always)]
+fn map16(a: Register16, b: Register16, f: impl Fn(i16, i16) -> i16) -> Register16 {
+    std::array::from_fn(|i| f(a[i], b[i]))
+}
+
+#[inline(always)]
+pub fn zero16() -> Register16 {
+    [0; CHUNK_SIZE_I16]
+}
+
+#[inline(always)]
+pub fn set1_i16(v: i16) -> Register16 {
+    [v; CHUNK_SIZE_I16]
+}
+
+// the first CHUNK_SIZE_I16 values of a slice
+#[inline(always)]
+pub fn load16(values: &[i16]) -> Register16 {
+    values[..CHUNK_SIZE_I16].try_into().unwrap()
+}
+
+#[inline(always)]
+pub fn store16(values: &mut [i16], v: Register16) {
+    values[..CHUNK_SIZE_I16].copy_from_slice(&v);
+}
+
+#[inline(always)]
+pub fn min_i16(a: Register16, b: Register16) -> Register16 {
+    map16(a, b, i16::min)
+}
+
+#[inline(always)]
+pub fn max_i16(a: Register16, b: Register16) -> Register16 {
+    map16(a, b, i16::max)
+}
+
+#[inline(always)]
+pub fn clamp_i16(v: Register16, min: Register16, max: Register16) -> Register16 {
+    min_i16(max_i16(v, min), max)
+}
+
+#[inline(always)]
+pub fn add_i16(a: Register16, b: Register16) -> Register16 {
+    map16(a, b, i16::wrapping_add)
+}
+
+#[inline(always)]
+pub fn sub_i16(a: Register16, b: Register16) -> Register16 {
+    map16(a, b, i16::wrapping_sub)
+}
+
+#[inline(always)]
+pub fn mul_i16(a: Register16, b: Register16) -> Register16 {
+    map16(a, b, i16::wrapping_mul)
+}
+
+#[inline(always)]
+pub fn mul_add_adj_i16(a: Register16, b: Register16) -> Register32 {
+    std::array::from_fn(|i| {
+        let low = i32::from(a[2 * i]) * i32::from(b[2 * i]);
+        let high = i32::from(a[2 * i + 1]) * i32::from(b[2 * i + 1]);
+        low.wrapping_add(high)
+    })
+}
+
+#[inline(always)]
+pub fn zero32() -> Register32 {
+    [0; CHUNK_SIZE_I16 / 2]
+}
+
+#[inline(always)]
+pub fn add_i32(a: Register32, b: Register32) -> Register32 {
+    std::array::from_fn(|i| a[i].wrapping_add(b[i]))
+}
+
+#[inline(always)]
+pub fn horizontal_sum_i32(v: Register32) -> i32 {
+    v.into_iter().fold(0, i32::wrapping_add)
+}
@@ -0,0 +1,96 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// conversions between the 8x8 layout used by bitboards and a dense
+// 7x7 layout with no unused bits, as used by dataset formats
+
+use crate::bitboard::Bitboard;
+
+#[allow(clippy::unreadable_literal)]
+#[must_use]
+pub fn pack_dense(board: Bitboard) -> u64 {
+    #[cfg(target_feature = "bmi2")]
+    {
+        use std::arch::x86_64::*;
+        unsafe { _pext_u64(board.raw(), Bitboard::ALL.raw()) }
+    }
+
+    #[cfg(not(target_feature = "bmi2"))]
+    {
+        let bb = board.raw();
+        bb & 0x7f
+            | (bb & 0x7f00) >> 1
+            | (bb & 0x7f0000) >> 2
+            | (bb & 0x7f000000) >> 3
+            | (bb & 0x7f00000000) >> 4
+            | (bb & 0x7f0000000000) >> 5
+            | (bb & 0x7f000000000000) >> 6
+    }
+}
+
+#[allow(clippy::unreadable_literal)]
+#[must_use]
+pub fn unpack_dense(dense: u64) -> Bitboard {
+    #[cfg(target_feature = "bmi2")]
+    {
+        use std::arch::x86_64::*;
+        Bitboard::from_raw(unsafe { _pdep_u64(dense, Bitboard::ALL.raw()) })
+    }
+
+    #[cfg(not(target_feature = "bmi2"))]
+    {
+        Bitboard::from_raw(
+            dense & 0x7f
+                | (dense & (0x7f << 7)) << 1
+                | (dense & (0x7f << 14)) << 2
+                | (dense & (0x7f << 21)) << 3
+                | (dense & (0x7f << 28)) << 4
+                | (dense & (0x7f << 35)) << 5
+                | (dense & (0x7f << 42)) << 6,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitboard::Bitboard;
+    use crate::core::Square;
+    use crate::util::squares::{pack_dense, unpack_dense};
+
+    #[test]
+    fn dense_square_indices() {
+        assert_eq!(pack_dense(Square::A1.bit()), 1 << Square::A1.idx());
+        assert_eq!(pack_dense(Square::D4.bit()), 1 << Square::D4.idx());
+        assert_eq!(pack_dense(Square::G7.bit()), 1 << Square::G7.idx());
+        assert_eq!(pack_dense(Bitboard::ALL), (1 << Square::N_SQUARES) - 1);
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let boards = [
+            Bitboard::EMPTY,
+            Bitboard::ALL,
+            Bitboard::FILE_C | Bitboard::RANK_5,
+            Bitboard::from_raw(0x0055_2a55_2a55_2a55) & Bitboard::ALL,
+        ];
+
+        for board in boards {
+            assert_eq!(unpack_dense(pack_dense(board)), board);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::ataxx_move::AtaxxMove;
+use crate::iolog::uai_println;
+use crate::limit::SearchSignals;
+use crate::movegen::{fill_move_list, is_legal, MoveList};
+use crate::position::Position;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// a search is only considered stuck once it is far past its limit,
+// so ordinary overruns from slow stop checks never trigger it
+const WATCHDOG_FACTOR: f64 = 4.0;
+const WATCHDOG_MIN_SLACK_MS: f64 = 1000.0;
+
+const WATCHDOG_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// how long a stopped search gets to send its own bestmove
+const WATCHDOG_GRACE: Duration = Duration::from_millis(100);
+
+#[must_use]
+pub fn watchdog_deadline(time_limit_ms: f64) -> Duration {
+    let ms = (time_limit_ms * WATCHDOG_FACTOR).max(time_limit_ms + WATCHDOG_MIN_SLACK_MS);
+    Duration::from_secs_f64(ms / 1000.0)
+}
+
+// a stuck thread cannot be killed, but the gui can still be given a legal move
+// in time. the clock only runs while not pondering, as the search itself does.
+// returns whether the watchdog had to answer in the search's place
+pub fn spawn_watchdog(
+    signals: Arc<SearchSignals>,
+    pos: Position,
+    deadline: Duration,
+) -> JoinHandle<bool> {
+    std::thread::spawn(move || {
+        let mut start = Instant::now();
+
+        while start.elapsed() < deadline {
+            if signals.bestmove_sent() {
+                return false;
+            }
+
+            if signals.pondering() {
+                start = Instant::now();
+            }
+
+            std::thread::sleep(WATCHDOG_POLL_INTERVAL);
+        }
+
+        signals.stop();
+        std::thread::sleep(WATCHDOG_GRACE);
+
+        if !signals.claim_bestmove() {
+            return false;
+        }
+
+        let best_move = fallback_move(&pos, signals.best_move());
+
+        uai_println!(
+            "info string watchdog: search still running after {} ms, deadline {} ms",
+            start.elapsed().as_millis(),
+            deadline.as_millis()
+        );
+        uai_println!("info string watchdog: position {}", pos.to_fen());
+        uai_println!(
+            "info string watchdog: last best move {}, sending {}",
+            signals.best_move(),
+            best_move
+        );
+        uai_println!("bestmove {}", best_move);
+
+        true
+    })
+}
+
+// the last completed iteration's move if there is one, otherwise anything legal
+fn fallback_move(pos: &Position, best_move: AtaxxMove) -> AtaxxMove {
+    if is_legal(pos, best_move) {
+        return best_move;
+    }
+
+    let mut moves = MoveList::new();
+    fill_move_list(&mut moves, pos);
+
+    moves.first().copied().unwrap_or(AtaxxMove::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ataxx_move::AtaxxMove;
+    use crate::core::Square;
+    use crate::limit::SearchSignals;
+    use crate::position::Position;
+    use crate::watchdog::{fallback_move, spawn_watchdog, watchdog_deadline};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn deadline_has_slack() {
+        assert_eq!(watchdog_deadline(0.0), Duration::from_secs(1));
+        assert_eq!(watchdog_deadline(10_000.0), Duration::from_secs(40));
+    }
+
+    #[test]
+    fn fallback_is_legal() {
+        let pos = Position::startpos();
+
+        let best = AtaxxMove::Single(Square::B6);
+        assert_eq!(fallback_move(&pos, best), best);
+
+        let fallback = fallback_move(&pos, AtaxxMove::Single(Square::D4));
+        assert_ne!(fallback, AtaxxMove::None);
+        assert_ne!(fallback, AtaxxMove::Single(Square::D4));
+    }
+
+    #[test]
+    fn answers_for_stuck_search() {
+        let signals = Arc::new(SearchSignals::default());
+        signals.reset(false);
+
+        let answered = spawn_watchdog(
+            Arc::clone(&signals),
+            Position::startpos(),
+            Duration::from_millis(10),
+        )
+        .join()
+        .unwrap();
+
+        assert!(answered);
+        assert!(signals.stop_requested());
+        assert!(!signals.claim_bestmove());
+    }
+
+    #[test]
+    fn quiet_when_search_answered() {
+        let signals = Arc::new(SearchSignals::default());
+        signals.reset(false);
+
+        let handle = spawn_watchdog(
+            Arc::clone(&signals),
+            Position::startpos(),
+            Duration::from_secs(30),
+        );
+
+        assert!(signals.claim_bestmove());
+        assert!(!handle.join().unwrap());
+
+        assert!(!signals.stop_requested());
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Sanctaphraxx, a UAI Ataxx engine
+ * Copyright (C) 2024 Ciekce
+ *
+ * Sanctaphraxx is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * Sanctaphraxx is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with Sanctaphraxx. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::core::{Score, SCORE_WIN};
+use crate::datagen::{is_bulletformat_file, BulletFormatReader};
+use crate::error::{Error, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// fitted with wdlfit on 146k positions from 1500 datagen games with the embedded network
+const DEFAULT_WIN_MIDPOINT: f64 = 796.0;
+const DEFAULT_WIN_SCALE: f64 = 170.0;
+
+const FIT_ROUNDS: usize = 12;
+const FIT_GRID_STEPS: i32 = 10;
+
+// the chance of a win at a given score is a logistic curve centred on the midpoint,
+// and the chance of a loss is the same curve mirrored. draws make up the rest
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WdlModel {
+    pub midpoint: f64,
+    pub scale: f64,
+}
+
+impl Default for WdlModel {
+    fn default() -> Self {
+        Self {
+            midpoint: DEFAULT_WIN_MIDPOINT,
+            scale: DEFAULT_WIN_SCALE,
+        }
+    }
+}
+
+impl WdlModel {
+    #[must_use]
+    fn probabilities(self, score: f64) -> (f64, f64, f64) {
+        let win = 1.0 / (1.0 + ((self.midpoint - score) / self.scale).exp());
+        let loss = 1.0 / (1.0 + ((self.midpoint + score) / self.scale).exp());
+
+        (win, (1.0 - win - loss).max(0.0), loss)
+    }
+
+    // in permille, always summing to 1000
+    #[must_use]
+    pub fn wdl(self, score: Score) -> (u32, u32, u32) {
+        if score > SCORE_WIN {
+            return (1000, 0, 0);
+        } else if score < -SCORE_WIN {
+            return (0, 0, 1000);
+        }
+
+        let (win, _, loss) = self.probabilities(f64::from(score));
+
+        let win = (win * 1000.0).round() as u32;
+        let loss = ((loss * 1000.0).round() as u32).min(1000 - win);
+
+        (win, 1000 - win - loss, loss)
+    }
+
+    // mean negative log likelihood of the results, which are 0.0, 0.5 or 1.0
+    #[must_use]
+    fn loss(self, samples: &[(f64, f64)]) -> f64 {
+        const EPSILON: f64 = 1e-9;
+
+        let total: f64 = samples
+            .iter()
+            .map(|&(score, result)| {
+                let (win, draw, loss) = self.probabilities(score);
+                let p = if result > 0.75 {
+                    win
+                } else if result < 0.25 {
+                    loss
+                } else {
+                    draw
+                };
+                -(p.max(EPSILON)).ln()
+            })
+            .sum();
+
+        total / samples.len() as f64
+    }
+
+    // searches a grid around the current best fit, narrowing it every round
+    #[must_use]
+    pub fn fit(samples: &[(f64, f64)]) -> Self {
+        let max_score = samples
+            .iter()
+            .map(|&(score, _)| score.abs())
+            .fold(1.0, f64::max);
+
+        let mut best = Self {
+            midpoint: max_score / 2.0,
+            scale: max_score / 4.0,
+        };
+        let mut best_loss = best.loss(samples);
+
+        let mut midpoint_range = max_score / 2.0;
+        let mut scale_range = max_score / 4.0;
+
+        for _ in 0..FIT_ROUNDS {
+            let center = best;
+
+            for i in -FIT_GRID_STEPS..=FIT_GRID_STEPS {
+                for j in -FIT_GRID_STEPS..=FIT_GRID_STEPS {
+                    let candidate = Self {
+                        midpoint: center.midpoint
+                            + midpoint_range * f64::from(i) / f64::from(FIT_GRID_STEPS),
+                        scale: center.scale
+                            + scale_range * f64::from(j) / f64::from(FIT_GRID_STEPS),
+                    };
+
+                    if candidate.scale <= 0.0 {
+                        continue;
+                    }
+
+                    let loss = candidate.loss(samples);
+                    if loss < best_loss {
+                        best = candidate;
+                        best_loss = loss;
+                    }
+                }
+            }
+
+            midpoint_range /= 2.0;
+            scale_range /= 2.0;
+        }
+
+        best
+    }
+}
+
+// reads datagen fen output, where the score and result are both from red's point of view.
+// scores of won or lost positions are left out, as the search already knows those
+pub fn run_wdl_fit(path: &str) -> Result<()> {
+    let mut samples = Vec::new();
+
+    if is_bulletformat_file(path) {
+        for point in BulletFormatReader::open(path)? {
+            let point = point?;
+            if point.red_score.abs() <= SCORE_WIN {
+                samples.push((f64::from(point.red_score), point.red_result()));
+            }
+        }
+    } else {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+
+            let fields = line.split('|').map(str::trim).collect::<Vec<_>>();
+            let [_, score, result] = fields.as_slice() else {
+                continue;
+            };
+
+            let (Ok(score), Ok(result)) = (score.parse::<Score>(), result.parse::<f64>()) else {
+                continue;
+            };
+
+            if score.abs() <= SCORE_WIN {
+                samples.push((f64::from(score), result));
+            }
+        }
+    }
+
+    if samples.is_empty() {
+        return Err(Error::missing("scored positions"));
+    }
+
+    let model = WdlModel::fit(&samples);
+
+    println!("positions: {}", samples.len());
+    println!("midpoint: {:.1}", model.midpoint);
+    println!("scale: {:.1}", model.scale);
+    println!("loss: {:.6}", model.loss(&samples));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WdlModel;
+    use crate::core::SCORE_MATE;
+    use crate::util::rng::Jsf64Rng;
+
+    #[test]
+    fn wdl_sums_to_1000() {
+        let model = WdlModel::default();
+
+        for score in [-5000, -300, 0, 25, 800, 20000] {
+            let (w, d, l) = model.wdl(score);
+            assert_eq!(w + d + l, 1000);
+        }
+
+        assert_eq!(model.wdl(SCORE_MATE - 1), (1000, 0, 0));
+    }
+
+    #[test]
+    fn fit_recovers_model() {
+        let truth = WdlModel {
+            midpoint: 600.0,
+            scale: 150.0,
+        };
+
+        let mut rng = Jsf64Rng::new(7);
+        let samples = (0..4000)
+            .map(|_| {
+                let score = f64::from(rng.next_u32_bounded(3000)) - 1500.0;
+                let (win, draw, _) = truth.probabilities(score);
+
+                let roll = f64::from(rng.next_u32()) / f64::from(u32::MAX);
+                let result = if roll < win {
+                    1.0
+                } else if roll < win + draw {
+                    0.5
+                } else {
+                    0.0
+                };
+
+                (score, result)
+            })
+            .collect::<Vec<_>>();
+
+        let fitted = WdlModel::fit(&samples);
+
+        assert!((fitted.midpoint - truth.midpoint).abs() < 50.0);
+        assert!((fitted.scale - truth.scale).abs() < 30.0);
+    }
+}